@@ -1,13 +1,14 @@
 //! Content-addressable blocks
 
-use crate::{Hash, Location};
+use crate::outboard::GROUP_LEN;
+use crate::{Hash, HashAlgorithm, Location, Outboard};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// A content-addressable block of data
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Block {
-    /// Content hash (SHA-256) - used as unique identifier
+    /// Content hash - used as unique identifier
     pub hash: Hash,
 
     /// Raw data (may be encrypted/compressed)
@@ -22,12 +23,33 @@ pub struct Block {
 
     /// Storage locations where this block is stored
     pub locations: Vec<Location>,
+
+    /// Outboard tree for verified streaming and ranged reads (large BLAKE3 blocks)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outboard: Option<Outboard>,
 }
 
 impl Block {
     /// Create a new block from data
     pub fn new(data: Vec<u8>, metadata: BlockMetadata) -> Self {
-        let hash = Hash::from_data(&data);
+        Self::with_algorithm(data, metadata, HashAlgorithm::default())
+    }
+
+    /// Create a new block hashed with a specific algorithm
+    ///
+    /// BLAKE3 blocks larger than one verification group also get an
+    /// [`Outboard`], whose root doubles as the block hash.
+    pub fn with_algorithm(
+        data: Vec<u8>,
+        metadata: BlockMetadata,
+        algorithm: HashAlgorithm,
+    ) -> Self {
+        let outboard = (algorithm == HashAlgorithm::Blake3 && data.len() > GROUP_LEN)
+            .then(|| Outboard::new(&data));
+        let hash = match outboard.as_ref().and_then(Outboard::root) {
+            Some(root) => root,
+            None => Hash::from_data_with(algorithm, &data),
+        };
         let size = data.len();
 
         Self {
@@ -36,6 +58,7 @@ impl Block {
             size,
             metadata,
             locations: Vec::new(),
+            outboard,
         }
     }
 
@@ -46,9 +69,9 @@ impl Block {
         }
     }
 
-    /// Verify data integrity
+    /// Verify data integrity (using the algorithm the block was hashed with)
     pub fn verify(&self) -> bool {
-        Hash::from_data(&self.data) == self.hash
+        Hash::from_data_with(self.hash.algorithm(), &self.data) == self.hash
     }
 }
 
@@ -110,4 +133,27 @@ mod tests {
         block.data[0] ^= 1;
         assert!(!block.verify());
     }
+
+    #[test]
+    fn test_block_blake3() {
+        let data = b"test data".to_vec();
+        let mut block =
+            Block::with_algorithm(data, BlockMetadata::default(), HashAlgorithm::Blake3);
+
+        assert_eq!(block.hash.algorithm(), HashAlgorithm::Blake3);
+        assert!(block.verify());
+
+        block.data[0] ^= 1;
+        assert!(!block.verify());
+    }
+
+    #[test]
+    fn test_large_blake3_block_outboard() {
+        let data = vec![7u8; 3 * GROUP_LEN];
+        let block = Block::with_algorithm(data, BlockMetadata::default(), HashAlgorithm::Blake3);
+
+        let outboard = block.outboard.as_ref().unwrap();
+        assert!(block.verify());
+        assert!(outboard.verify_root(&block.hash).is_ok());
+    }
 }
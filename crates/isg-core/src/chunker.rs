@@ -0,0 +1,425 @@
+//! Content-defined chunking
+//!
+//! Splits a byte stream into [`Block`]s at content-dependent boundaries using a
+//! FastCDC-style Gear rolling hash. Because cut points depend only on the bytes
+//! around them, inserting or removing data near the start of a file only
+//! changes the blocks touching the edit, and the rest still deduplicate.
+
+use crate::{Block, BlockMetadata, Error, File, FileMetadata, Hash, HashAlgorithm, Result};
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Bytes requested from the reader at a time
+const READ_SIZE: usize = 64 * 1024;
+
+/// Gear table: one pseudo-random 64-bit value per byte value
+const GEAR: [u64; 256] = gear_table();
+
+/// Generate the Gear table at compile time (splitmix64 with a fixed seed)
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x4953_4743_4443_0001; // "ISGCDC" + version
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Build a mask with `bits` ones in the most significant positions.
+///
+/// The Gear hash shifts left on every byte, so the high bits depend on the
+/// widest window of input and give the best-distributed cut points.
+fn high_mask(bits: u32) -> u64 {
+    if bits == 0 {
+        0
+    } else {
+        !0u64 << (64 - bits.min(64))
+    }
+}
+
+/// Chunk size limits for content-defined chunking
+///
+/// Only built through [`ChunkerConfig::new`] or `Default`, so the sizes are
+/// always valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkerConfig {
+    /// Minimum block size in bytes (no cut point is searched before this)
+    min_size: usize,
+
+    /// Target average block size in bytes (rounded to a power of two)
+    avg_size: usize,
+
+    /// Maximum block size in bytes (a cut is forced here)
+    max_size: usize,
+
+    /// Algorithm used to hash emitted blocks
+    hash_algorithm: HashAlgorithm,
+}
+
+impl ChunkerConfig {
+    /// Create a validated configuration
+    pub fn new(min_size: usize, avg_size: usize, max_size: usize) -> Result<Self> {
+        if min_size == 0 {
+            return Err(Error::Config(
+                "Minimum chunk size must be non-zero".to_string(),
+            ));
+        }
+
+        if !(min_size <= avg_size && avg_size <= max_size) {
+            return Err(Error::Config(format!(
+                "Chunk sizes must satisfy min <= avg <= max (got {}/{}/{})",
+                min_size, avg_size, max_size
+            )));
+        }
+
+        Ok(Self {
+            min_size,
+            avg_size,
+            max_size,
+            hash_algorithm: HashAlgorithm::default(),
+        })
+    }
+
+    /// Hash emitted blocks with a specific algorithm
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Minimum block size in bytes
+    pub fn min_size(&self) -> usize {
+        self.min_size
+    }
+
+    /// Target average block size in bytes
+    pub fn avg_size(&self) -> usize {
+        self.avg_size
+    }
+
+    /// Maximum block size in bytes
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Algorithm used to hash emitted blocks
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    /// Number of mask bits corresponding to the average size
+    fn avg_bits(&self) -> u32 {
+        // Round to the nearest power of two
+        let bits = usize::BITS - 1 - self.avg_size.leading_zeros();
+        let lower = 1usize << bits;
+        if self.avg_size - lower > lower / 2 {
+            bits + 1
+        } else {
+            bits
+        }
+    }
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        // 256 KiB / 1 MiB / 4 MiB
+        Self {
+            min_size: 256 * 1024,
+            avg_size: 1024 * 1024,
+            max_size: 4 * 1024 * 1024,
+            hash_algorithm: HashAlgorithm::default(),
+        }
+    }
+}
+
+/// Streaming content-defined chunker over any `AsyncRead`
+///
+/// Call [`Chunker::next_block`] until it returns `None`, then
+/// [`Chunker::into_file`] to get the `File` built from the emitted blocks.
+pub struct Chunker<R> {
+    reader: R,
+    config: ChunkerConfig,
+
+    /// Mask used before the average size is reached (harder to match)
+    mask_small: u64,
+
+    /// Mask used after the average size is reached (easier to match)
+    mask_large: u64,
+
+    /// Bytes read but not yet emitted as a block
+    buffer: Vec<u8>,
+
+    /// Read buffer, reused across reads
+    scratch: Vec<u8>,
+
+    /// Whether the reader has been exhausted
+    eof: bool,
+
+    /// Hashes of the blocks emitted so far, in order
+    block_hashes: Vec<Hash>,
+
+    /// Sizes of the blocks emitted so far, in order
+    block_sizes: Vec<u64>,
+
+    /// Total bytes emitted so far
+    total_size: u64,
+}
+
+impl<R: AsyncRead + Unpin> Chunker<R> {
+    /// Create a chunker reading from `reader`
+    pub fn new(reader: R, config: ChunkerConfig) -> Self {
+        // Normalized chunking (FastCDC level 2): two extra mask bits before
+        // the average size, two fewer after, to tighten the size distribution
+        let bits = config.avg_bits();
+
+        Self {
+            reader,
+            config,
+            mask_small: high_mask(bits + 2),
+            mask_large: high_mask(bits.saturating_sub(2)),
+            buffer: Vec::with_capacity(config.max_size),
+            scratch: vec![0u8; READ_SIZE],
+            eof: false,
+            block_hashes: Vec::new(),
+            block_sizes: Vec::new(),
+            total_size: 0,
+        }
+    }
+
+    /// Read the next block, or `None` once the input is exhausted
+    pub async fn next_block(&mut self) -> Result<Option<Block>> {
+        self.fill_buffer().await?;
+
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+
+        let cut = self.cut_point(&self.buffer);
+        let rest = self.buffer.split_off(cut);
+        let data = std::mem::replace(&mut self.buffer, rest);
+
+        let block =
+            Block::with_algorithm(data, BlockMetadata::default(), self.config.hash_algorithm);
+        self.block_hashes.push(block.hash);
+        self.block_sizes.push(block.size as u64);
+        self.total_size += block.size as u64;
+
+        Ok(Some(block))
+    }
+
+    /// Consume the chunker and build the `File` for the emitted blocks
+    ///
+    /// `metadata.size` is overwritten with the number of bytes chunked.
+    pub fn into_file(self, path: PathBuf, mut metadata: FileMetadata) -> Result<File> {
+        metadata.size = self.total_size;
+        let file = File::new(
+            path,
+            self.block_hashes,
+            metadata,
+            self.config.hash_algorithm,
+        )?;
+        Ok(file.with_block_sizes(self.block_sizes))
+    }
+
+    /// Read until the buffer holds `max_size` bytes or the reader is done
+    async fn fill_buffer(&mut self) -> Result<()> {
+        while !self.eof && self.buffer.len() < self.config.max_size {
+            let want = (self.config.max_size - self.buffer.len()).min(self.scratch.len());
+            let n = self.reader.read(&mut self.scratch[..want]).await?;
+
+            if n == 0 {
+                self.eof = true;
+            } else {
+                self.buffer.extend_from_slice(&self.scratch[..n]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the end of the first chunk in `data`
+    fn cut_point(&self, data: &[u8]) -> usize {
+        let len = data.len();
+        if len <= self.config.min_size {
+            return len;
+        }
+
+        let end = len.min(self.config.max_size);
+        let normal = self.config.avg_size.min(end);
+        let mut hash = 0u64;
+
+        for (i, &byte) in data
+            .iter()
+            .enumerate()
+            .take(normal)
+            .skip(self.config.min_size)
+        {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            if hash & self.mask_small == 0 {
+                return i + 1;
+            }
+        }
+
+        for (i, &byte) in data.iter().enumerate().take(end).skip(normal) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            if hash & self.mask_large == 0 {
+                return i + 1;
+            }
+        }
+
+        end
+    }
+}
+
+/// Chunk an entire reader into blocks and the resulting `File`
+pub async fn chunk_reader<R: AsyncRead + Unpin>(
+    reader: R,
+    config: ChunkerConfig,
+    path: PathBuf,
+    metadata: FileMetadata,
+) -> Result<(Vec<Block>, File)> {
+    let mut chunker = Chunker::new(reader, config);
+    let mut blocks = Vec::new();
+
+    while let Some(block) = chunker.next_block().await? {
+        blocks.push(block);
+    }
+
+    Ok((blocks, chunker.into_file(path, metadata)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Deterministic pseudo-random test data (xorshift64)
+    fn test_data(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn small_config() -> ChunkerConfig {
+        ChunkerConfig::new(1024, 4096, 16384).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chunk_roundtrip() {
+        let data = test_data(200_000, 1);
+        let (blocks, file) = chunk_reader(
+            data.as_slice(),
+            small_config(),
+            PathBuf::from("/test/file.bin"),
+            FileMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+        let joined: Vec<u8> = blocks.iter().flat_map(|b| b.data.clone()).collect();
+        assert_eq!(joined, data);
+
+        assert_eq!(file.size(), data.len() as u64);
+        assert_eq!(file.block_count(), blocks.len());
+        assert_eq!(file.block_sizes.len(), blocks.len());
+        assert!(file.verify());
+
+        // Every block but the last respects the size bounds
+        for block in &blocks[..blocks.len() - 1] {
+            assert!(block.size >= 1024 && block.size <= 16384);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insertion_preserves_most_blocks() {
+        let data = test_data(500_000, 2);
+        let mut shifted = vec![0xAB];
+        shifted.extend_from_slice(&data);
+
+        let path = PathBuf::from("/test/file.bin");
+        let (original, _) = chunk_reader(
+            data.as_slice(),
+            small_config(),
+            path.clone(),
+            FileMetadata::default(),
+        )
+        .await
+        .unwrap();
+        let (edited, _) = chunk_reader(
+            shifted.as_slice(),
+            small_config(),
+            path,
+            FileMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+        let original_hashes: HashSet<Hash> = original.iter().map(|b| b.hash).collect();
+        let shared = edited
+            .iter()
+            .filter(|b| original_hashes.contains(&b.hash))
+            .count();
+
+        // Only the first block or two should differ
+        assert!(shared + 2 >= original.len());
+    }
+
+    #[tokio::test]
+    async fn test_empty_input() {
+        let (blocks, file) = chunk_reader(
+            &b""[..],
+            small_config(),
+            PathBuf::from("/test/empty"),
+            FileMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(blocks.is_empty());
+        assert_eq!(file.size(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_configured_hash_algorithm() {
+        let data = test_data(50_000, 3);
+        let config = small_config().with_hash_algorithm(HashAlgorithm::Blake3);
+        assert_eq!(config.hash_algorithm(), HashAlgorithm::Blake3);
+        let (blocks, file) = chunk_reader(
+            data.as_slice(),
+            config,
+            PathBuf::from("/test/file.bin"),
+            FileMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(blocks
+            .iter()
+            .all(|b| b.hash.algorithm() == HashAlgorithm::Blake3 && b.verify()));
+        assert_eq!(file.root_hash.algorithm(), HashAlgorithm::Blake3);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(ChunkerConfig::new(0, 10, 20).is_err());
+        assert!(ChunkerConfig::new(10, 5, 20).is_err());
+        assert!(ChunkerConfig::new(10, 20, 15).is_err());
+
+        let config = ChunkerConfig::new(1, 1, 1).unwrap();
+        assert_eq!(
+            (config.min_size(), config.avg_size(), config.max_size()),
+            (1, 1, 1)
+        );
+    }
+}
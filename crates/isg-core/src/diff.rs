@@ -0,0 +1,371 @@
+//! Structural diff between Merkle trees
+//!
+//! Walks two trees from the top down and only descends into subtrees whose
+//! hashes differ, so an identical leading region of a large file is skipped
+//! without comparing every block hash. The blocks after it are aligned by
+//! hash, as a patience diff does: blocks that occur exactly once in both
+//! trees anchor the alignment, so a block inserted or removed mid-file shows
+//! up as such instead of shifting every later block into a change.
+
+use crate::{Error, Hash, MerkleTree, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Kind of change for a run of blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    /// Blocks present in both trees with different content
+    Changed,
+    /// Blocks only present in the new tree
+    Added,
+    /// Blocks only present in the old tree
+    Removed,
+}
+
+/// A contiguous run of changed blocks
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffEntry {
+    /// What happened to these blocks
+    pub kind: ChangeKind,
+
+    /// Block indices in the old tree (empty for `Added`)
+    pub old_blocks: Range<usize>,
+
+    /// Block indices in the new tree (empty for `Removed`)
+    pub new_blocks: Range<usize>,
+
+    /// Byte range in the old file (empty for `Added`)
+    pub old_bytes: Range<u64>,
+
+    /// Byte range in the new file (empty for `Removed`)
+    pub new_bytes: Range<u64>,
+}
+
+/// Diff two trees, using per-block sizes to report byte ranges
+pub fn diff_trees(
+    old: &MerkleTree,
+    old_sizes: &[u64],
+    new: &MerkleTree,
+    new_sizes: &[u64],
+) -> Result<Vec<DiffEntry>> {
+    if old_sizes.len() != old.leaf_count() || new_sizes.len() != new.leaf_count() {
+        return Err(Error::Other(format!(
+            "Block sizes do not match tree leaves ({}/{} old, {}/{} new)",
+            old_sizes.len(),
+            old.leaf_count(),
+            new_sizes.len(),
+            new.leaf_count()
+        )));
+    }
+
+    if old.algorithm() != new.algorithm() {
+        return Err(Error::Other(format!(
+            "Cannot diff Merkle trees of different algorithms ({} vs {})",
+            old.algorithm(),
+            new.algorithm()
+        )));
+    }
+
+    if old.format() != new.format() {
+        return Err(Error::Other(format!(
+            "Cannot diff Merkle trees of different formats ({:?} vs {:?})",
+            old.format(),
+            new.format()
+        )));
+    }
+
+    let (old_count, new_count) = (old.leaf_count(), new.leaf_count());
+    let height = old.levels().len().max(new.levels().len());
+    let prefix = match height {
+        0 => 0,
+        _ => first_difference(old, new, height - 1, 0).unwrap_or(old_count),
+    }
+    .min(old_count.min(new_count));
+
+    let mut hunks = Vec::new();
+    align(
+        &leaf_nodes(old)[prefix..],
+        &leaf_nodes(new)[prefix..],
+        prefix,
+        prefix,
+        &mut hunks,
+    );
+
+    let old_offsets = offsets(old_sizes);
+    let new_offsets = offsets(new_sizes);
+    let entry = |kind, old_blocks: Range<usize>, new_blocks: Range<usize>| DiffEntry {
+        kind,
+        old_bytes: old_offsets[old_blocks.start]..old_offsets[old_blocks.end],
+        new_bytes: new_offsets[new_blocks.start]..new_offsets[new_blocks.end],
+        old_blocks,
+        new_blocks,
+    };
+
+    // Blocks replaced one for one are changes; the rest of a hunk was added
+    // or removed
+    let mut entries = Vec::new();
+    for (old_blocks, new_blocks) in hunks {
+        let paired = old_blocks.len().min(new_blocks.len());
+        let (old_split, new_split) = (old_blocks.start + paired, new_blocks.start + paired);
+        if paired > 0 {
+            entries.push(entry(
+                ChangeKind::Changed,
+                old_blocks.start..old_split,
+                new_blocks.start..new_split,
+            ));
+        }
+        if old_split < old_blocks.end {
+            entries.push(entry(
+                ChangeKind::Removed,
+                old_split..old_blocks.end,
+                new_split..new_split,
+            ));
+        }
+        if new_split < new_blocks.end {
+            entries.push(entry(
+                ChangeKind::Added,
+                old_split..old_split,
+                new_split..new_blocks.end,
+            ));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// First leaf index under node `index` at `level` where the trees differ
+fn first_difference(
+    old: &MerkleTree,
+    new: &MerkleTree,
+    level: usize,
+    index: usize,
+) -> Option<usize> {
+    let start = index << level;
+    let end = (index + 1) << level;
+    if start >= old.leaf_count().max(new.leaf_count()) {
+        return None;
+    }
+
+    let old_node = old.levels().get(level).and_then(|l| l.get(index));
+    let new_node = new.levels().get(level).and_then(|l| l.get(index));
+
+    // Equal hashes only prove equal subtrees when they cover the same number
+    // of leaves (V1 pairs an odd trailing node with itself)
+    let old_width = end.min(old.leaf_count()).saturating_sub(start);
+    let new_width = end.min(new.leaf_count()).saturating_sub(start);
+    if old_node.is_some() && old_node == new_node && old_width == new_width {
+        return None;
+    }
+
+    if level == 0 {
+        Some(index)
+    } else {
+        first_difference(old, new, level - 1, index * 2)
+            .or_else(|| first_difference(old, new, level - 1, index * 2 + 1))
+    }
+}
+
+/// Collect the unequal runs of `old` and `new` (leaf nodes starting at block
+/// `old_start` and `new_start`) as pairs of block ranges
+fn align(
+    old: &[Hash],
+    new: &[Hash],
+    old_start: usize,
+    new_start: usize,
+    out: &mut Vec<(Range<usize>, Range<usize>)>,
+) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let (old_start, new_start) = (old_start + prefix, new_start + prefix);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    if old.is_empty() && new.is_empty() {
+        return;
+    }
+
+    let anchors = anchors(old, new);
+    if anchors.is_empty() {
+        out.push((
+            old_start..old_start + old.len(),
+            new_start..new_start + new.len(),
+        ));
+        return;
+    }
+
+    // Recurse into the gaps between anchors, and after the last one
+    let (mut old_from, mut new_from) = (0, 0);
+    for (old_at, new_at) in anchors.into_iter().chain([(old.len(), new.len())]) {
+        align(
+            &old[old_from..old_at],
+            &new[new_from..new_at],
+            old_start + old_from,
+            new_start + new_from,
+            out,
+        );
+        (old_from, new_from) = (old_at + 1, new_at + 1);
+    }
+}
+
+/// Positions of hashes that occur exactly once in each of `old` and `new`,
+/// the longest run of them in the same order in both
+fn anchors(old: &[Hash], new: &[Hash]) -> Vec<(usize, usize)> {
+    // Occurrences and last position in old, then in new
+    let mut seen: HashMap<&Hash, (usize, usize, usize, usize)> = HashMap::new();
+    for (i, hash) in old.iter().enumerate() {
+        let entry = seen.entry(hash).or_default();
+        entry.0 += 1;
+        entry.1 = i;
+    }
+    for (i, hash) in new.iter().enumerate() {
+        if let Some(entry) = seen.get_mut(hash) {
+            entry.2 += 1;
+            entry.3 = i;
+        }
+    }
+    let mut unique: Vec<(usize, usize)> = seen
+        .into_values()
+        .filter(|&(old_count, _, new_count, _)| old_count == 1 && new_count == 1)
+        .map(|(_, old_at, _, new_at)| (old_at, new_at))
+        .collect();
+    unique.sort_unstable();
+
+    // Longest increasing run of new positions (patience sorting): `tails[k]`
+    // ends the best run of length k + 1, `previous` links each pair back
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; unique.len()];
+    for (i, &(_, new_at)) in unique.iter().enumerate() {
+        let k = tails.partition_point(|&t| unique[t].1 < new_at);
+        previous[i] = k.checked_sub(1).map(|k| tails[k]);
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut run = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        run.push(unique[i]);
+        next = previous[i];
+    }
+    run.reverse();
+    run
+}
+
+/// Leaf level of `tree`
+fn leaf_nodes(tree: &MerkleTree) -> &[Hash] {
+    tree.levels().first().map_or(&[], Vec::as_slice)
+}
+
+/// Byte offset of every block boundary (one more entry than blocks)
+fn offsets(sizes: &[u64]) -> Vec<u64> {
+    let mut offsets = Vec::with_capacity(sizes.len() + 1);
+    let mut total = 0;
+    offsets.push(0);
+    for size in sizes {
+        total += size;
+        offsets.push(total);
+    }
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Hash, HashAlgorithm};
+
+    fn leaves(names: &[&str]) -> Vec<Hash> {
+        names
+            .iter()
+            .map(|n| Hash::from_data(n.as_bytes()))
+            .collect()
+    }
+
+    fn diff(old: &[&str], new: &[&str]) -> Vec<DiffEntry> {
+        let old_tree = MerkleTree::new(HashAlgorithm::Sha256, &leaves(old)).unwrap();
+        let new_tree = MerkleTree::new(HashAlgorithm::Sha256, &leaves(new)).unwrap();
+        diff_trees(
+            &old_tree,
+            &vec![100; old.len()],
+            &new_tree,
+            &vec![100; new.len()],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_identical_trees() {
+        assert!(diff(&["a", "b", "c"], &["a", "b", "c"]).is_empty());
+    }
+
+    #[test]
+    fn test_changed_range() {
+        let entries = diff(&["a", "b", "c", "d", "e"], &["a", "x", "y", "d", "e"]);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, ChangeKind::Changed);
+        assert_eq!(entries[0].old_blocks, 1..3);
+        assert_eq!(entries[0].new_bytes, 100..300);
+    }
+
+    #[test]
+    fn test_added_and_removed() {
+        let added = diff(&["a", "b", "c"], &["a", "b", "c", "c"]);
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].kind, ChangeKind::Added);
+        assert_eq!(added[0].new_blocks, 3..4);
+        assert_eq!(added[0].old_bytes, 300..300);
+
+        let removed = diff(&["a", "b", "c", "d"], &["a", "b"]);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].kind, ChangeKind::Removed);
+        assert_eq!(removed[0].old_blocks, 2..4);
+        assert_eq!(removed[0].old_bytes, 200..400);
+    }
+
+    #[test]
+    fn test_insert_and_delete_mid_file() {
+        let old = ["a", "b", "c", "d", "e", "f", "g", "h", "i"];
+        let inserted = diff(&old, &["a", "b", "c", "d", "x", "e", "f", "g", "h", "i"]);
+        assert_eq!(inserted.len(), 1);
+        assert_eq!(inserted[0].kind, ChangeKind::Added);
+        assert_eq!(inserted[0].old_blocks, 4..4);
+        assert_eq!(inserted[0].new_blocks, 4..5);
+        assert_eq!(inserted[0].new_bytes, 400..500);
+
+        let deleted = diff(&old, &["a", "c", "d", "e", "f", "g", "h", "i"]);
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].kind, ChangeKind::Removed);
+        assert_eq!(deleted[0].old_blocks, 1..2);
+        assert_eq!(deleted[0].new_blocks, 1..1);
+
+        // A change and an insert elsewhere, around repeated blocks
+        let entries = diff(
+            &["a", "z", "b", "z", "c", "d"],
+            &["a", "z", "y", "z", "c", "x", "d"],
+        );
+        let kinds: Vec<_> = entries
+            .iter()
+            .map(|e| (e.kind, e.old_blocks.clone(), e.new_blocks.clone()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ChangeKind::Changed, 2..3, 2..3),
+                (ChangeKind::Added, 5..5, 5..6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sizes_must_match() {
+        let tree = MerkleTree::new(HashAlgorithm::Sha256, &leaves(&["a", "b"])).unwrap();
+        assert!(diff_trees(&tree, &[1], &tree, &[1, 2]).is_err());
+    }
+}
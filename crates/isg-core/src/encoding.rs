@@ -3,6 +3,13 @@
 use crate::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Byte source for streaming encode/decode
+pub type ByteReader<'a> = dyn AsyncRead + Unpin + Send + 'a;
+
+/// Byte sink for streaming encode/decode
+pub type ByteWriter<'a> = dyn AsyncWrite + Unpin + Send + 'a;
 
 /// Encoded data representation
 #[derive(Clone, Debug)]
@@ -17,6 +24,26 @@ pub struct EncodedData {
     pub metadata: EncodingMetadata,
 }
 
+impl EncodedData {
+    /// Wrap encoded bytes whose format and metadata are not known
+    ///
+    /// Decoders only rely on the bytes, so this is enough to decode a blob
+    /// read back from storage.
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self {
+            metadata: EncodingMetadata {
+                original_size: 0,
+                encoded_size: data.len(),
+                compression_ratio: 0.0,
+                strategy: "unknown".to_string(),
+                parameters: serde_json::Value::Null,
+            },
+            format: "unknown".to_string(),
+            data,
+        }
+    }
+}
+
 /// Metadata about encoded data
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EncodingMetadata {
@@ -48,6 +75,9 @@ pub enum EncodingStrategy {
         fps: u32,
         /// Resolution
         resolution: (u32, u32),
+        /// Gray levels per block (2 = black/white, 4 = 2 bits, 8 = 3 bits)
+        #[serde(default = "black_and_white")]
+        levels: u8,
     },
 
     /// RGB color-based encoding
@@ -88,6 +118,26 @@ pub enum EncodingStrategy {
     },
 }
 
+/// Gray levels assumed for pixel strategies stored before levels existed
+fn black_and_white() -> u8 {
+    2
+}
+
+impl EncodingStrategy {
+    /// Name of the strategy variant (the serialized `type` tag)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PixelEncoding { .. } => "PixelEncoding",
+            Self::ColorEncoding { .. } => "ColorEncoding",
+            Self::QREncoding { .. } => "QREncoding",
+            Self::Steganography { .. } => "Steganography",
+            Self::RawCompressed { .. } => "RawCompressed",
+            Self::DNAEncoding => "DNAEncoding",
+            Self::Hybrid { .. } => "Hybrid",
+        }
+    }
+}
+
 /// Color space options
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ColorSpace {
@@ -139,6 +189,44 @@ pub trait Encoder: Send + Sync {
         // Default: assume no compression
         input_size
     }
+
+    /// Encode everything read from `input`, writing the encoded bytes to `output`
+    ///
+    /// The output is the same format `decode` accepts. The default buffers the
+    /// whole input; encoders that can work incrementally override this to run
+    /// in bounded memory.
+    async fn encode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<EncodingMetadata> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data).await?;
+
+        let encoded = self.encode(&data).await?;
+        output.write_all(&encoded.data).await?;
+        output.flush().await?;
+        Ok(encoded.metadata)
+    }
+
+    /// Decode an encoded stream from `input`, writing the original bytes to `output`
+    ///
+    /// Returns the number of bytes written. Incremental decoders may write data
+    /// before detecting corruption at the end of the stream. The default
+    /// buffers the whole input.
+    async fn decode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<u64> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data).await?;
+
+        let decoded = self.decode(&EncodedData::from_bytes(data)).await?;
+        output.write_all(&decoded).await?;
+        output.flush().await?;
+        Ok(decoded.len() as u64)
+    }
 }
 
 #[cfg(test)]
@@ -151,18 +239,35 @@ mod tests {
             block_size: 4,
             fps: 30,
             resolution: (1920, 1080),
+            levels: 4,
         };
 
         let json = serde_json::to_string(&strategy).unwrap();
         let deserialized: EncodingStrategy = serde_json::from_str(&json).unwrap();
 
         match deserialized {
-            EncodingStrategy::PixelEncoding { block_size, fps, resolution } => {
+            EncodingStrategy::PixelEncoding {
+                block_size,
+                fps,
+                resolution,
+                levels,
+            } => {
                 assert_eq!(block_size, 4);
                 assert_eq!(fps, 30);
                 assert_eq!(resolution, (1920, 1080));
+                assert_eq!(levels, 4);
             }
             _ => panic!("Wrong variant"),
         }
+
+        let tagged = serde_json::to_value(&strategy).unwrap();
+        assert_eq!(tagged["type"], strategy.kind());
+
+        // Strategies stored before gray levels were added are black/white
+        let legacy = r#"{"type":"PixelEncoding","block_size":2,"fps":24,"resolution":[640,480]}"#;
+        match serde_json::from_str(legacy).unwrap() {
+            EncodingStrategy::PixelEncoding { levels, .. } => assert_eq!(levels, 2),
+            _ => panic!("Wrong variant"),
+        }
     }
 }
@@ -1,6 +1,7 @@
 //! File representation with Merkle tree structure
 
-use crate::Hash;
+use crate::diff::diff_trees;
+use crate::{DiffEntry, Error, Hash, HashAlgorithm, MerkleFormat, MerkleProof, MerkleTree, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -14,69 +15,104 @@ pub struct File {
     /// Root hash of Merkle tree
     pub root_hash: Hash,
 
+    /// Merkle tree format the root hash was computed with
+    #[serde(default = "MerkleFormat::legacy")]
+    pub merkle_format: MerkleFormat,
+
     /// Block hashes (leaf nodes of Merkle tree)
     pub block_hashes: Vec<Hash>,
 
+    /// Size in bytes of each block (empty if not recorded)
+    #[serde(default)]
+    pub block_sizes: Vec<u64>,
+
     /// File metadata
     pub metadata: FileMetadata,
 }
 
 impl File {
-    /// Create a new file
-    pub fn new(path: PathBuf, block_hashes: Vec<Hash>, metadata: FileMetadata) -> Self {
-        let root_hash = Self::compute_merkle_root(&block_hashes);
-
-        Self {
+    /// Create a new file from blocks hashed with `algorithm`
+    ///
+    /// Fails if a block hash uses another algorithm.
+    pub fn new(
+        path: PathBuf,
+        block_hashes: Vec<Hash>,
+        metadata: FileMetadata,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self> {
+        let root_hash = Self::compute_merkle_root(algorithm, &block_hashes)?;
+
+        Ok(Self {
             path,
             root_hash,
+            merkle_format: MerkleFormat::CURRENT,
             block_hashes,
+            block_sizes: Vec::new(),
             metadata,
-        }
+        })
     }
 
-    /// Compute Merkle root from block hashes
-    pub fn compute_merkle_root(hashes: &[Hash]) -> Hash {
-        if hashes.is_empty() {
-            return Hash::from_data(b"");
-        }
+    /// Record the size of each block (needed for byte-range diffs)
+    pub fn with_block_sizes(mut self, block_sizes: Vec<u64>) -> Self {
+        self.block_sizes = block_sizes;
+        self
+    }
 
-        if hashes.len() == 1 {
-            return hashes[0];
-        }
+    /// Compute Merkle root from `algorithm` block hashes (current format)
+    pub fn compute_merkle_root(algorithm: HashAlgorithm, hashes: &[Hash]) -> Result<Hash> {
+        Ok(MerkleTree::new(algorithm, hashes)?.root())
+    }
 
-        // Build Merkle tree bottom-up
-        let mut current_level: Vec<Hash> = hashes.to_vec();
-
-        while current_level.len() > 1 {
-            let mut next_level = Vec::new();
-
-            for chunk in current_level.chunks(2) {
-                let combined = if chunk.len() == 2 {
-                    // Combine two hashes
-                    let mut data = Vec::new();
-                    data.extend_from_slice(chunk[0].as_bytes());
-                    data.extend_from_slice(chunk[1].as_bytes());
-                    Hash::from_data(&data)
-                } else {
-                    // Odd one out, hash it with itself
-                    let mut data = Vec::new();
-                    data.extend_from_slice(chunk[0].as_bytes());
-                    data.extend_from_slice(chunk[0].as_bytes());
-                    Hash::from_data(&data)
-                };
-
-                next_level.push(combined);
-            }
-
-            current_level = next_level;
-        }
+    /// Algorithm of the file's hashes, as recorded in its root
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.root_hash.algorithm()
+    }
 
-        current_level[0]
+    /// Build the full Merkle tree over this file's blocks in its recorded format
+    pub fn merkle_tree(&self) -> Result<MerkleTree> {
+        MerkleTree::with_format(
+            self.hash_algorithm(),
+            &self.block_hashes,
+            self.merkle_format,
+        )
     }
 
-    /// Verify file integrity
+    /// Build an inclusion proof for the block at `index`
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        self.merkle_tree().ok()?.proof(index)
+    }
+
+    /// Diff against a newer version of this file
+    ///
+    /// Both files must have their block sizes recorded.
+    pub fn diff(&self, new: &File) -> Result<Vec<DiffEntry>> {
+        // Trees are rebuilt in one format so mixed-version files still compare
+        diff_trees(
+            &MerkleTree::new(self.hash_algorithm(), &self.block_hashes)?,
+            &self.block_sizes,
+            &MerkleTree::new(new.hash_algorithm(), &new.block_hashes)?,
+            &new.block_sizes,
+        )
+    }
+
+    /// Verify file integrity against the recorded Merkle format
     pub fn verify(&self) -> bool {
-        Self::compute_merkle_root(&self.block_hashes) == self.root_hash
+        self.merkle_tree()
+            .is_ok_and(|tree| tree.root() == self.root_hash)
+    }
+
+    /// Re-root a verified file in the current Merkle format
+    pub fn upgrade_merkle_format(&mut self) -> Result<()> {
+        if !self.verify() {
+            return Err(Error::Corruption(format!(
+                "Merkle root mismatch for {}",
+                self.path.display()
+            )));
+        }
+
+        self.merkle_format = MerkleFormat::CURRENT;
+        self.root_hash = self.merkle_tree()?.root();
+        Ok(())
     }
 
     /// Get total size
@@ -142,8 +178,16 @@ mod tests {
     fn test_merkle_root_single() {
         let hash = Hash::from_data(b"test");
         let hashes = vec![hash];
-        let root = File::compute_merkle_root(&hashes);
+
+        // Legacy roots of one block equal the block hash
+        let root = MerkleTree::with_format(HashAlgorithm::Sha256, &hashes, MerkleFormat::V1)
+            .unwrap()
+            .root();
         assert_eq!(root, hash);
+
+        // The current format is domain-separated
+        let root = File::compute_merkle_root(HashAlgorithm::Sha256, &hashes).unwrap();
+        assert_ne!(root, hash);
     }
 
     #[test]
@@ -152,14 +196,14 @@ mod tests {
         let hash2 = Hash::from_data(b"block2");
         let hashes = vec![hash1, hash2];
 
-        let root = File::compute_merkle_root(&hashes);
+        let root = File::compute_merkle_root(HashAlgorithm::Sha256, &hashes).unwrap();
 
         // Root should be different from individual hashes
         assert_ne!(root, hash1);
         assert_ne!(root, hash2);
 
         // Should be deterministic
-        let root2 = File::compute_merkle_root(&hashes);
+        let root2 = File::compute_merkle_root(HashAlgorithm::Sha256, &hashes).unwrap();
         assert_eq!(root, root2);
     }
 
@@ -173,7 +217,9 @@ mod tests {
             PathBuf::from("/test/file.txt"),
             hashes.clone(),
             FileMetadata::default(),
-        );
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
 
         assert!(file.verify());
 
@@ -182,4 +228,86 @@ mod tests {
         file_corrupted.root_hash = Hash::from_data(b"wrong");
         assert!(!file_corrupted.verify());
     }
+
+    #[test]
+    fn test_file_proof() {
+        let hashes: Vec<Hash> = (0..5)
+            .map(|i| Hash::from_data(format!("block{}", i).as_bytes()))
+            .collect();
+        let file = File::new(
+            PathBuf::from("/test/file.txt"),
+            hashes.clone(),
+            FileMetadata::default(),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+
+        let proof = file.proof(3).unwrap();
+        assert!(proof.verify(&file.root_hash, file.merkle_format, &hashes[3], 3));
+        assert!(file.proof(5).is_none());
+    }
+
+    #[test]
+    fn test_file_diff() {
+        let old = File::new(
+            PathBuf::from("/test/file.txt"),
+            vec![Hash::from_data(b"a"), Hash::from_data(b"b")],
+            FileMetadata::default(),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap()
+        .with_block_sizes(vec![4, 4]);
+        let new = File::new(
+            PathBuf::from("/test/file.txt"),
+            vec![Hash::from_data(b"a"), Hash::from_data(b"c")],
+            FileMetadata::default(),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap()
+        .with_block_sizes(vec![4, 6]);
+
+        let entries = old.diff(&new).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].old_bytes, 4..8);
+        assert_eq!(entries[0].new_bytes, 4..10);
+
+        // Sizes are required
+        let bare = File::new(
+            PathBuf::from("/test/file.txt"),
+            vec![Hash::from_data(b"a")],
+            FileMetadata::default(),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        assert!(bare.diff(&new).is_err());
+    }
+
+    #[test]
+    fn test_legacy_file_verification() {
+        let hashes = vec![Hash::from_data(b"block1"), Hash::from_data(b"block2")];
+        let file = File::new(
+            PathBuf::from("/test/file.txt"),
+            hashes.clone(),
+            FileMetadata::default(),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+
+        // Catalog entries written before the format was recorded are V1
+        let mut json = serde_json::to_value(&file).unwrap();
+        let legacy_root = MerkleTree::with_format(HashAlgorithm::Sha256, &hashes, MerkleFormat::V1)
+            .unwrap()
+            .root();
+        json["root_hash"] = serde_json::to_value(legacy_root).unwrap();
+        json.as_object_mut().unwrap().remove("merkle_format");
+
+        let mut legacy: File = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.merkle_format, MerkleFormat::V1);
+        assert!(legacy.verify());
+
+        legacy.upgrade_merkle_format().unwrap();
+        assert_eq!(legacy.merkle_format, MerkleFormat::V2);
+        assert_eq!(legacy.root_hash, file.root_hash);
+        assert!(legacy.verify());
+    }
 }
@@ -1,54 +1,199 @@
-//! Content-addressable hashing using SHA-256
+//! Content-addressable hashing
+//!
+//! Hashes are self-describing: each one carries the algorithm that produced it
+//! (SHA-256 or BLAKE3), and its string form is a multihash (algorithm code,
+//! digest length, digest) in hex. Bare 32-byte SHA-256 digests written before
+//! the algorithm was recorded are still accepted when parsing.
 
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fmt;
+use std::str::FromStr;
 
-/// A SHA-256 hash used for content addressing
+/// Digest length shared by all supported algorithms
+pub const DIGEST_LEN: usize = 32;
+
+/// Supported hash algorithms
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    /// SHA-256 (the original ISG hash)
+    #[default]
+    Sha256,
+    /// BLAKE3 (faster for large media)
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Multihash code for this algorithm
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Sha256 => 0x12,
+            Self::Blake3 => 0x1e,
+        }
+    }
+
+    /// Look up an algorithm by multihash code
+    pub fn from_code(code: u8) -> crate::Result<Self> {
+        match code {
+            0x12 => Ok(Self::Sha256),
+            0x1e => Ok(Self::Blake3),
+            _ => Err(crate::Error::InvalidHash(format!(
+                "Unknown hash algorithm code: {:#04x}",
+                code
+            ))),
+        }
+    }
+
+    /// Short name of the algorithm
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// Hash data with this algorithm
+    pub fn digest(&self, data: &[u8]) -> [u8; DIGEST_LEN] {
+        match self {
+            Self::Sha256 => Sha256::digest(data).into(),
+            Self::Blake3 => *blake3::hash(data).as_bytes(),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" | "sha2-256" => Ok(Self::Sha256),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(crate::Error::InvalidHash(format!(
+                "Unknown hash algorithm: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// A content hash tagged with the algorithm that produced it
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct Hash([u8; 32]);
+#[serde(from = "HashRepr")]
+pub struct Hash {
+    algorithm: HashAlgorithm,
+    digest: [u8; DIGEST_LEN],
+}
+
+/// Accepted serialized forms of a hash
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HashRepr {
+    Tagged {
+        algorithm: HashAlgorithm,
+        digest: [u8; DIGEST_LEN],
+    },
+    /// Bare SHA-256 digest from catalogs written before algorithms were tagged
+    Legacy([u8; DIGEST_LEN]),
+}
+
+impl From<HashRepr> for Hash {
+    fn from(repr: HashRepr) -> Self {
+        match repr {
+            HashRepr::Tagged { algorithm, digest } => Self::from_digest(algorithm, digest),
+            HashRepr::Legacy(digest) => Self::from_bytes(digest),
+        }
+    }
+}
 
 impl Hash {
-    /// Compute hash from data
+    /// Compute hash from data (SHA-256)
     pub fn from_data(data: &[u8]) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let result = hasher.finalize();
-        let mut hash = [0u8; 32];
-        hash.copy_from_slice(&result);
-        Self(hash)
+        Self::from_data_with(HashAlgorithm::Sha256, data)
     }
 
-    /// Create hash from raw bytes
-    pub fn from_bytes(bytes: [u8; 32]) -> Self {
-        Self(bytes)
+    /// Compute hash from data with a specific algorithm
+    pub fn from_data_with(algorithm: HashAlgorithm, data: &[u8]) -> Self {
+        Self {
+            algorithm,
+            digest: algorithm.digest(data),
+        }
     }
 
-    /// Get raw bytes
-    pub fn as_bytes(&self) -> &[u8; 32] {
-        &self.0
+    /// Create hash from raw SHA-256 bytes
+    pub fn from_bytes(bytes: [u8; DIGEST_LEN]) -> Self {
+        Self::from_digest(HashAlgorithm::Sha256, bytes)
     }
 
-    /// Convert to hex string
-    pub fn to_hex(&self) -> String {
-        hex::encode(self.0)
+    /// Create hash from a raw digest produced by `algorithm`
+    pub fn from_digest(algorithm: HashAlgorithm, digest: [u8; DIGEST_LEN]) -> Self {
+        Self { algorithm, digest }
     }
 
-    /// Parse from hex string
-    pub fn from_hex(s: &str) -> crate::Result<Self> {
-        let bytes = hex::decode(s)
-            .map_err(|e| crate::Error::InvalidHash(format!("Invalid hex: {}", e)))?;
+    /// Algorithm that produced this hash
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Get raw digest bytes
+    pub fn as_bytes(&self) -> &[u8; DIGEST_LEN] {
+        &self.digest
+    }
+
+    /// Encode as multihash bytes (code, length, digest)
+    pub fn to_multihash(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(DIGEST_LEN + 2);
+        bytes.push(self.algorithm.code());
+        bytes.push(DIGEST_LEN as u8);
+        bytes.extend_from_slice(&self.digest);
+        bytes
+    }
 
-        if bytes.len() != 32 {
+    /// Decode from multihash bytes
+    pub fn from_multihash(bytes: &[u8]) -> crate::Result<Self> {
+        if bytes.len() != DIGEST_LEN + 2 {
             return Err(crate::Error::InvalidHash(format!(
-                "Expected 32 bytes, got {}",
+                "Expected {} multihash bytes, got {}",
+                DIGEST_LEN + 2,
                 bytes.len()
             )));
         }
 
-        let mut hash = [0u8; 32];
-        hash.copy_from_slice(&bytes);
-        Ok(Self(hash))
+        let algorithm = HashAlgorithm::from_code(bytes[0])?;
+        if bytes[1] as usize != DIGEST_LEN {
+            return Err(crate::Error::InvalidHash(format!(
+                "Unsupported digest length: {}",
+                bytes[1]
+            )));
+        }
+
+        let mut digest = [0u8; DIGEST_LEN];
+        digest.copy_from_slice(&bytes[2..]);
+        Ok(Self::from_digest(algorithm, digest))
+    }
+
+    /// Convert to hex string (multihash form)
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_multihash())
+    }
+
+    /// Parse from hex string (multihash form, or a bare SHA-256 digest)
+    pub fn from_hex(s: &str) -> crate::Result<Self> {
+        let bytes =
+            hex::decode(s).map_err(|e| crate::Error::InvalidHash(format!("Invalid hex: {}", e)))?;
+
+        if bytes.len() == DIGEST_LEN {
+            let mut hash = [0u8; DIGEST_LEN];
+            hash.copy_from_slice(&bytes);
+            return Ok(Self::from_bytes(hash));
+        }
+
+        Self::from_multihash(&bytes)
     }
 }
 
@@ -60,7 +205,20 @@ impl fmt::Display for Hash {
 
 impl fmt::Debug for Hash {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Hash({})", &self.to_hex()[..16])
+        write!(
+            f,
+            "Hash({}:{})",
+            self.algorithm,
+            &hex::encode(self.digest)[..16]
+        )
+    }
+}
+
+impl FromStr for Hash {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::from_hex(s)
     }
 }
 
@@ -84,4 +242,42 @@ mod tests {
         let parsed = Hash::from_hex(&hex).unwrap();
         assert_eq!(hash, parsed);
     }
+
+    #[test]
+    fn test_algorithms_are_distinct() {
+        let sha = Hash::from_data_with(HashAlgorithm::Sha256, b"data");
+        let blake = Hash::from_data_with(HashAlgorithm::Blake3, b"data");
+
+        assert_ne!(sha, blake);
+        assert_eq!(blake.algorithm(), HashAlgorithm::Blake3);
+        assert!(blake.to_hex().starts_with("1e20"));
+        assert_eq!(Hash::from_hex(&blake.to_hex()).unwrap(), blake);
+    }
+
+    #[test]
+    fn test_legacy_formats() {
+        let hash = Hash::from_data(b"legacy");
+
+        // Bare hex digest
+        let parsed = Hash::from_hex(&hex::encode(hash.as_bytes())).unwrap();
+        assert_eq!(parsed, hash);
+
+        // Bare serialized digest array
+        let json = serde_json::to_string(hash.as_bytes()).unwrap();
+        let parsed: Hash = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, hash);
+
+        // Tagged form round-trips
+        let blake = Hash::from_data_with(HashAlgorithm::Blake3, b"legacy");
+        let json = serde_json::to_string(&blake).unwrap();
+        assert_eq!(serde_json::from_str::<Hash>(&json).unwrap(), blake);
+    }
+
+    #[test]
+    fn test_unknown_algorithm() {
+        let mut bytes = Hash::from_data(b"x").to_multihash();
+        bytes[0] = 0x99;
+        assert!(Hash::from_multihash(&bytes).is_err());
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
 }
@@ -4,20 +4,32 @@
 //!
 //! This crate provides the fundamental abstractions used throughout the ISG ecosystem:
 //! - Content-addressable blocks
+//! - Content-defined chunking
 //! - Encoding strategies
 //! - Storage backends
 //! - File and chunk representations
 
 pub mod block;
+pub mod chunker;
+pub mod diff;
 pub mod encoding;
 pub mod error;
 pub mod file;
 pub mod hash;
+pub mod merkle;
+pub mod outboard;
 pub mod storage;
 
 pub use block::{Block, BlockMetadata};
-pub use encoding::{EncodedData, Encoder, EncodingStrategy};
+pub use chunker::{chunk_reader, Chunker, ChunkerConfig};
+pub use diff::{ChangeKind, DiffEntry};
+pub use encoding::{
+    ByteReader, ByteWriter, ColorSpace, CompressionCodec, ECCLevel, EncodedData, Encoder,
+    EncodingMetadata, EncodingStrategy, StegoMethod,
+};
 pub use error::{Error, Result};
 pub use file::{File, FileMetadata};
-pub use hash::Hash;
+pub use hash::{Hash, HashAlgorithm};
+pub use merkle::{MerkleFormat, MerkleProof, MerkleTree};
+pub use outboard::{Outboard, StreamVerifier};
 pub use storage::{Location, StorageBackend, StorageMetadata};
@@ -0,0 +1,450 @@
+//! Merkle tree construction and inclusion proofs
+//!
+//! Two tree formats exist:
+//! - **V1** (legacy): leaves are the block hashes, interior nodes hash the two
+//!   children concatenated, and an odd trailing node is paired with itself.
+//! - **V2**: leaves and interior nodes are hashed with distinct prefixes, an odd
+//!   trailing node is promoted unchanged, and the root is bound to the number
+//!   of leaves. This removes V1's ambiguities (`[a, b, c]` vs `[a, b, c, c]`,
+//!   and a one-block root equal to the block hash).
+//!
+//! Trees are built for an explicit algorithm, that of the repository: leaves
+//! must all have been hashed with it and interior nodes use it too, so a
+//! repository configured for BLAKE3 gets BLAKE3 trees, empty ones included.
+
+use crate::{Error, Hash, HashAlgorithm, Result};
+use serde::{Deserialize, Serialize};
+
+/// Domain separation prefix for V2 leaf nodes
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Domain separation prefix for V2 interior nodes
+const INTERIOR_PREFIX: u8 = 0x01;
+
+/// Domain separation prefix for the V2 root (binds the leaf count)
+const ROOT_PREFIX: u8 = 0x02;
+
+/// Merkle tree format version
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MerkleFormat {
+    /// Legacy format without domain separation
+    V1,
+    /// Domain-separated, length-bound format
+    V2,
+}
+
+impl MerkleFormat {
+    /// Format used for newly created trees
+    pub const CURRENT: Self = Self::V2;
+
+    /// All known formats, newest first
+    pub const ALL: [Self; 2] = [Self::V2, Self::V1];
+
+    /// Format assumed for catalogs written before the version was recorded
+    pub fn legacy() -> Self {
+        Self::V1
+    }
+
+    /// Turn a block hash into a leaf node
+    fn leaf_node(self, algorithm: HashAlgorithm, leaf: &Hash) -> Hash {
+        match self {
+            Self::V1 => *leaf,
+            Self::V2 => prefixed_hash(algorithm, LEAF_PREFIX, &[leaf.as_bytes()]),
+        }
+    }
+
+    /// Hash two children into their parent
+    fn interior_node(self, algorithm: HashAlgorithm, left: &Hash, right: &Hash) -> Hash {
+        match self {
+            Self::V1 => {
+                let mut data = Vec::with_capacity(64);
+                data.extend_from_slice(left.as_bytes());
+                data.extend_from_slice(right.as_bytes());
+                Hash::from_data_with(algorithm, &data)
+            }
+            Self::V2 => prefixed_hash(
+                algorithm,
+                INTERIOR_PREFIX,
+                &[left.as_bytes(), right.as_bytes()],
+            ),
+        }
+    }
+
+    /// Parent of an odd trailing node that has no sibling
+    fn lone_node(self, algorithm: HashAlgorithm, node: &Hash) -> Hash {
+        match self {
+            Self::V1 => self.interior_node(algorithm, node, node),
+            Self::V2 => *node,
+        }
+    }
+
+    /// Turn the top node into the published root
+    fn root(self, algorithm: HashAlgorithm, top: Option<&Hash>, leaf_count: usize) -> Hash {
+        match self {
+            Self::V1 => top
+                .copied()
+                .unwrap_or_else(|| Hash::from_data_with(algorithm, b"")),
+            Self::V2 => {
+                let count = (leaf_count as u64).to_le_bytes();
+                let top = top.map_or(&[][..], |hash| &hash.as_bytes()[..]);
+                prefixed_hash(algorithm, ROOT_PREFIX, &[&count, top])
+            }
+        }
+    }
+}
+
+/// Hash a domain prefix followed by the given parts
+fn prefixed_hash(algorithm: HashAlgorithm, prefix: u8, parts: &[&[u8]]) -> Hash {
+    let mut data = vec![prefix];
+    for part in parts {
+        data.extend_from_slice(part);
+    }
+    Hash::from_data_with(algorithm, &data)
+}
+
+/// A Merkle tree with all levels retained
+#[derive(Clone, Debug)]
+pub struct MerkleTree {
+    /// Tree format
+    format: MerkleFormat,
+
+    /// Algorithm of the leaves and interior nodes
+    algorithm: HashAlgorithm,
+
+    /// Levels from the leaf nodes (index 0) up to the top node (last)
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    /// Build a tree over `algorithm` leaves in the current format
+    pub fn new(algorithm: HashAlgorithm, leaves: &[Hash]) -> Result<Self> {
+        Self::with_format(algorithm, leaves, MerkleFormat::CURRENT)
+    }
+
+    /// Build a tree over `algorithm` leaves in a specific format
+    ///
+    /// Fails if a leaf was hashed with another algorithm.
+    pub fn with_format(
+        algorithm: HashAlgorithm,
+        leaves: &[Hash],
+        format: MerkleFormat,
+    ) -> Result<Self> {
+        if let Some((index, leaf)) = leaves
+            .iter()
+            .enumerate()
+            .find(|(_, leaf)| leaf.algorithm() != algorithm)
+        {
+            return Err(Error::InvalidHash(format!(
+                "Leaf {} is a {} hash in a {} Merkle tree",
+                index,
+                leaf.algorithm(),
+                algorithm
+            )));
+        }
+
+        if leaves.is_empty() {
+            return Ok(Self {
+                format,
+                algorithm,
+                levels: Vec::new(),
+            });
+        }
+
+        let mut levels: Vec<Vec<Hash>> = vec![leaves
+            .iter()
+            .map(|leaf| format.leaf_node(algorithm, leaf))
+            .collect()];
+
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => format.interior_node(algorithm, left, right),
+                    [single] => format.lone_node(algorithm, single),
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Ok(Self {
+            format,
+            algorithm,
+            levels,
+        })
+    }
+
+    /// Find which known format (if any) produces `root` from `leaves`
+    ///
+    /// Useful for catalogs that stored a root hash without its format. The
+    /// leaves must share the root's algorithm.
+    pub fn detect_format(leaves: &[Hash], root: &Hash) -> Option<MerkleFormat> {
+        MerkleFormat::ALL.into_iter().find(|format| {
+            Self::with_format(root.algorithm(), leaves, *format)
+                .is_ok_and(|tree| tree.root() == *root)
+        })
+    }
+
+    /// Tree format
+    pub fn format(&self) -> MerkleFormat {
+        self.format
+    }
+
+    /// Algorithm of the leaves and interior nodes
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Root hash
+    pub fn root(&self) -> Hash {
+        let top = self.levels.last().map(|level| &level[0]);
+        self.format.root(self.algorithm, top, self.leaf_count())
+    }
+
+    /// Number of leaves
+    pub fn leaf_count(&self) -> usize {
+        self.levels.first().map_or(0, Vec::len)
+    }
+
+    /// All levels, leaf nodes first
+    pub fn levels(&self) -> &[Vec<Hash>] {
+        &self.levels
+    }
+
+    /// Build an inclusion proof for the leaf at `index`
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut siblings = Vec::new();
+        let mut idx = index;
+
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = idx ^ 1;
+            // The last odd node has no sibling
+            if sibling < level.len() {
+                siblings.push(level[sibling]);
+            }
+            idx /= 2;
+        }
+
+        Some(MerkleProof {
+            format: self.format,
+            leaf_count: self.leaf_count(),
+            siblings,
+        })
+    }
+}
+
+/// Proof that a leaf is included in a Merkle tree with a given root
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Format of the tree the proof was built from
+    #[serde(default = "MerkleFormat::legacy")]
+    pub format: MerkleFormat,
+
+    /// Number of leaves in the tree the proof was built from
+    pub leaf_count: usize,
+
+    /// Sibling hashes from the leaf level upwards
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Check that `leaf` sits at `index` in the tree with root `root`, built
+    /// in `format`
+    ///
+    /// The format must come from the trusted side along with the root (such as
+    /// `File::merkle_format`): a proof claiming another format is rejected, so
+    /// it can't downgrade a V2 root to the V1 rules.
+    pub fn verify(&self, root: &Hash, format: MerkleFormat, leaf: &Hash, index: usize) -> bool {
+        let algorithm = root.algorithm();
+        if self.format != format
+            || index >= self.leaf_count
+            || leaf.algorithm() != algorithm
+            || self
+                .siblings
+                .iter()
+                .any(|sibling| sibling.algorithm() != algorithm)
+        {
+            return false;
+        }
+
+        let mut hash = format.leaf_node(algorithm, leaf);
+        let mut idx = index;
+        let mut width = self.leaf_count;
+        let mut siblings = self.siblings.iter();
+
+        while width > 1 {
+            hash = if idx % 2 == 1 {
+                match siblings.next() {
+                    Some(sibling) => format.interior_node(algorithm, sibling, &hash),
+                    None => return false,
+                }
+            } else if idx + 1 == width {
+                format.lone_node(algorithm, &hash)
+            } else {
+                match siblings.next() {
+                    Some(sibling) => format.interior_node(algorithm, &hash, sibling),
+                    None => return false,
+                }
+            };
+
+            idx /= 2;
+            width = width.div_ceil(2);
+        }
+
+        siblings.next().is_none() && format.root(algorithm, Some(&hash), self.leaf_count) == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<Hash> {
+        (0..n)
+            .map(|i| Hash::from_data(format!("block{}", i).as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_proof_every_leaf() {
+        for format in MerkleFormat::ALL {
+            for n in 1..=9 {
+                let leaves = leaves(n);
+                let tree = MerkleTree::with_format(HashAlgorithm::Sha256, &leaves, format).unwrap();
+                let root = tree.root();
+
+                for (i, leaf) in leaves.iter().enumerate() {
+                    let proof = tree.proof(i).unwrap();
+                    assert!(
+                        proof.verify(&root, format, leaf, i),
+                        "{:?} n={} i={}",
+                        format,
+                        n,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_rejects_wrong_leaf_or_index() {
+        let leaves = leaves(5);
+        let tree = MerkleTree::new(HashAlgorithm::Sha256, &leaves).unwrap();
+        let root = tree.root();
+        let proof = tree.proof(2).unwrap();
+
+        assert!(!proof.verify(&root, MerkleFormat::V2, &leaves[3], 2));
+        assert!(!proof.verify(&root, MerkleFormat::V2, &leaves[2], 3));
+        assert!(!proof.verify(&Hash::from_data(b"wrong"), MerkleFormat::V2, &leaves[2], 2));
+        assert!(tree.proof(5).is_none());
+    }
+
+    #[test]
+    fn test_proof_rejects_format_downgrade() {
+        let leaves = leaves(4);
+        let tree = MerkleTree::new(HashAlgorithm::Sha256, &leaves).unwrap();
+        let root = tree.root();
+
+        let mut proof = tree.proof(1).unwrap();
+        assert!(!proof.verify(&root, MerkleFormat::V1, &leaves[1], 1));
+        proof.format = MerkleFormat::V1;
+        assert!(!proof.verify(&root, MerkleFormat::V2, &leaves[1], 1));
+
+        // Under V1 rules a one-leaf proof accepts the root itself as a leaf
+        let forged = MerkleProof {
+            format: MerkleFormat::V1,
+            leaf_count: 1,
+            siblings: Vec::new(),
+        };
+        assert!(forged.verify(&root, MerkleFormat::V1, &root, 0));
+        assert!(!forged.verify(&root, MerkleFormat::V2, &root, 0));
+    }
+
+    #[test]
+    fn test_v1_ambiguities() {
+        let a = Hash::from_data(b"a");
+        let b = Hash::from_data(b"b");
+        let c = Hash::from_data(b"c");
+
+        let v1 = |leaves: &[Hash]| {
+            MerkleTree::with_format(HashAlgorithm::Sha256, leaves, MerkleFormat::V1)
+                .unwrap()
+                .root()
+        };
+        assert_eq!(v1(&[a, b, c]), v1(&[a, b, c, c]));
+        assert_eq!(v1(&[a]), a);
+    }
+
+    #[test]
+    fn test_v2_domain_separation() {
+        let a = Hash::from_data(b"a");
+        let b = Hash::from_data(b"b");
+        let c = Hash::from_data(b"c");
+
+        let v2 = |leaves: &[Hash]| {
+            MerkleTree::new(HashAlgorithm::Sha256, leaves)
+                .unwrap()
+                .root()
+        };
+        assert_ne!(v2(&[a, b, c]), v2(&[a, b, c, c]));
+        assert_ne!(v2(&[a]), a);
+        assert_ne!(v2(&[]), v2(&[Hash::from_data(b"")]));
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let leaves = leaves(3);
+        let v1_root = MerkleTree::with_format(HashAlgorithm::Sha256, &leaves, MerkleFormat::V1)
+            .unwrap()
+            .root();
+        let v2_root = MerkleTree::new(HashAlgorithm::Sha256, &leaves)
+            .unwrap()
+            .root();
+
+        assert_eq!(
+            MerkleTree::detect_format(&leaves, &v1_root),
+            Some(MerkleFormat::V1)
+        );
+        assert_eq!(
+            MerkleTree::detect_format(&leaves, &v2_root),
+            Some(MerkleFormat::V2)
+        );
+        assert_eq!(
+            MerkleTree::detect_format(&leaves, &Hash::from_data(b"x")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_blake3_tree() {
+        let leaves: Vec<Hash> = (0..5)
+            .map(|i| Hash::from_data_with(HashAlgorithm::Blake3, &[i]))
+            .collect();
+        let tree = MerkleTree::new(HashAlgorithm::Blake3, &leaves).unwrap();
+        let root = tree.root();
+
+        assert_eq!(root.algorithm(), HashAlgorithm::Blake3);
+        let proof = tree.proof(4).unwrap();
+        assert!(proof.verify(&root, MerkleFormat::V2, &leaves[4], 4));
+        let sha_leaf = Hash::from_digest(HashAlgorithm::Sha256, *leaves[4].as_bytes());
+        assert!(!proof.verify(&root, MerkleFormat::V2, &sha_leaf, 4));
+    }
+
+    #[test]
+    fn test_algorithm_is_explicit() {
+        // Empty trees use the repository algorithm, not the default
+        let empty = MerkleTree::new(HashAlgorithm::Blake3, &[]).unwrap();
+        assert_eq!(empty.root().algorithm(), HashAlgorithm::Blake3);
+
+        // Leaves of another algorithm are rejected
+        let mut mixed = leaves(3);
+        mixed.push(Hash::from_data_with(HashAlgorithm::Blake3, b"x"));
+        assert!(MerkleTree::new(HashAlgorithm::Sha256, &mixed).is_err());
+        assert!(MerkleTree::new(HashAlgorithm::Blake3, &leaves(2)).is_err());
+    }
+}
@@ -0,0 +1,429 @@
+//! Bao-style outboard verification for BLAKE3 blocks
+//!
+//! An [`Outboard`] holds the BLAKE3 chaining value of every 16 KiB group of a
+//! block. Merging those values with the BLAKE3 tree rules reproduces the
+//! block's hash, so once the outboard is checked against a trusted hash each
+//! group can be verified on its own: downloads are validated as bytes arrive
+//! ([`StreamVerifier`]) and a slice of a block only needs the groups that
+//! cover it ([`Outboard::verify_range`]).
+//!
+//! Outboards only exist for BLAKE3, whose tree structure they mirror. Blocks
+//! hashed with SHA-256, the default [`HashAlgorithm`], have none and can only
+//! be verified whole; configure the chunker with
+//! [`ChunkerConfig::with_hash_algorithm`] to get them. Verifying an outboard
+//! against a hash of any other algorithm fails.
+//!
+//! [`ChunkerConfig::with_hash_algorithm`]: crate::ChunkerConfig::with_hash_algorithm
+
+use crate::{Error, Hash, HashAlgorithm, Result};
+use blake3::hazmat::{
+    left_subtree_len, merge_subtrees_non_root, merge_subtrees_root, ChainingValue, HasherExt, Mode,
+};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Bytes per verified group (16 BLAKE3 chunks)
+pub const GROUP_LEN: usize = 16 * blake3::CHUNK_LEN;
+
+/// Outboard verification tree for one block
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outboard {
+    /// Length of the block in bytes
+    pub len: u64,
+
+    /// Chaining value of each group, in order (empty for single-group blocks)
+    pub group_cvs: Vec<ChainingValue>,
+}
+
+impl Outboard {
+    /// Build the outboard for `data`
+    pub fn new(data: &[u8]) -> Self {
+        let group_cvs = if data.len() <= GROUP_LEN {
+            Vec::new()
+        } else {
+            data.chunks(GROUP_LEN)
+                .enumerate()
+                .map(|(i, group)| group_cv(i, group))
+                .collect()
+        };
+
+        Self {
+            len: data.len() as u64,
+            group_cvs,
+        }
+    }
+
+    /// Number of groups in the block
+    pub fn group_count(&self) -> usize {
+        (self.len as usize).div_ceil(GROUP_LEN).max(1)
+    }
+
+    /// Byte range of the group at `index`
+    pub fn group_range(&self, index: usize) -> Range<u64> {
+        let start = (index * GROUP_LEN) as u64;
+        start..(start + GROUP_LEN as u64).min(self.len)
+    }
+
+    /// Expand `range` to group boundaries (the bytes to fetch for a ranged read)
+    pub fn aligned_range(&self, range: Range<u64>) -> Range<u64> {
+        let group = GROUP_LEN as u64;
+        let start = range.start / group * group;
+        let end = range.end.div_ceil(group) * group;
+        start.min(self.len)..end.min(self.len)
+    }
+
+    /// BLAKE3 hash implied by the outboard
+    pub fn root(&self) -> Option<Hash> {
+        if self.group_cvs.len() < 2 {
+            return None;
+        }
+
+        let (left, right) = self.split(0, self.group_cvs.len());
+        let hash = merge_subtrees_root(&left, &right, Mode::Hash);
+        Some(Hash::from_digest(HashAlgorithm::Blake3, *hash.as_bytes()))
+    }
+
+    /// Check that the outboard belongs to the block with hash `root`
+    pub fn verify_root(&self, root: &Hash) -> Result<()> {
+        if root.algorithm() != HashAlgorithm::Blake3 {
+            return Err(Error::InvalidHash(format!(
+                "Outboard verification requires a BLAKE3 hash, got {}",
+                root.algorithm()
+            )));
+        }
+
+        if self.group_cvs.len() != self.expected_cvs() {
+            return Err(Error::Corruption(format!(
+                "Outboard has {} group hashes, expected {}",
+                self.group_cvs.len(),
+                self.expected_cvs()
+            )));
+        }
+
+        // Single-group blocks carry no outboard data and are checked whole
+        match self.root() {
+            Some(computed) if computed != *root => Err(Error::Corruption(
+                "Outboard does not match block hash".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Verify one group of data against the outboard
+    pub fn verify_group(&self, root: &Hash, index: usize, data: &[u8]) -> Result<()> {
+        if index >= self.group_count() {
+            return Err(Error::Corruption(format!(
+                "Group {} out of range for block of {} groups",
+                index,
+                self.group_count()
+            )));
+        }
+
+        let range = self.group_range(index);
+        if data.len() as u64 != range.end - range.start {
+            return Err(Error::Corruption(format!(
+                "Group {} has {} bytes, expected {}",
+                index,
+                data.len(),
+                range.end - range.start
+            )));
+        }
+
+        let valid = if self.group_cvs.is_empty() {
+            Hash::from_data_with(HashAlgorithm::Blake3, data) == *root
+        } else {
+            group_cv(index, data) == self.group_cvs[index]
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::Corruption(format!(
+                "Group {} (bytes {}..{}) failed verification",
+                index, range.start, range.end
+            )))
+        }
+    }
+
+    /// Verify a ranged read and return exactly the requested bytes
+    ///
+    /// `data` must hold the bytes of [`Outboard::aligned_range`] for `range`.
+    pub fn verify_range(&self, root: &Hash, range: Range<u64>, data: &[u8]) -> Result<Vec<u8>> {
+        self.verify_root(root)?;
+        self.verify_range_groups(root, range, data)
+    }
+
+    /// [`Outboard::verify_range`] for an outboard already checked against `root`
+    pub(crate) fn verify_range_groups(
+        &self,
+        root: &Hash,
+        range: Range<u64>,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        if range.start > range.end || range.end > self.len {
+            return Err(Error::Other(format!(
+                "Range {}..{} out of bounds for block of {} bytes",
+                range.start, range.end, self.len
+            )));
+        }
+
+        let aligned = self.aligned_range(range.clone());
+        if data.len() as u64 != aligned.end - aligned.start {
+            return Err(Error::Corruption(format!(
+                "Expected {} bytes for range {}..{}, got {}",
+                aligned.end - aligned.start,
+                aligned.start,
+                aligned.end,
+                data.len()
+            )));
+        }
+
+        let first_group = aligned.start as usize / GROUP_LEN;
+        for (i, group) in data.chunks(GROUP_LEN).enumerate() {
+            self.verify_group(root, first_group + i, group)?;
+        }
+
+        let start = (range.start - aligned.start) as usize;
+        let end = (range.end - aligned.start) as usize;
+        Ok(data[start..end].to_vec())
+    }
+
+    /// Serialize for storage next to the block (length + chaining values)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.group_cvs.len() * 32);
+        bytes.extend_from_slice(&self.len.to_le_bytes());
+        for cv in &self.group_cvs {
+            bytes.extend_from_slice(cv);
+        }
+        bytes
+    }
+
+    /// Parse an outboard written by [`Outboard::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 8 || !(bytes.len() - 8).is_multiple_of(32) {
+            return Err(Error::Decoding(format!(
+                "Invalid outboard length: {}",
+                bytes.len()
+            )));
+        }
+
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&bytes[..8]);
+        let group_cvs = bytes[8..]
+            .chunks(32)
+            .map(|chunk| {
+                let mut cv = [0u8; 32];
+                cv.copy_from_slice(chunk);
+                cv
+            })
+            .collect();
+
+        Ok(Self {
+            len: u64::from_le_bytes(len),
+            group_cvs,
+        })
+    }
+
+    /// Number of chaining values a well-formed outboard carries
+    fn expected_cvs(&self) -> usize {
+        if self.len as usize <= GROUP_LEN {
+            0
+        } else {
+            self.group_count()
+        }
+    }
+
+    /// Split groups `[start, end)` per BLAKE3 rules into left/right subtree values
+    fn split(&self, start: usize, end: usize) -> (ChainingValue, ChainingValue) {
+        let bytes = self.group_range(end - 1).end - self.group_range(start).start;
+        let mid = start + (left_subtree_len(bytes) as usize / GROUP_LEN);
+        (self.subtree(start, mid), self.subtree(mid, end))
+    }
+
+    /// Non-root chaining value of groups `[start, end)`
+    fn subtree(&self, start: usize, end: usize) -> ChainingValue {
+        if end - start == 1 {
+            return self.group_cvs[start];
+        }
+
+        let (left, right) = self.split(start, end);
+        merge_subtrees_non_root(&left, &right, Mode::Hash)
+    }
+}
+
+/// Non-root chaining value of the group at `index`
+fn group_cv(index: usize, data: &[u8]) -> ChainingValue {
+    blake3::Hasher::new()
+        .set_input_offset((index * GROUP_LEN) as u64)
+        .update(data)
+        .finalize_non_root()
+}
+
+/// Incremental verifier for a block download
+///
+/// Feed bytes as they arrive; only bytes belonging to fully verified groups
+/// are released, so corruption is reported at the first bad group.
+pub struct StreamVerifier {
+    outboard: Outboard,
+    root: Hash,
+    next_group: usize,
+    pending: Vec<u8>,
+}
+
+impl StreamVerifier {
+    /// Start verifying a download of the block with hash `root`
+    pub fn new(outboard: Outboard, root: Hash) -> Result<Self> {
+        outboard.verify_root(&root)?;
+
+        Ok(Self {
+            outboard,
+            root,
+            next_group: 0,
+            pending: Vec::with_capacity(GROUP_LEN),
+        })
+    }
+
+    /// Add downloaded bytes, returning the newly verified prefix
+    pub fn update(&mut self, mut bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut verified = Vec::new();
+
+        while !bytes.is_empty() {
+            let range = self.outboard.group_range(self.next_group);
+            let group_len = range.end.saturating_sub(range.start) as usize;
+            if group_len == 0 {
+                return Err(Error::Corruption(format!(
+                    "Received more than the expected {} bytes",
+                    self.outboard.len
+                )));
+            }
+
+            let take = (group_len - self.pending.len()).min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+
+            if self.pending.len() == group_len {
+                self.outboard
+                    .verify_group(&self.root, self.next_group, &self.pending)?;
+                verified.append(&mut self.pending);
+                self.next_group += 1;
+            }
+        }
+
+        Ok(verified)
+    }
+
+    /// Finish the download, failing if it was truncated
+    pub fn finish(self) -> Result<()> {
+        if self.outboard.len == 0 {
+            // The empty block is a single empty group that `update` never sees
+            return self.outboard.verify_group(&self.root, 0, &[]);
+        }
+
+        let expected = self.outboard.group_count();
+
+        if self.next_group == expected && self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Corruption(format!(
+                "Download truncated after {} of {} groups",
+                self.next_group, expected
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_root_matches_blake3() {
+        for len in [
+            GROUP_LEN + 1,
+            2 * GROUP_LEN,
+            3 * GROUP_LEN + 7,
+            9 * GROUP_LEN,
+        ] {
+            let data = test_data(len);
+            let outboard = Outboard::new(&data);
+            let expected = Hash::from_data_with(HashAlgorithm::Blake3, &data);
+            assert_eq!(outboard.root(), Some(expected), "len={}", len);
+        }
+    }
+
+    #[test]
+    fn test_stream_verification() {
+        let data = test_data(5 * GROUP_LEN + 100);
+        let root = Hash::from_data_with(HashAlgorithm::Blake3, &data);
+        let outboard = Outboard::new(&data);
+
+        let mut verifier = StreamVerifier::new(outboard.clone(), root).unwrap();
+        let mut output = Vec::new();
+        for piece in data.chunks(7000) {
+            output.extend(verifier.update(piece).unwrap());
+        }
+        verifier.finish().unwrap();
+        assert_eq!(output, data);
+
+        // Corruption in the third group is caught before the rest arrives
+        let mut corrupted = data.clone();
+        corrupted[2 * GROUP_LEN + 5] ^= 1;
+        let mut verifier = StreamVerifier::new(outboard, root).unwrap();
+        assert!(verifier.update(&corrupted[..2 * GROUP_LEN]).is_ok());
+        assert!(verifier
+            .update(&corrupted[2 * GROUP_LEN..3 * GROUP_LEN])
+            .is_err());
+    }
+
+    #[test]
+    fn test_range_verification() {
+        let data = test_data(4 * GROUP_LEN + 10);
+        let root = Hash::from_data_with(HashAlgorithm::Blake3, &data);
+        let outboard = Outboard::from_bytes(&Outboard::new(&data).to_bytes()).unwrap();
+
+        let range = (GROUP_LEN as u64 + 3)..(3 * GROUP_LEN as u64 + 9);
+        let aligned = outboard.aligned_range(range.clone());
+        let fetched = &data[aligned.start as usize..aligned.end as usize];
+
+        let slice = outboard
+            .verify_range(&root, range.clone(), fetched)
+            .unwrap();
+        assert_eq!(slice, &data[range.start as usize..range.end as usize]);
+
+        let mut bad = fetched.to_vec();
+        bad[0] ^= 1;
+        assert!(outboard.verify_range(&root, range, &bad).is_err());
+
+        // Groups past the end are rejected rather than indexed
+        let Err(Error::Corruption(_)) = outboard.verify_group(&root, 5, &[]) else {
+            panic!("out-of-range group accepted");
+        };
+    }
+
+    #[test]
+    fn test_small_blocks_and_wrong_root() {
+        let data = test_data(100);
+        let root = Hash::from_data_with(HashAlgorithm::Blake3, &data);
+        let outboard = Outboard::new(&data);
+        assert!(outboard.group_cvs.is_empty());
+
+        let mut verifier = StreamVerifier::new(outboard.clone(), root).unwrap();
+        assert_eq!(verifier.update(&data).unwrap(), data);
+        verifier.finish().unwrap();
+
+        let empty = Hash::from_data_with(HashAlgorithm::Blake3, b"");
+        StreamVerifier::new(Outboard::new(b""), empty)
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let large = test_data(3 * GROUP_LEN);
+        let other = Hash::from_data_with(HashAlgorithm::Blake3, b"other");
+        assert!(StreamVerifier::new(Outboard::new(&large), other).is_err());
+        assert!(StreamVerifier::new(outboard, Hash::from_data(&data)).is_err());
+    }
+}
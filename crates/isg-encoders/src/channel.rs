@@ -0,0 +1,489 @@
+//! Lossy channel simulator
+//!
+//! Platforms re-encode what they are given: frames come back recompressed,
+//! rescaled, blurred, noisier and with shifted brightness or color. A
+//! [`Channel`] applies those impairments to the frames of an encoding, in
+//! order, so an encoder configuration can be checked against a platform
+//! without uploading anything.
+//!
+//! [`Channel::measure`] encodes some data, passes it through the channel and
+//! reports the raw bit error rate (before error correction and checksums)
+//! alongside whether the data still decodes. [`Channel::sweep`] does the same
+//! for a series of configurations, e.g. every block size and gray level
+//! count worth trying.
+//!
+//! Impairments are deterministic: noise comes from a seeded generator, so the
+//! same channel damages the same frames the same way every time.
+
+use crate::color::{chroma_420, yuv_of, yuv_to_rgb, ColorEncoder};
+use crate::container;
+use crate::pixel::PixelEncoder;
+use crate::video;
+use async_trait::async_trait;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use isg_core::{EncodedData, Encoder, Error, Result};
+use tracing::debug;
+
+/// One way a platform degrades frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Impairment {
+    /// Recompress as JPEG at `quality` (1-100)
+    Jpeg { quality: u8 },
+
+    /// Recompress as lossy WebP at `quality` (1-100)
+    #[cfg(feature = "webp")]
+    Webp { quality: u8 },
+
+    /// Scale down to `width x height`, then back up to the original size
+    Rescale { width: u32, height: u32 },
+
+    /// Gaussian blur with standard deviation `sigma` pixels
+    Blur { sigma: f32 },
+
+    /// Additive Gaussian noise with standard deviation `sigma` levels, drawn
+    /// independently per channel
+    Noise { sigma: f32 },
+
+    /// Raise every channel to the power `gamma` (below 1 brightens)
+    Gamma(f32),
+
+    /// YUV 4:2:0: luma kept per pixel, chroma averaged over each 2x2 pixels
+    ChromaSubsampling,
+}
+
+/// A series of impairments applied to every frame
+#[derive(Clone, Debug)]
+pub struct Channel {
+    /// Impairments, in the order they are applied
+    impairments: Vec<Impairment>,
+
+    /// Seed for noise
+    seed: u64,
+}
+
+/// What a channel did to an encoding
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelReport {
+    /// Data bits compared
+    pub bits: usize,
+
+    /// Data bits that read back wrong, before error correction
+    pub bit_errors: usize,
+
+    /// Whether the data decoded back exactly
+    pub decoded: bool,
+
+    /// Why decoding failed, if it did
+    pub error: Option<String>,
+}
+
+impl ChannelReport {
+    /// Fraction of data bits that read back wrong, before error correction
+    pub fn bit_error_rate(&self) -> f64 {
+        if self.bits == 0 {
+            return 0.0;
+        }
+        self.bit_errors as f64 / self.bits as f64
+    }
+}
+
+/// Encoders that can read back their raw data bits from damaged frames
+#[async_trait]
+pub trait RawDecoder: Encoder {
+    /// Hard decision for each bit of the original data, before error
+    /// correction and without verifying checksums
+    async fn decode_raw(&self, encoded: &EncodedData) -> Result<Vec<bool>>;
+}
+
+#[async_trait]
+impl RawDecoder for PixelEncoder {
+    async fn decode_raw(&self, encoded: &EncodedData) -> Result<Vec<bool>> {
+        let soft = self.decode_soft(encoded)?;
+        Ok(soft.into_iter().map(|llr| llr > 0.0).collect())
+    }
+}
+
+#[async_trait]
+impl RawDecoder for ColorEncoder {
+    async fn decode_raw(&self, encoded: &EncodedData) -> Result<Vec<bool>> {
+        let bytes = self.decode_uncorrected(encoded)?;
+        Ok(bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 == 1))
+            .collect())
+    }
+}
+
+impl Channel {
+    /// A channel that changes nothing
+    pub fn new() -> Self {
+        Self {
+            impairments: Vec::new(),
+            seed: 0,
+        }
+    }
+
+    /// Append an impairment, applied after those already added
+    pub fn then(mut self, impairment: Impairment) -> Self {
+        self.impairments.push(impairment);
+        self
+    }
+
+    /// Create with a custom noise seed
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Impairments, in the order they are applied
+    pub fn impairments(&self) -> &[Impairment] {
+        &self.impairments
+    }
+
+    /// Pass one frame through the channel
+    ///
+    /// `index` picks the noise drawn, so different frames get different
+    /// noise.
+    pub fn apply(&self, frame: &RgbaImage, index: u64) -> Result<RgbaImage> {
+        let mut frame = frame.clone();
+        let mut noise = SplitMix64::new(self.seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+
+        for impairment in &self.impairments {
+            frame = match *impairment {
+                Impairment::Jpeg { quality } => jpeg(&frame, quality)?,
+                #[cfg(feature = "webp")]
+                Impairment::Webp { quality } => webp(&frame, quality)?,
+                Impairment::Rescale { width, height } => {
+                    if width == 0 || height == 0 {
+                        return Err(Error::Config(format!(
+                            "Cannot rescale to {}x{}",
+                            width, height
+                        )));
+                    }
+                    let small = imageops::resize(&frame, width, height, FilterType::Triangle);
+                    imageops::resize(&small, frame.width(), frame.height(), FilterType::Triangle)
+                }
+                Impairment::Blur { sigma } => imageops::blur(&frame, sigma),
+                Impairment::Noise { sigma } => {
+                    for pixel in frame.pixels_mut() {
+                        for channel in &mut pixel.0[..3] {
+                            let level = *channel as f64 + noise.gaussian() * sigma as f64;
+                            *channel = level.round().clamp(0.0, 255.0) as u8;
+                        }
+                    }
+                    frame
+                }
+                Impairment::Gamma(gamma) => {
+                    if !(gamma.is_finite() && gamma > 0.0) {
+                        return Err(Error::Config(format!("Invalid gamma {}", gamma)));
+                    }
+                    for pixel in frame.pixels_mut() {
+                        for channel in &mut pixel.0[..3] {
+                            let level = 255.0 * (*channel as f64 / 255.0).powf(gamma as f64);
+                            *channel = level.round() as u8;
+                        }
+                    }
+                    frame
+                }
+                Impairment::ChromaSubsampling => subsample_420(&frame),
+            };
+        }
+
+        Ok(frame)
+    }
+
+    /// Pass every frame of an encoding through the channel
+    ///
+    /// The encoding must hold a PNG sequence, as pixel and color encoders
+    /// write, or a video file carrying its header. Frames come back as a PNG
+    /// sequence, re-encoded losslessly after the impairments, so the result
+    /// decodes like anything the encoder wrote.
+    pub fn transmit(&self, encoded: &EncodedData) -> Result<EncodedData> {
+        let (header, frames) = video::read_frames(&encoded.data)?;
+        let frames = frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| self.apply(frame, index as u64))
+            .collect::<Result<Vec<_>>>()?;
+
+        let payload = container::write_png_sequence(&frames)?;
+        Ok(EncodedData {
+            data: header.wrap(&payload)?,
+            format: "png_sequence".to_string(),
+            metadata: encoded.metadata.clone(),
+        })
+    }
+
+    /// Encode `data`, pass it through the channel and report how it came
+    /// back
+    pub async fn measure<E: RawDecoder + ?Sized>(
+        &self,
+        encoder: &E,
+        data: &[u8],
+    ) -> Result<ChannelReport> {
+        let encoded = encoder.encode(data).await?;
+        let received = self.transmit(&encoded)?;
+
+        // Bits that never came back count as errors
+        let raw = encoder.decode_raw(&received).await?;
+        let bits = data.len() * 8;
+        let bit_errors = (0..bits)
+            .filter(|&i| raw.get(i) != Some(&(data[i / 8] >> (7 - i % 8) & 1 == 1)))
+            .count();
+
+        let (decoded, error) = match encoder.decode(&received).await {
+            Ok(decoded) if decoded == data => (true, None),
+            Ok(_) => (false, Some("Decoded data differs".to_string())),
+            Err(e) => (false, Some(e.to_string())),
+        };
+
+        let report = ChannelReport {
+            bits,
+            bit_errors,
+            decoded,
+            error,
+        };
+        debug!(
+            "Channel {:?}: bit error rate {:.2e}, decoded: {}",
+            self.impairments,
+            report.bit_error_rate(),
+            report.decoded
+        );
+        Ok(report)
+    }
+
+    /// [`measure`](Self::measure) each encoder in turn
+    pub async fn sweep<E: RawDecoder>(
+        &self,
+        encoders: impl IntoIterator<Item = E>,
+        data: &[u8],
+    ) -> Result<Vec<(E, ChannelReport)>> {
+        let mut results = Vec::new();
+        for encoder in encoders {
+            let report = self.measure(&encoder, data).await?;
+            results.push((encoder, report));
+        }
+        Ok(results)
+    }
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `frame` after a JPEG round trip at `quality`
+fn jpeg(frame: &RgbaImage, quality: u8) -> Result<RgbaImage> {
+    let rgb = DynamicImage::ImageRgba8(frame.clone()).to_rgb8();
+    let mut bytes = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100))
+        .encode_image(&rgb)
+        .map_err(|e| Error::Encoding(format!("JPEG encoding failed: {}", e)))?;
+
+    Ok(
+        image::load_from_memory_with_format(&bytes, image::ImageFormat::Jpeg)
+            .map_err(|e| Error::Decoding(format!("JPEG decoding failed: {}", e)))?
+            .to_rgba8(),
+    )
+}
+
+/// `frame` after a lossy WebP round trip at `quality`
+#[cfg(feature = "webp")]
+// Lossy WebP is deprecated upstream, but it is what image hosts serve
+#[allow(deprecated)]
+fn webp(frame: &RgbaImage, quality: u8) -> Result<RgbaImage> {
+    use image::codecs::webp::{WebPEncoder, WebPQuality};
+
+    let rgb = DynamicImage::ImageRgba8(frame.clone()).to_rgb8();
+    let mut bytes = Vec::new();
+    WebPEncoder::new_with_quality(&mut bytes, WebPQuality::lossy(quality.clamp(1, 100)))
+        .encode(&rgb, rgb.width(), rgb.height(), image::ColorType::Rgb8)
+        .map_err(|e| Error::Encoding(format!("WebP encoding failed: {}", e)))?;
+
+    Ok(
+        image::load_from_memory_with_format(&bytes, image::ImageFormat::WebP)
+            .map_err(|e| Error::Decoding(format!("WebP decoding failed: {}", e)))?
+            .to_rgba8(),
+    )
+}
+
+/// `frame` with chroma averaged over each 2x2 pixels (BT.601 full range)
+pub(crate) fn subsample_420(frame: &RgbaImage) -> RgbaImage {
+    let chroma = chroma_420(frame);
+    let chroma_width = frame.width().div_ceil(2) as usize;
+
+    let mut out = frame.clone();
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let [u, v] = chroma[(y / 2) as usize * chroma_width + (x / 2) as usize];
+        let [r, g, b] = yuv_to_rgb([yuv_of(pixel)[0], u, v])
+            .map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+        *pixel = Rgba([r, g, b, pixel[3]]);
+    }
+    out
+}
+
+/// Small deterministic generator for noise (splitmix64)
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1]
+    fn uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal (Box–Muller)
+    fn gaussian(&mut self) -> f64 {
+        let radius = (-2.0 * self.uniform().ln()).sqrt();
+        radius * (std::f64::consts::TAU * self.uniform()).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::testing::test_data;
+
+    #[tokio::test]
+    async fn test_clean_channel_is_lossless() {
+        let encoder = PixelEncoder::new().with_resolution(160, 90);
+        let report = Channel::new()
+            .measure(&encoder, &test_data(400, 89))
+            .await
+            .unwrap();
+
+        assert_eq!(report.bits, 400 * 8);
+        assert_eq!(report.bit_errors, 0);
+        assert!(report.decoded && report.error.is_none());
+    }
+
+    #[test]
+    fn test_impairments_keep_frame_size() {
+        let frame = RgbaImage::from_fn(33, 17, |x, y| {
+            let level = ((x * 7 + y * 11) % 256) as u8;
+            Rgba([level, 255 - level, level / 2, 255])
+        });
+
+        let channel = Channel::new()
+            .then(Impairment::Jpeg { quality: 50 })
+            .then(Impairment::Rescale {
+                width: 20,
+                height: 10,
+            })
+            .then(Impairment::Blur { sigma: 1.0 })
+            .then(Impairment::Noise { sigma: 4.0 })
+            .then(Impairment::Gamma(0.8))
+            .then(Impairment::ChromaSubsampling);
+        let out = channel.apply(&frame, 0).unwrap();
+        assert_eq!(out.dimensions(), frame.dimensions());
+
+        // Noise is seeded: same frame index, same noise
+        assert_eq!(channel.apply(&frame, 0).unwrap(), out);
+        assert_ne!(channel.apply(&frame, 1).unwrap(), out);
+
+        let bad = Channel::new().then(Impairment::Rescale {
+            width: 0,
+            height: 10,
+        });
+        assert!(bad.apply(&frame, 0).is_err());
+
+        for gamma in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let bad = Channel::new().then(Impairment::Gamma(gamma));
+            assert!(bad.apply(&frame, 0).is_err(), "{}", gamma);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heavier_damage_raises_bit_error_rate() {
+        let encoder = PixelEncoder::new()
+            .with_resolution(160, 90)
+            .with_block_size(2)
+            .with_levels(8);
+        let data = test_data(400, 89);
+
+        let mut rates = Vec::new();
+        for sigma in [0.0, 20.0, 60.0] {
+            let channel = Channel::new().then(Impairment::Noise { sigma });
+            rates.push(channel.measure(&encoder, &data).await.unwrap());
+        }
+
+        assert_eq!(rates[0].bit_errors, 0);
+        assert!(rates[1].bit_error_rate() > 0.0);
+        assert!(rates[2].bit_error_rate() > rates[1].bit_error_rate());
+        assert!(!rates[2].decoded && rates[2].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_sweep_block_size_and_levels_under_jpeg() {
+        let channel = Channel::new()
+            .then(Impairment::Jpeg { quality: 30 })
+            .then(Impairment::Blur { sigma: 0.8 });
+        let data = test_data(400, 89);
+
+        let configs = [(2, 8), (8, 2)].map(|(block_size, levels)| {
+            PixelEncoder::new()
+                .with_resolution(320, 180)
+                .with_block_size(block_size)
+                .with_levels(levels)
+        });
+        let results = channel.sweep(configs, &data).await.unwrap();
+        assert_eq!(results.len(), 2);
+
+        // Small blocks with many levels break down where large binary blocks
+        // don't
+        let (fragile, robust) = (&results[0].1, &results[1].1);
+        assert!(fragile.bit_error_rate() > robust.bit_error_rate());
+        assert!(robust.decoded, "{:?}", robust);
+    }
+
+    #[tokio::test]
+    async fn test_color_encoder_raw_bits() {
+        let encoder = ColorEncoder::new()
+            .with_resolution(160, 96)
+            .with_error_correction(16);
+        let data = test_data(300, 89);
+
+        let clean = Channel::new().measure(&encoder, &data).await.unwrap();
+        assert_eq!(clean.bits, data.len() * 8);
+        assert_eq!(clean.bit_errors, 0);
+        assert!(clean.decoded);
+
+        // Small blocks average out little of the noise
+        let fragile = ColorEncoder::new()
+            .with_palette_size(64)
+            .with_block_size(2)
+            .with_resolution(160, 96);
+        let noisy = Channel::new()
+            .then(Impairment::Noise { sigma: 40.0 })
+            .measure(&fragile, &data)
+            .await
+            .unwrap();
+        assert!(noisy.bit_errors > 0, "{:?}", noisy);
+    }
+
+    #[cfg(feature = "webp")]
+    #[tokio::test]
+    async fn test_webp_recompression() {
+        let encoder = PixelEncoder::new().with_resolution(320, 180);
+        let channel = Channel::new().then(Impairment::Webp { quality: 50 });
+
+        let report = channel
+            .measure(&encoder, &test_data(400, 89))
+            .await
+            .unwrap();
+        assert!(report.decoded, "{:?}", report);
+    }
+}
@@ -1,19 +1,669 @@
 //! RGB color encoding
 //!
 //! Maps data to unique RGB color values for higher density encoding.
+//!
+//! Each block shows one color from a palette chosen for maximal separation in
+//! the selected color space, carrying log2(palette size) bits. The top of
+//! every frame holds a calibration strip showing each palette color in turn;
+//! the decoder measures where the colors landed after lossy re-encoding and
+//! assigns each block to the nearest measured color by Euclidean distance.
+//!
+//! With [`ColorSpace::YUV`] frames are laid out to survive YUV 4:2:0, which
+//! keeps luma per pixel but shares one chroma sample between each 2x2 pixels.
+//! Every block shows one of several luma levels, while its color comes from
+//! a chroma palette shared by a cell of 2x2 blocks, so chroma symbols always
+//! line up with the subsampling grid. Luma levels and chroma points are kept
+//! inside the RGB gamut in every combination, so neither clips the other.
+//!
+//! Each frame's data is a whole number of bytes, optionally protected by
+//! interleaved Reed–Solomon codewords (see [`crate::ecc`]).
 
+use crate::container::{self, ContainerHeader};
+use crate::ecc::FrameCode;
+use crate::gray;
 use async_trait::async_trait;
-use isg_core::{EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result};
+use image::{Rgba, RgbaImage};
+use isg_core::{
+    ColorSpace, EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use tracing::{debug, trace};
+
+/// Largest supported palette
+const MAX_PALETTE: usize = 256;
+
+/// Channel values considered when choosing palette colors
+const CANDIDATE_STEPS: u32 = 16;
+
+/// Refinement passes when choosing palette colors
+const REFINE_PASSES: usize = 8;
+
+/// Default luma levels per block in YUV mode
+const DEFAULT_LUMA_LEVELS: u8 = 4;
 
-/// Color encoder (placeholder for future implementation)
+/// Luma kept clear of black and white in YUV mode, leaving room for chroma
+const LUMA_MARGIN: f64 = 64.0;
+
+/// Chroma values considered when choosing YUV chroma points
+const CHROMA_STEP: f64 = 2.0;
+
+/// Color encoder configuration
 #[derive(Clone, Debug)]
 pub struct ColorEncoder {
-    // TODO: Implement color-based encoding
+    /// Color space the palette is spread out in
+    color_space: ColorSpace,
+
+    /// Number of palette colors (a power of two); chroma points in YUV mode
+    palette_size: usize,
+
+    /// Luma levels per block in YUV mode (a power of two)
+    luma_levels: u8,
+
+    /// Reed–Solomon check bytes per codeword (0 = no error correction)
+    parity: usize,
+
+    /// Block size (e.g., 8 means 8x8 pixels per symbol)
+    block_size: u32,
+
+    /// Resolution (width, height)
+    resolution: (u32, u32),
+
+    /// Strategy describing the settings above
+    strategy: EncodingStrategy,
 }
 
 impl ColorEncoder {
+    /// Create a new color encoder with default settings (16 RGB colors)
     pub fn new() -> Self {
-        Self {}
+        Self::with_color_space(ColorSpace::RGB)
+    }
+
+    /// Create for a specific color space (YUV defaults to 4 chroma points
+    /// and 4 luma levels)
+    pub fn with_color_space(color_space: ColorSpace) -> Self {
+        let palette_size = match color_space {
+            ColorSpace::YUV => 4,
+            _ => 16,
+        };
+
+        Self {
+            strategy: EncodingStrategy::ColorEncoding {
+                color_space: color_space.clone(),
+            },
+            color_space,
+            palette_size,
+            luma_levels: DEFAULT_LUMA_LEVELS,
+            parity: 0,
+            block_size: 8,
+            resolution: (1920, 1080),
+        }
+    }
+
+    /// Create with a custom palette size (a power of two from 2 to 256)
+    pub fn with_palette_size(mut self, palette_size: usize) -> Self {
+        self.palette_size = palette_size;
+        self
+    }
+
+    /// Create with custom luma levels for YUV mode (a power of two from 2
+    /// to 16)
+    pub fn with_luma_levels(mut self, luma_levels: u8) -> Self {
+        self.luma_levels = luma_levels;
+        self
+    }
+
+    /// Protect each frame with Reed–Solomon codewords carrying `parity` check
+    /// bytes each (0 disables error correction)
+    pub fn with_error_correction(mut self, parity: usize) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Create with custom block size
+    pub fn with_block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Create with custom resolution
+    pub fn with_resolution(mut self, width: u32, height: u32) -> Self {
+        self.resolution = (width, height);
+        self
+    }
+
+    /// Whether symbols are split into per-block luma and per-cell chroma
+    fn subsampled(&self) -> bool {
+        matches!(self.color_space, ColorSpace::YUV)
+    }
+
+    /// Whether the configured palette size is supported
+    fn palette_supported(&self) -> bool {
+        (2..=MAX_PALETTE).contains(&self.palette_size) && self.palette_size.is_power_of_two()
+    }
+
+    /// Error for an unsupported palette size
+    fn palette_size_error(&self) -> Error {
+        Error::Config(format!(
+            "Palette size must be a power of two from 2 to {}, got {}",
+            MAX_PALETTE, self.palette_size
+        ))
+    }
+
+    /// Palette for the current settings, failing if the size is unsupported
+    ///
+    /// Choosing colors searches thousands of candidates, so each palette is
+    /// built on first use and shared by every encoder with the same settings.
+    fn palette(&self) -> Result<Arc<Palette>> {
+        static PALETTES: OnceLock<Mutex<PaletteCache>> = OnceLock::new();
+
+        if !self.palette_supported() {
+            return Err(self.palette_size_error());
+        }
+
+        let space = match self.color_space {
+            ColorSpace::RGB => 0,
+            ColorSpace::YUV => 1,
+            ColorSpace::HSV => 2,
+        };
+        let mut palettes = PALETTES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let palette = palettes
+            .entry((self.palette_size, space))
+            .or_insert_with(|| {
+                Arc::new(if self.subsampled() {
+                    Palette::chroma(self.palette_size)
+                } else {
+                    Palette::generate(self.palette_size, &self.color_space)
+                })
+            });
+        Ok(Arc::clone(palette))
+    }
+
+    /// Bits carried by each palette symbol
+    fn bits_per_block(&self) -> usize {
+        self.palette_size.trailing_zeros() as usize
+    }
+
+    /// Bits carried by each block's luma level in YUV mode
+    fn bits_per_luma(&self) -> Result<usize> {
+        Ok(gray::bits_per_block(self.luma_levels)? as usize)
+    }
+
+    /// Data bits per frame: a palette symbol per block, or in YUV mode a
+    /// chroma symbol plus four luma levels per cell
+    fn bits_per_frame(&self) -> Result<usize> {
+        let (_, _, data_units) = self.layout()?;
+        let bits = if self.subsampled() {
+            self.bits_per_block() + 4 * self.bits_per_luma()?
+        } else {
+            self.bits_per_block()
+        };
+        Ok(data_units * bits)
+    }
+
+    /// Error correction code over the bytes of a frame
+    fn frame_code(&self) -> Result<FrameCode> {
+        let code = FrameCode::new(self.bits_per_frame()? / 8, self.parity)?;
+        if code.data_len() == 0 {
+            return Err(Error::Config(format!(
+                "Resolution {:?} holds no data bytes with block size {}",
+                self.resolution, self.block_size
+            )));
+        }
+        Ok(code)
+    }
+
+    /// Decoder for data encoded with the settings recorded in `header`
+    fn decoder_for(&self, header: &ContainerHeader) -> Result<Self> {
+        let EncodingStrategy::ColorEncoding { color_space } = &header.strategy else {
+            return Err(Error::Decoding(format!(
+                "Expected color encoding, found {:?}",
+                header.strategy
+            )));
+        };
+
+        let missing = |name: &str| Error::Decoding(format!("Color container is missing {}", name));
+        let resolution = &header.parameters["resolution"];
+        let (Some(width), Some(height)) = (resolution[0].as_u64(), resolution[1].as_u64()) else {
+            return Err(Error::Decoding(
+                "Color container is missing resolution".to_string(),
+            ));
+        };
+        let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+            return Err(Error::Corruption(format!(
+                "Color container resolution out of range: {}x{}",
+                width, height
+            )));
+        };
+
+        let decoder = Self {
+            color_space: color_space.clone(),
+            palette_size: container::parameter(header, "palette_size")?
+                .ok_or_else(|| missing("palette_size"))?,
+            luma_levels: container::parameter(header, "luma_levels")?
+                .unwrap_or(DEFAULT_LUMA_LEVELS),
+            parity: container::parameter(header, "ecc_parity")?.unwrap_or(0),
+            block_size: container::parameter(header, "block_size")?
+                .ok_or_else(|| missing("block_size"))?,
+            resolution: (width, height),
+            strategy: header.strategy.clone(),
+        };
+
+        // Checked before anything is allocated, as it is from untrusted data
+        decoder.validate().map_err(container::corrupt_settings)?;
+        Ok(decoder)
+    }
+
+    /// Parameters recorded alongside the strategy
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "palette_size": self.palette_size,
+            "luma_levels": self.luma_levels,
+            "block_size": self.block_size,
+            "resolution": self.resolution,
+            "ecc_parity": self.parity,
+            "ecc_rate": self.frame_code().map_or(1.0, |code| code.rate()),
+        })
+    }
+
+    /// Check the settings before allocating frames or a palette for them
+    fn validate(&self) -> Result<()> {
+        container::check_frame_size(self.resolution, self.block_size)?;
+        if !self.palette_supported() {
+            return Err(self.palette_size_error());
+        }
+        if self.subsampled() {
+            self.bits_per_luma()?;
+        }
+        Ok(())
+    }
+
+    /// Grid size, rows taken by the calibration strip, and data units per
+    /// frame, where a unit is a block, or a cell of 2x2 blocks in YUV mode
+    fn layout(&self) -> Result<((u32, u32), u32, usize)> {
+        self.validate()?;
+
+        let (width, height) = self.resolution;
+        let mut units_x = width / self.block_size;
+        let mut units_y = height / self.block_size;
+
+        // The strip shows every palette color, and in YUV mode every luma
+        // level across the blocks of its cells
+        let mut strip_units = self.palette_size as u32;
+        if self.subsampled() {
+            units_x /= 2;
+            units_y /= 2;
+            strip_units = strip_units.max((self.luma_levels as u32).div_ceil(4));
+        }
+
+        let strip_rows = strip_units.div_ceil(units_x.max(1));
+        let data_units = (units_x * units_y.saturating_sub(strip_rows)) as usize;
+        if data_units == 0 {
+            return Err(Error::Config(format!(
+                "Resolution {:?} holds no data blocks of size {} besides the calibration strip",
+                self.resolution, self.block_size
+            )));
+        }
+
+        Ok(((units_x, units_y), strip_rows, data_units))
+    }
+
+    /// Encode binary data into image frames
+    fn encode_to_frames(&self, data: &[u8]) -> Result<Vec<RgbaImage>> {
+        let palette = self.palette()?;
+        let ((units_x, units_y), strip_rows, _) = self.layout()?;
+        let bits = self.bits_per_block();
+        let code = self.frame_code()?;
+        let num_frames = data.len().div_ceil(code.data_len());
+
+        debug!(
+            "Encoding {} bytes into {} frames of {} colors",
+            data.len(),
+            num_frames,
+            self.palette_size
+        );
+
+        let mut frames = Vec::with_capacity(num_frames);
+
+        for (frame_idx, chunk) in data.chunks(code.data_len()).enumerate() {
+            let mut frame = RgbaImage::new(self.resolution.0, self.resolution.1);
+            let bytes = code.encode(chunk);
+            let mut bit_index = 0;
+
+            for unit_y in 0..units_y {
+                for unit_x in 0..units_x {
+                    let index = (unit_y * units_x + unit_x) as usize;
+                    if self.subsampled() {
+                        self.fill_cell(
+                            &mut frame,
+                            &palette,
+                            (unit_x, unit_y),
+                            index,
+                            (unit_y >= strip_rows).then_some((&bytes[..], &mut bit_index)),
+                        )?;
+                        continue;
+                    }
+
+                    let color = if unit_y < strip_rows {
+                        // Calibration strip: every palette color in turn
+                        index % self.palette_size
+                    } else {
+                        take_bits(&bytes, &mut bit_index, bits)
+                    };
+
+                    let [r, g, b] = palette.colors[color];
+                    self.fill_block(&mut frame, unit_x, unit_y, Rgba([r, g, b, 255]));
+                }
+            }
+
+            frames.push(frame);
+            trace!("Created frame {}/{}", frame_idx + 1, num_frames);
+        }
+
+        Ok(frames)
+    }
+
+    /// Fill a cell of 2x2 blocks in YUV mode: a chroma symbol shared by the
+    /// cell and a luma level per block, taken from `data` or, without it,
+    /// cycled through for the calibration strip
+    fn fill_cell(
+        &self,
+        img: &mut RgbaImage,
+        palette: &Palette,
+        (cell_x, cell_y): (u32, u32),
+        index: usize,
+        data: Option<(&[u8], &mut usize)>,
+    ) -> Result<()> {
+        let luma_bits = self.bits_per_luma()?;
+
+        let (chroma, levels) = match data {
+            Some((data, bit_index)) => {
+                let chroma = take_bits(data, bit_index, self.bits_per_block());
+                let levels = [(); 4]
+                    .map(|_| gray::symbol_level(take_bits(data, bit_index, luma_bits) as u8));
+                (chroma, levels)
+            }
+            None => {
+                let levels = std::array::from_fn(|block| {
+                    ((4 * index + block) % self.luma_levels as usize) as u8
+                });
+                (index % self.palette_size, levels)
+            }
+        };
+
+        for (block, &level) in levels.iter().enumerate() {
+            let color = palette.shade(chroma, self.luma_of(level));
+            self.fill_block(
+                img,
+                2 * cell_x + block as u32 % 2,
+                2 * cell_y + block as u32 / 2,
+                color,
+            );
+        }
+        Ok(())
+    }
+
+    /// Nominal luma of a level in YUV mode (0 = brightest)
+    fn luma_of(&self, level: u8) -> f64 {
+        let range = 255.0 - 2.0 * LUMA_MARGIN;
+        LUMA_MARGIN + gray::level_luma(level, self.luma_levels) as f64 * range / 255.0
+    }
+
+    /// Fill a block with a color
+    fn fill_block(&self, img: &mut RgbaImage, block_x: u32, block_y: u32, color: Rgba<u8>) {
+        for dy in 0..self.block_size {
+            for dx in 0..self.block_size {
+                let x = block_x * self.block_size + dx;
+                let y = block_y * self.block_size + dy;
+
+                if x < img.width() && y < img.height() {
+                    img.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Average color of a block's centre, away from edges smeared by
+    /// chroma subsampling
+    fn read_block(&self, frame: &RgbaImage, block_x: u32, block_y: u32) -> [f64; 3] {
+        let margin = self.block_size / 4;
+        let mut sum = [0.0; 3];
+        let mut count = 0.0;
+
+        for dy in margin..self.block_size - margin {
+            for dx in margin..self.block_size - margin {
+                let x = block_x * self.block_size + dx;
+                let y = block_y * self.block_size + dy;
+
+                if x < frame.width() && y < frame.height() {
+                    let pixel = frame.get_pixel(x, y);
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        *total += pixel[channel] as f64;
+                    }
+                    count += 1.0;
+                }
+            }
+        }
+
+        if count == 0.0 {
+            return [0.0; 3];
+        }
+        sum.map(|total| total / count)
+    }
+
+    /// Where each palette color landed in a frame, measured from the
+    /// calibration strip (in color space coordinates)
+    fn measure_palette(&self, frame: &RgbaImage) -> Result<Vec<[f64; 3]>> {
+        let ((blocks_x, _), strip_rows, _) = self.layout()?;
+        let mut sums = vec![([0.0; 3], 0.0); self.palette_size];
+
+        for block_y in 0..strip_rows {
+            for block_x in 0..blocks_x {
+                let index = (block_y * blocks_x + block_x) as usize;
+                let color = self.read_block(frame, block_x, block_y);
+                let (sum, count) = &mut sums[index % self.palette_size];
+                for channel in 0..3 {
+                    sum[channel] += color[channel];
+                }
+                *count += 1.0;
+            }
+        }
+
+        Ok(sums
+            .into_iter()
+            .map(|(sum, count)| to_space(sum.map(|total| total / count), &self.color_space))
+            .collect())
+    }
+
+    /// Read the palette index of every data block in a frame
+    fn read_symbols(&self, frame: &RgbaImage, measured: &[[f64; 3]]) -> Result<Vec<usize>> {
+        let ((blocks_x, blocks_y), strip_rows, _) = self.layout()?;
+
+        Ok((strip_rows..blocks_y)
+            .flat_map(|block_y| (0..blocks_x).map(move |block_x| (block_x, block_y)))
+            .map(|(block_x, block_y)| {
+                let color = self.read_block(frame, block_x, block_y);
+                nearest(measured, to_space(color, &self.color_space))
+            })
+            .collect())
+    }
+
+    /// Read the chroma symbol and luma levels of every data cell in a YUV
+    /// mode frame, as (symbol, bits) pairs in encoding order
+    fn read_cells(&self, frame: &RgbaImage) -> Result<Vec<(usize, usize)>> {
+        let ((cells_x, cells_y), strip_rows, data_cells) = self.layout()?;
+        let chroma_bits = self.bits_per_block();
+        let luma_bits = self.bits_per_luma()?;
+        let levels = self.luma_levels as usize;
+
+        // Average color of each block in a cell, in YUV
+        let read_cell = |cell_x: u32, cell_y: u32| -> [[f64; 3]; 4] {
+            std::array::from_fn(|block| {
+                let color = self.read_block(
+                    frame,
+                    2 * cell_x + block as u32 % 2,
+                    2 * cell_y + block as u32 / 2,
+                );
+                to_space(color, &ColorSpace::YUV)
+            })
+        };
+        // Chroma shared by a cell, with luma dropped so only chroma counts
+        let chroma_of = |blocks: &[[f64; 3]; 4]| {
+            let mut chroma = [0.0; 3];
+            for block in blocks {
+                chroma[1] += block[1] / 4.0;
+                chroma[2] += block[2] / 4.0;
+            }
+            chroma
+        };
+
+        // Where luma levels and chroma points landed, from the strip
+        let mut luma_sums = vec![(0.0, 0.0); levels];
+        let mut chroma_sums = vec![([0.0; 3], 0.0); self.palette_size];
+        for cell_y in 0..strip_rows {
+            for cell_x in 0..cells_x {
+                let index = (cell_y * cells_x + cell_x) as usize;
+                let blocks = read_cell(cell_x, cell_y);
+
+                for (block, yuv) in blocks.iter().enumerate() {
+                    let (sum, count) = &mut luma_sums[(4 * index + block) % levels];
+                    *sum += yuv[0];
+                    *count += 1.0;
+                }
+
+                let (sum, count) = &mut chroma_sums[index % self.palette_size];
+                for (total, value) in sum.iter_mut().zip(chroma_of(&blocks)) {
+                    *total += value;
+                }
+                *count += 1.0;
+            }
+        }
+        let luma: Vec<[f64; 3]> = luma_sums
+            .into_iter()
+            .map(|(sum, count)| [sum / count, 0.0, 0.0])
+            .collect();
+        let chroma: Vec<[f64; 3]> = chroma_sums
+            .into_iter()
+            .map(|(sum, count)| sum.map(|total| total / count))
+            .collect();
+
+        let mut symbols = Vec::with_capacity(data_cells * 5);
+        for cell_y in strip_rows..cells_y {
+            for cell_x in 0..cells_x {
+                let blocks = read_cell(cell_x, cell_y);
+                symbols.push((nearest(&chroma, chroma_of(&blocks)), chroma_bits));
+                for yuv in &blocks {
+                    let level = nearest(&luma, [yuv[0], 0.0, 0.0]) as u8;
+                    symbols.push((gray::to_gray(level) as usize, luma_bits));
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Decode frames back to binary data, with the bytes corrected in each
+    /// frame
+    fn decode_from_frames(
+        &self,
+        frames: &[RgbaImage],
+        expected_size: usize,
+    ) -> Result<(Vec<u8>, Vec<usize>)> {
+        let code = self.frame_code()?;
+        let mut data = Vec::with_capacity(expected_size);
+        let mut corrections = Vec::with_capacity(frames.len());
+
+        for (index, frame) in frames.iter().enumerate() {
+            let bytes = self.read_frame_bytes(index, frame)?;
+            let (frame_data, corrected, failed) = code.decode(&bytes);
+            if corrected > 0 || failed > 0 {
+                debug!(
+                    "Frame {}: {} bytes corrected, {} codewords beyond repair",
+                    index, corrected, failed
+                );
+            }
+            data.extend(frame_data);
+            corrections.push(corrected);
+        }
+
+        if data.len() < expected_size {
+            return Err(Error::Corruption(format!(
+                "Decoded {} bytes, expected {}",
+                data.len(),
+                expected_size
+            )));
+        }
+
+        data.truncate(expected_size);
+        Ok((data, corrections))
+    }
+
+    /// Bytes of a frame as read, check bytes included
+    fn read_frame_bytes(&self, index: usize, frame: &RgbaImage) -> Result<Vec<u8>> {
+        let bits = self.bits_per_block();
+        let symbols = if self.subsampled() {
+            self.read_cells(frame)?
+        } else {
+            let measured = self.measure_palette(frame)?;
+            trace!("Frame {} palette: {:?}", index, measured);
+
+            self.read_symbols(frame, &measured)?
+                .into_iter()
+                .map(|symbol| (symbol, bits))
+                .collect()
+        };
+
+        let mut bytes = Vec::new();
+        let mut byte = 0u8;
+        let mut filled = 0;
+        for (symbol, bits) in symbols {
+            for bit in (0..bits).rev() {
+                byte = (byte << 1) | ((symbol >> bit) & 1) as u8;
+                filled += 1;
+                if filled == 8 {
+                    bytes.push(byte);
+                    filled = 0;
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Decode data bytes as read, before error correction
+    ///
+    /// Meant for measuring raw error rates, so the checksum is not verified.
+    pub fn decode_uncorrected(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
+        let (header, payload) = ContainerHeader::parse(&encoded.data)?;
+        let decoder = self.decoder_for(&header)?;
+        let code = decoder.frame_code()?;
+
+        let mut data = Vec::new();
+        for (index, frame) in container::read_png_sequence(payload)?.iter().enumerate() {
+            let bytes = decoder.read_frame_bytes(index, frame)?;
+            data.extend(
+                code.data_positions()
+                    .map(|i| bytes.get(i).copied().unwrap_or(0)),
+            );
+        }
+
+        data.truncate(header.original_size as usize);
+        Ok(data)
+    }
+
+    /// Decode data, also returning how many bytes error correction fixed in
+    /// every frame
+    pub fn decode_with_corrections(&self, encoded: &EncodedData) -> Result<(Vec<u8>, Vec<usize>)> {
+        let (header, payload) = ContainerHeader::parse(&encoded.data)?;
+        let decoder = self.decoder_for(&header)?;
+        decoder.palette()?;
+
+        let frames = container::read_png_sequence(payload)?;
+        let (decoded, corrections) =
+            decoder.decode_from_frames(&frames, header.original_size as usize)?;
+        header.verify(&decoded)?;
+        Ok((decoded, corrections))
     }
 }
 
@@ -25,25 +675,496 @@ impl Default for ColorEncoder {
 
 #[async_trait]
 impl Encoder for ColorEncoder {
-    async fn encode(&self, _data: &[u8]) -> Result<EncodedData> {
-        Err(Error::Encoding(
-            "Color encoding not yet implemented".to_string(),
-        ))
+    async fn encode(&self, data: &[u8]) -> Result<EncodedData> {
+        debug!("Encoding {} bytes with color encoder", data.len());
+
+        let frames = self.encode_to_frames(data)?;
+
+        let mut parameters = self.parameters();
+        parameters["frame_count"] = (frames.len() as u32).into();
+        let header = ContainerHeader::new(self.strategy.clone(), data, parameters.clone());
+        let encoded = header.wrap(&container::write_png_sequence(&frames)?)?;
+
+        let metadata = EncodingMetadata {
+            original_size: data.len(),
+            encoded_size: encoded.len(),
+            compression_ratio: encoded.len() as f64 / data.len() as f64,
+            strategy: "color".to_string(),
+            parameters,
+        };
+
+        Ok(EncodedData {
+            data: encoded,
+            format: "png_sequence".to_string(),
+            metadata,
+        })
     }
 
-    async fn decode(&self, _encoded: &EncodedData) -> Result<Vec<u8>> {
-        Err(Error::Decoding(
-            "Color decoding not yet implemented".to_string(),
-        ))
+    async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
+        debug!("Decoding color-encoded data");
+        self.decode_with_corrections(encoded).map(|(data, _)| data)
     }
 
     fn strategy(&self) -> &EncodingStrategy {
-        static STRATEGY: once_cell::sync::Lazy<EncodingStrategy> =
-            once_cell::sync::Lazy::new(|| {
-                EncodingStrategy::ColorEncoding {
-                    color_space: isg_core::ColorSpace::RGB,
+        &self.strategy
+    }
+
+    fn estimate_size(&self, input_size: usize) -> usize {
+        let data_per_frame = self.frame_code().map_or(1, |code| code.data_len());
+        let num_frames = input_size.div_ceil(data_per_frame);
+
+        // Rough estimate: PNG keeps flat color blocks to about a third of RGBA
+        let (width, height) = self.resolution;
+        let bytes_per_frame = (width * height * 4) as usize / 3;
+
+        num_frames * (bytes_per_frame + 4) + container::HEADER_ESTIMATE + 4
+    }
+}
+
+/// Palettes built so far, by size and color space
+type PaletteCache = HashMap<(usize, u8), Arc<Palette>>;
+
+/// Palette colors, indexed by symbol
+#[derive(Clone, Debug)]
+struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Choose `size` colors spread as far apart as possible in `space`
+    ///
+    /// Farthest-point selection from a grid of RGB candidates, starting from
+    /// black, then refined by moving each color to the candidate farthest
+    /// from all the others until no color can move further away.
+    fn generate(size: usize, space: &ColorSpace) -> Self {
+        let step = 255 / (CANDIDATE_STEPS - 1);
+        let candidates: Vec<[u8; 3]> = (0..CANDIDATE_STEPS.pow(3))
+            .map(|i| {
+                let channel =
+                    |shift: u32| ((i / CANDIDATE_STEPS.pow(shift)) % CANDIDATE_STEPS * step) as u8;
+                [channel(2), channel(1), channel(0)]
+            })
+            .collect();
+        let points: Vec<[f64; 3]> = candidates
+            .iter()
+            .map(|c| to_space(c.map(f64::from), space))
+            .collect();
+
+        Self {
+            colors: spread(&points, size)
+                .into_iter()
+                .map(|c| candidates[c])
+                .collect(),
+        }
+    }
+
+    /// Choose `size` chroma points for YUV mode, spread as far apart as
+    /// possible while staying inside the RGB gamut at every luma level
+    ///
+    /// Colors are stored at mid luma; [`Palette::shade`] moves them to a
+    /// level.
+    fn chroma(size: usize) -> Self {
+        let steps = (255.0 / CHROMA_STEP) as i32;
+        let mid = 127.5;
+        let in_gamut = |yuv: [f64; 3]| {
+            [LUMA_MARGIN, 255.0 - LUMA_MARGIN].iter().all(|&luma| {
+                yuv_to_rgb([luma, yuv[1], yuv[2]])
+                    .iter()
+                    .all(|channel| (0.0..=255.0).contains(channel))
+            })
+        };
+        let points: Vec<[f64; 3]> = (0..=steps)
+            .flat_map(|u| (0..=steps).map(move |v| (u, v)))
+            .map(|(u, v)| {
+                let axis = |step: i32| step as f64 * CHROMA_STEP - mid;
+                [mid, axis(u), axis(v)]
+            })
+            .filter(|&point| in_gamut(point))
+            .collect();
+
+        Self {
+            colors: spread(&points, size)
+                .into_iter()
+                .map(|c| yuv_to_rgb(points[c]).map(|channel| channel.round() as u8))
+                .collect(),
+        }
+    }
+
+    /// Color `index` moved to `luma` without changing its chroma
+    fn shade(&self, index: usize, luma: f64) -> Rgba<u8> {
+        let [r, g, b] = self.colors[index].map(|channel| {
+            // Luma adds equally to every RGB channel
+            (channel as f64 + luma - 127.5).round().clamp(0.0, 255.0) as u8
+        });
+        Rgba([r, g, b, 255])
+    }
+}
+
+/// Indices of `size` points spread as far apart as possible
+///
+/// Farthest-point selection starting from the first point, then refined by
+/// moving each choice to the point farthest from all the others until none
+/// can move further away.
+fn spread(points: &[[f64; 3]], size: usize) -> Vec<usize> {
+    // Squared distance from every point to a chosen one
+    let column = |chosen: usize| -> Vec<f64> {
+        points
+            .iter()
+            .map(|&point| squared_distance(point, points[chosen]))
+            .collect()
+    };
+    let farthest = |clearance: &[f64]| {
+        (0..clearance.len()).fold(0, |best, c| {
+            if clearance[c] > clearance[best] {
+                c
+            } else {
+                best
+            }
+        })
+    };
+
+    let mut chosen = vec![0];
+    let mut columns = vec![column(0)];
+    let mut clearance = columns[0].clone();
+    while chosen.len() < size {
+        let next = farthest(&clearance);
+        let distances = column(next);
+        for (current, &distance) in clearance.iter_mut().zip(&distances) {
+            *current = current.min(distance);
+        }
+        chosen.push(next);
+        columns.push(distances);
+    }
+
+    for _ in 0..REFINE_PASSES {
+        let mut moved = false;
+        for slot in 0..chosen.len() {
+            // Clearance of every point from the other choices
+            let mut clearance = vec![f64::INFINITY; points.len()];
+            for (_, distances) in columns
+                .iter()
+                .enumerate()
+                .filter(|&(other, _)| other != slot)
+            {
+                for (current, &distance) in clearance.iter_mut().zip(distances) {
+                    *current = current.min(distance);
                 }
-            });
-        &STRATEGY
+            }
+
+            let best = farthest(&clearance);
+            if clearance[best] > clearance[chosen[slot]] + 1e-9 {
+                chosen[slot] = best;
+                columns[slot] = column(best);
+                moved = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    chosen
+}
+
+/// Coordinates of an RGB color in `space`, where Euclidean distance tracks
+/// how far apart colors are
+pub(crate) fn to_space(rgb: [f64; 3], space: &ColorSpace) -> [f64; 3] {
+    let [r, g, b] = rgb;
+    match space {
+        ColorSpace::RGB => rgb,
+        // BT.601 full range, chroma centred on zero
+        ColorSpace::YUV => [
+            0.299 * r + 0.587 * g + 0.114 * b,
+            -0.168_736 * r - 0.331_264 * g + 0.5 * b,
+            0.5 * r - 0.418_688 * g - 0.081_312 * b,
+        ],
+        // HSV cone: hue is the angle, chroma (saturation x value) the radius
+        ColorSpace::HSV => {
+            let max = r.max(g).max(b);
+            let chroma = max - r.min(g).min(b);
+            let sector = if chroma == 0.0 {
+                0.0
+            } else if max == r {
+                ((g - b) / chroma).rem_euclid(6.0)
+            } else if max == g {
+                (b - r) / chroma + 2.0
+            } else {
+                (r - g) / chroma + 4.0
+            };
+            let hue = sector * std::f64::consts::FRAC_PI_3;
+            [chroma * hue.cos(), chroma * hue.sin(), max]
+        }
+    }
+}
+
+/// RGB color of BT.601 full range YUV, chroma centred on zero (the inverse
+/// of [`to_space`] for YUV)
+pub(crate) fn yuv_to_rgb([y, u, v]: [f64; 3]) -> [f64; 3] {
+    [
+        y + 1.402 * v,
+        y - 0.344_136 * u - 0.714_136 * v,
+        y + 1.772 * u,
+    ]
+}
+
+/// BT.601 full range YUV of a pixel, chroma centred on zero
+pub(crate) fn yuv_of(pixel: &Rgba<u8>) -> [f64; 3] {
+    to_space(
+        [pixel[0], pixel[1], pixel[2]].map(f64::from),
+        &ColorSpace::YUV,
+    )
+}
+
+/// Chroma of `frame` averaged over each 2x2 pixels, row by row
+pub(crate) fn chroma_420(frame: &RgbaImage) -> Vec<[f64; 2]> {
+    let (width, height) = frame.dimensions();
+    let mut chroma = Vec::with_capacity(width.div_ceil(2) as usize * height.div_ceil(2) as usize);
+
+    for y in (0..height).step_by(2) {
+        for x in (0..width).step_by(2) {
+            // Odd edges share chroma over a smaller square
+            let square: Vec<[f64; 3]> = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
+                .into_iter()
+                .filter(|&(x, y)| x < width && y < height)
+                .map(|(x, y)| yuv_of(frame.get_pixel(x, y)))
+                .collect();
+            let n = square.len() as f64;
+            chroma.push([
+                square.iter().map(|c| c[1]).sum::<f64>() / n,
+                square.iter().map(|c| c[2]).sum::<f64>() / n,
+            ]);
+        }
+    }
+
+    chroma
+}
+
+/// Squared Euclidean distance between two points
+fn squared_distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+/// Index of the point nearest to `target`
+fn nearest(points: &[[f64; 3]], target: [f64; 3]) -> usize {
+    points
+        .iter()
+        .enumerate()
+        .min_by(|a, b| squared_distance(*a.1, target).total_cmp(&squared_distance(*b.1, target)))
+        .map_or(0, |(index, _)| index)
+}
+
+/// Next `bits` bits of data as a symbol, most significant first
+fn take_bits(data: &[u8], bit_index: &mut usize, bits: usize) -> usize {
+    let mut symbol = 0;
+    for _ in 0..bits {
+        symbol = (symbol << 1) | get_bit(data, *bit_index) as usize;
+        *bit_index += 1;
+    }
+    symbol
+}
+
+/// Get a bit from data at given index (zero past the end)
+fn get_bit(data: &[u8], bit_index: usize) -> bool {
+    data.get(bit_index / 8)
+        .is_some_and(|byte| (byte >> (7 - bit_index % 8)) & 1 == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel;
+    use crate::container::testing::{edit_frames, edit_header, frames, test_data};
+
+    /// Palette colors in color space coordinates
+    fn points(palette: &Palette, space: &ColorSpace) -> Vec<[f64; 3]> {
+        palette
+            .colors
+            .iter()
+            .map(|c| to_space(c.map(f64::from), space))
+            .collect()
+    }
+
+    /// Smallest distance between two palette colors
+    fn separation(palette: &Palette, space: &ColorSpace) -> f64 {
+        let points = points(palette, space);
+        let mut min = f64::INFINITY;
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                min = min.min(squared_distance(*a, *b).sqrt());
+            }
+        }
+        min
+    }
+
+    /// Pass frames through YUV 4:2:0: luma kept per pixel, chroma averaged
+    /// over each 2x2 pixels
+    fn subsample_420(encoded: &EncodedData) -> EncodedData {
+        edit_frames(encoded, |frames| {
+            for frame in frames {
+                *frame = channel::subsample_420(frame);
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_color_roundtrip() {
+        let data = test_data(600, 151);
+
+        for (space, size) in [
+            (ColorSpace::RGB, 8),
+            (ColorSpace::YUV, 16),
+            (ColorSpace::HSV, 64),
+        ] {
+            let encoder = ColorEncoder::with_color_space(space)
+                .with_palette_size(size)
+                .with_resolution(160, 96);
+            let encoded = encoder.encode(&data).await.unwrap();
+            assert_eq!(encoded.format, "png_sequence");
+
+            // Palette and geometry come from the header
+            let decoded = ColorEncoder::new().decode(&encoded).await.unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_header_settings() {
+        let encoder = ColorEncoder::with_color_space(ColorSpace::YUV).with_resolution(160, 96);
+        let encoded = encoder.encode(b"checked settings").await.unwrap();
+
+        for (name, value) in [
+            ("luma_levels", 260u64),
+            ("block_size", 0),
+            ("palette_size", 1 << 40),
+        ] {
+            let encoded = edit_header(&encoded, |header| header.parameters[name] = value.into());
+            let result = ColorEncoder::new().decode(&encoded).await;
+            assert!(
+                matches!(result, Err(Error::Corruption(_))),
+                "{}: {:?}",
+                name,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn test_palette_separation() {
+        // Eight RGB colors end up on the corners of the cube
+        let palette = Palette::generate(8, &ColorSpace::RGB);
+        assert_eq!(separation(&palette, &ColorSpace::RGB), 255.0);
+        assert!(palette.colors.contains(&[255, 255, 255]));
+
+        let dense = Palette::generate(16, &ColorSpace::YUV);
+        assert_eq!(dense.colors.len(), 16);
+        assert!(separation(&dense, &ColorSpace::YUV) > 60.0);
+
+        assert!(ColorEncoder::new().with_palette_size(12).palette().is_err());
+
+        // Palettes are built once and shared
+        let encoder = ColorEncoder::with_color_space(ColorSpace::HSV).with_palette_size(32);
+        let rebuilt = ColorEncoder::new().with_palette_size(32).with_block_size(4);
+        assert!(!Arc::ptr_eq(
+            &encoder.palette().unwrap(),
+            &rebuilt.palette().unwrap()
+        ));
+        assert!(Arc::ptr_eq(
+            &encoder.palette().unwrap(),
+            &encoder.clone().with_resolution(640, 480).palette().unwrap()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_yuv_mode_survives_chroma_subsampling() {
+        let data = test_data(2000, 89);
+
+        // Single-pixel blocks: per-pixel color is lost in 4:2:0
+        let rgb = ColorEncoder::new()
+            .with_block_size(1)
+            .with_resolution(64, 48);
+        let encoded = rgb.encode(&data).await.unwrap();
+        let result = rgb.decode(&subsample_420(&encoded)).await;
+        assert_ne!(result.ok(), Some(data.clone()));
+
+        // Per-pixel luma with chroma per 2x2 pixels comes through
+        let yuv = ColorEncoder::with_color_space(ColorSpace::YUV)
+            .with_block_size(1)
+            .with_resolution(64, 48);
+        let encoded = yuv.encode(&data).await.unwrap();
+        let decoded = yuv.decode(&subsample_420(&encoded)).await.unwrap();
+        assert_eq!(decoded, data);
+
+        // 20x12 blocks make 10x6 cells, one row of them calibration; each
+        // cell carries a chroma symbol and four luma levels
+        let capacity = |encoder: ColorEncoder| encoder.with_resolution(160, 96).bits_per_frame();
+        let yuv = || ColorEncoder::with_color_space(ColorSpace::YUV);
+        assert_eq!(capacity(yuv()).unwrap(), 50 * (2 + 4 * 2));
+        // Sixteen chroma points take a second strip row
+        assert_eq!(
+            capacity(yuv().with_palette_size(16).with_luma_levels(16)).unwrap(),
+            40 * (4 + 4 * 4)
+        );
+        assert!(capacity(yuv().with_luma_levels(3)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_error_correction_repairs_damaged_blocks() {
+        let data = test_data(300, 43);
+        let smudge = |encoded: &EncodedData| {
+            edit_frames(encoded, |frames| {
+                for frame in frames {
+                    for y in 40..56 {
+                        for x in 48..80 {
+                            frame.put_pixel(x, y, Rgba([255, 0, 128, 255]));
+                        }
+                    }
+                }
+            })
+        };
+
+        let encoder = ColorEncoder::new()
+            .with_resolution(160, 96)
+            .with_error_correction(16);
+        let encoded = encoder.encode(&data).await.unwrap();
+        assert_eq!(encoded.metadata.parameters["ecc_parity"], 16);
+
+        let (decoded, corrections) = encoder.decode_with_corrections(&smudge(&encoded)).unwrap();
+        assert_eq!(decoded, data);
+        assert!(corrections.iter().all(|&c| c > 0));
+
+        let unprotected = encoder.with_error_correction(0);
+        let encoded = unprotected.encode(&data).await.unwrap();
+        assert!(unprotected.decode(&smudge(&encoded)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_calibration_corrects_palette_drift() {
+        let encoder = ColorEncoder::new()
+            .with_palette_size(16)
+            .with_resolution(160, 96);
+        let data = b"colors drift through a lossy codec".repeat(3);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // Washed out, tinted and with some channel crosstalk
+        let drifted = edit_frames(&encoded, |frames| {
+            for frame in frames {
+                for pixel in frame.pixels_mut() {
+                    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(f64::from);
+                    pixel[0] = (0.6 * r + 0.15 * g + 50.0) as u8;
+                    pixel[1] = (0.7 * g + 0.1 * b + 30.0) as u8;
+                    pixel[2] = (0.55 * b + 0.15 * r + 70.0) as u8;
+                }
+            }
+        });
+
+        // Against the nominal palette, blocks get misread
+        let frames = frames(&drifted);
+        let nominal = points(&encoder.palette().unwrap(), &ColorSpace::RGB);
+        let misread = encoder.read_symbols(&frames[0], &nominal).unwrap();
+        let measured = encoder.measure_palette(&frames[0]).unwrap();
+        let read = encoder.read_symbols(&frames[0], &measured).unwrap();
+        assert_ne!(misread, read);
+
+        assert_eq!(encoder.decode(&drifted).await.unwrap(), data);
     }
 }
@@ -3,49 +3,72 @@
 //! For platforms that store data without re-encoding (like local storage or R2),
 //! we can just use compression without converting to video.
 
+use crate::container::{self, ContainerHeader, PayloadReader};
 use async_trait::async_trait;
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use isg_core::{
-    CompressionCodec, EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result,
+    ByteReader, ByteWriter, CompressionCodec, EncodedData, Encoder, EncodingMetadata,
+    EncodingStrategy, Error, Result,
 };
 use std::io::{Read, Write};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::debug;
 
 /// Compression-based encoder
 #[derive(Clone, Debug)]
 pub struct CompressionEncoder {
     codec: CompressionCodec,
+    strategy: EncodingStrategy,
 }
 
 impl CompressionEncoder {
-    /// Create with zstd compression
-    pub fn zstd(level: i32) -> Self {
+    /// Create with a specific codec
+    pub fn new(codec: CompressionCodec) -> Self {
         Self {
-            codec: CompressionCodec::Zstd { level },
+            strategy: EncodingStrategy::RawCompressed {
+                codec: codec.clone(),
+            },
+            codec,
         }
     }
 
+    /// Create with zstd compression
+    pub fn zstd(level: i32) -> Self {
+        Self::new(CompressionCodec::Zstd { level })
+    }
+
     /// Create with gzip compression
     pub fn gzip(level: u32) -> Self {
-        Self {
-            codec: CompressionCodec::Gzip { level },
-        }
+        Self::new(CompressionCodec::Gzip { level })
+    }
+
+    /// Create with brotli compression
+    pub fn brotli(level: u32) -> Self {
+        Self::new(CompressionCodec::Brotli { level })
     }
 
     /// Create with no compression (passthrough)
     pub fn none() -> Self {
-        Self {
-            codec: CompressionCodec::None,
-        }
+        Self::new(CompressionCodec::None)
+    }
+
+    /// Parameters recorded alongside the strategy
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "codec": match &self.codec {
+                CompressionCodec::Zstd { level } => format!("zstd-{}", level),
+                CompressionCodec::Gzip { level } => format!("gzip-{}", level),
+                CompressionCodec::Brotli { level } => format!("brotli-{}", level),
+                CompressionCodec::None => "none".to_string(),
+            }
+        })
     }
 
     /// Compress data with the configured codec
     fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
         match &self.codec {
-            CompressionCodec::Zstd { level } => {
-                zstd::encode_all(data, *level)
-                    .map_err(|e| Error::Encoding(format!("Zstd compression failed: {}", e)))
-            }
+            CompressionCodec::Zstd { level } => zstd::encode_all(data, *level)
+                .map_err(|e| Error::Encoding(format!("Zstd compression failed: {}", e))),
 
             CompressionCodec::Gzip { level } => {
                 let mut encoder = GzEncoder::new(Vec::new(), Compression::new(*level));
@@ -71,9 +94,9 @@ impl CompressionEncoder {
         }
     }
 
-    /// Decompress data
-    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
-        match &self.codec {
+    /// Decompress data written with `codec`
+    fn decompress(codec: &CompressionCodec, data: &[u8]) -> Result<Vec<u8>> {
+        match codec {
             CompressionCodec::Zstd { .. } => zstd::decode_all(data)
                 .map_err(|e| Error::Decoding(format!("Zstd decompression failed: {}", e))),
 
@@ -100,6 +123,115 @@ impl CompressionEncoder {
     }
 }
 
+/// Incremental compressor; output accumulates in an in-memory buffer that is
+/// drained after every call
+enum StreamCompressor {
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    None,
+}
+
+impl StreamCompressor {
+    fn new(codec: &CompressionCodec) -> Result<Self> {
+        Ok(match codec {
+            CompressionCodec::Zstd { level } => Self::Zstd(
+                zstd::stream::write::Encoder::new(Vec::new(), *level)
+                    .map_err(|e| Error::Encoding(format!("Zstd compression failed: {}", e)))?,
+            ),
+            CompressionCodec::Gzip { level } => {
+                Self::Gzip(GzEncoder::new(Vec::new(), Compression::new(*level)))
+            }
+            CompressionCodec::Brotli { level } => Self::Brotli(Box::new(
+                brotli::CompressorWriter::new(Vec::new(), 4096, *level, 22),
+            )),
+            CompressionCodec::None => Self::None,
+        })
+    }
+
+    /// Compress `data`, returning whatever output is ready
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let result = match self {
+            Self::Zstd(encoder) => encoder
+                .write_all(data)
+                .map(|_| std::mem::take(encoder.get_mut())),
+            Self::Gzip(encoder) => encoder
+                .write_all(data)
+                .map(|_| std::mem::take(encoder.get_mut())),
+            Self::Brotli(encoder) => encoder
+                .write_all(data)
+                .map(|_| std::mem::take(encoder.get_mut())),
+            Self::None => Ok(data.to_vec()),
+        };
+        result.map_err(|e| Error::Encoding(format!("Compression failed: {}", e)))
+    }
+
+    /// Flush the remaining output
+    fn finish(self) -> Result<Vec<u8>> {
+        let result = match self {
+            Self::Zstd(encoder) => encoder.finish(),
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Brotli(encoder) => Ok(encoder.into_inner()),
+            Self::None => Ok(Vec::new()),
+        };
+        result.map_err(|e| Error::Encoding(format!("Compression failed: {}", e)))
+    }
+}
+
+/// Incremental decompressor, mirroring [`StreamCompressor`]
+enum StreamDecompressor {
+    Zstd(zstd::stream::write::Decoder<'static, Vec<u8>>),
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    Brotli(Box<brotli::DecompressorWriter<Vec<u8>>>),
+    None,
+}
+
+impl StreamDecompressor {
+    fn new(codec: &CompressionCodec) -> Result<Self> {
+        Ok(match codec {
+            CompressionCodec::Zstd { .. } => Self::Zstd(
+                zstd::stream::write::Decoder::new(Vec::new())
+                    .map_err(|e| Error::Decoding(format!("Zstd decompression failed: {}", e)))?,
+            ),
+            CompressionCodec::Gzip { .. } => Self::Gzip(flate2::write::GzDecoder::new(Vec::new())),
+            CompressionCodec::Brotli { .. } => {
+                Self::Brotli(Box::new(brotli::DecompressorWriter::new(Vec::new(), 4096)))
+            }
+            CompressionCodec::None => Self::None,
+        })
+    }
+
+    /// Decompress `data`, returning whatever output is ready
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let result = match self {
+            Self::Zstd(decoder) => decoder
+                .write_all(data)
+                .map(|_| std::mem::take(decoder.get_mut())),
+            Self::Gzip(decoder) => decoder
+                .write_all(data)
+                .map(|_| std::mem::take(decoder.get_mut())),
+            Self::Brotli(decoder) => decoder
+                .write_all(data)
+                .map(|_| std::mem::take(decoder.get_mut())),
+            Self::None => Ok(data.to_vec()),
+        };
+        result.map_err(|e| Error::Decoding(format!("Decompression failed: {}", e)))
+    }
+
+    /// Flush the remaining output, failing on a truncated stream
+    fn finish(self) -> Result<Vec<u8>> {
+        let result = match self {
+            Self::Zstd(mut decoder) => decoder.flush().map(|_| std::mem::take(decoder.get_mut())),
+            Self::Gzip(decoder) => decoder.finish(),
+            Self::Brotli(decoder) => decoder.into_inner().map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated stream")
+            }),
+            Self::None => Ok(Vec::new()),
+        };
+        result.map_err(|e| Error::Decoding(format!("Decompression failed: {}", e)))
+    }
+}
+
 impl Default for CompressionEncoder {
     fn default() -> Self {
         // Zstd level 3 is a good balance of speed and compression
@@ -110,27 +242,18 @@ impl Default for CompressionEncoder {
 #[async_trait]
 impl Encoder for CompressionEncoder {
     async fn encode(&self, data: &[u8]) -> Result<EncodedData> {
-        debug!(
-            "Compressing {} bytes with {:?}",
-            data.len(),
-            self.codec
-        );
+        debug!("Compressing {} bytes with {:?}", data.len(), self.codec);
 
-        let compressed = self.compress(data)?;
+        let parameters = self.parameters();
+        let header = ContainerHeader::new(self.strategy.clone(), data, parameters.clone());
+        let compressed = header.wrap(&self.compress(data)?)?;
 
         let metadata = EncodingMetadata {
             original_size: data.len(),
             encoded_size: compressed.len(),
             compression_ratio: compressed.len() as f64 / data.len() as f64,
             strategy: "compression".to_string(),
-            parameters: serde_json::json!({
-                "codec": match &self.codec {
-                    CompressionCodec::Zstd { level } => format!("zstd-{}", level),
-                    CompressionCodec::Gzip { level } => format!("gzip-{}", level),
-                    CompressionCodec::Brotli { level } => format!("brotli-{}", level),
-                    CompressionCodec::None => "none".to_string(),
-                }
-            }),
+            parameters,
         };
 
         debug!(
@@ -148,24 +271,126 @@ impl Encoder for CompressionEncoder {
 
     async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
         debug!("Decompressing {} bytes", encoded.data.len());
-        self.decompress(&encoded.data)
+
+        if !ContainerHeader::is_container(&encoded.data) {
+            // Blobs written before the container header are bare codec output
+            return Self::decompress(&self.codec, &encoded.data);
+        }
+
+        let (header, payload) = ContainerHeader::parse(&encoded.data)?;
+        let codec = match &header.strategy {
+            EncodingStrategy::RawCompressed { codec } => codec,
+            other => {
+                return Err(Error::Decoding(format!(
+                    "Expected raw compression, found {:?}",
+                    other
+                )))
+            }
+        };
+
+        let decompressed = Self::decompress(codec, payload)?;
+        header.verify(&decompressed)?;
+        Ok(decompressed)
+    }
+
+    async fn encode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<EncodingMetadata> {
+        let header = ContainerHeader::streaming(self.strategy.clone(), self.parameters());
+        let prefix = header.to_bytes()?;
+        output.write_all(&prefix).await?;
+
+        let mut encoded_size = prefix.len();
+        let mut crc = crc32fast::Hasher::new();
+        let mut original_size = 0u64;
+        let mut compressor = StreamCompressor::new(&self.codec)?;
+        let mut chunk = vec![0u8; container::STREAM_CHUNK];
+
+        loop {
+            let n = input.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+
+            crc.update(&chunk[..n]);
+            original_size += n as u64;
+            let compressed = compressor.update(&chunk[..n])?;
+            output.write_all(&compressed).await?;
+            encoded_size += compressed.len();
+        }
+
+        let rest = compressor.finish()?;
+        output.write_all(&rest).await?;
+        output
+            .write_all(&ContainerHeader::trailer(original_size, crc.finalize()))
+            .await?;
+        output.flush().await?;
+        encoded_size += rest.len() + container::TRAILER_LEN;
+
+        Ok(EncodingMetadata {
+            original_size: original_size as usize,
+            encoded_size,
+            compression_ratio: encoded_size as f64 / original_size as f64,
+            strategy: "compression".to_string(),
+            parameters: self.parameters(),
+        })
+    }
+
+    async fn decode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<u64> {
+        let mut header = ContainerHeader::read_from(input).await?;
+        let mut decompressor = match &header.strategy {
+            EncodingStrategy::RawCompressed { codec } => StreamDecompressor::new(codec)?,
+            other => {
+                return Err(Error::Decoding(format!(
+                    "Expected raw compression, found {:?}",
+                    other
+                )))
+            }
+        };
+
+        let mut payload = PayloadReader::new(input, &header);
+        let mut crc = crc32fast::Hasher::new();
+        let mut written = 0u64;
+
+        loop {
+            let chunk = payload.read_up_to(container::STREAM_CHUNK).await?;
+            let last = chunk.is_empty();
+            let decompressed = if last {
+                std::mem::replace(&mut decompressor, StreamDecompressor::None).finish()?
+            } else {
+                decompressor.update(chunk)?
+            };
+
+            crc.update(&decompressed);
+            written += decompressed.len() as u64;
+            output.write_all(&decompressed).await?;
+
+            if last {
+                break;
+            }
+        }
+
+        output.flush().await?;
+        payload.finish(&mut header).await?;
+        header.verify_summary(written, crc.finalize())?;
+        Ok(written)
     }
 
     fn strategy(&self) -> &EncodingStrategy {
-        static STRATEGY: once_cell::sync::Lazy<EncodingStrategy> =
-            once_cell::sync::Lazy::new(|| {
-                EncodingStrategy::RawCompressed {
-                    codec: CompressionCodec::Zstd { level: 3 },
-                }
-            });
-        &STRATEGY
+        &self.strategy
     }
 
     fn estimate_size(&self, input_size: usize) -> usize {
         // Rough estimates based on typical compression ratios
         match &self.codec {
-            CompressionCodec::Zstd { .. } => input_size / 3,  // ~3:1 ratio
-            CompressionCodec::Gzip { .. } => input_size / 2,  // ~2:1 ratio
+            CompressionCodec::Zstd { .. } => input_size / 3, // ~3:1 ratio
+            CompressionCodec::Gzip { .. } => input_size / 2, // ~2:1 ratio
             CompressionCodec::Brotli { .. } => input_size / 3, // ~3:1 ratio
             CompressionCodec::None => input_size,
         }
@@ -210,9 +435,78 @@ mod tests {
         let data = b"No compression applied";
 
         let encoded = encoder.encode(data).await.unwrap();
-        assert_eq!(encoded.data.len(), data.len());
+        assert!(encoded.data.ends_with(data));
 
         let decoded = encoder.decode(&encoded).await.unwrap();
         assert_eq!(data.as_slice(), decoded.as_slice());
+
+        // Bare data from before the container header may start with its magic
+        let legacy = EncodedData::from_bytes(b"ISGv2 release notes".to_vec());
+        assert_eq!(encoder.decode(&legacy).await.unwrap(), legacy.data);
+    }
+
+    #[tokio::test]
+    async fn test_stream_roundtrip() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 97) as u8).collect();
+
+        for encoder in [
+            CompressionEncoder::zstd(3),
+            CompressionEncoder::gzip(6),
+            CompressionEncoder::brotli(5),
+            CompressionEncoder::none(),
+        ] {
+            let mut streamed = Vec::new();
+            let metadata = encoder
+                .encode_stream(&mut &data[..], &mut streamed)
+                .await
+                .unwrap();
+            assert_eq!(metadata.encoded_size, streamed.len());
+
+            // Buffered decoding understands the streamed container
+            let buffered = EncodedData::from_bytes(streamed.clone());
+            assert_eq!(encoder.decode(&buffered).await.unwrap(), data);
+
+            let mut decoded = Vec::new();
+            CompressionEncoder::default()
+                .decode_stream(&mut &streamed[..], &mut decoded)
+                .await
+                .unwrap();
+            assert_eq!(decoded, data, "{:?}", encoder.codec);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_detects_truncation() {
+        let data = b"truncated stream ".repeat(100);
+        let encoded = CompressionEncoder::zstd(3).encode(&data).await.unwrap();
+        let truncated = &encoded.data[..encoded.data.len() - 10];
+
+        let mut decoded = Vec::new();
+        assert!(CompressionEncoder::default()
+            .decode_stream(&mut &truncated[..], &mut decoded)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_uses_header_codec() {
+        let data = b"Encoded with gzip, decoded by a zstd encoder".repeat(10);
+        let encoded = CompressionEncoder::gzip(6).encode(&data).await.unwrap();
+
+        let decoded = CompressionEncoder::zstd(3).decode(&encoded).await.unwrap();
+        assert_eq!(data.as_slice(), decoded.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_checksum_detects_corruption() {
+        let encoder = CompressionEncoder::none();
+        let mut encoded = encoder.encode(b"checksummed payload").await.unwrap();
+
+        let last = encoded.data.len() - 1;
+        encoded.data[last] ^= 1;
+        assert!(matches!(
+            encoder.decode(&encoded).await,
+            Err(Error::Corruption(_))
+        ));
     }
 }
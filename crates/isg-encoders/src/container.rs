@@ -0,0 +1,638 @@
+//! Self-describing ISG container header
+//!
+//! Every encoder prefixes its output with this header so a blob can be
+//! decoded without knowing the configuration that produced it.
+//!
+//! Layout (little-endian):
+//!
+//! | Field           | Size | Notes                                   |
+//! |-----------------|------|-----------------------------------------|
+//! | magic           | 4    | `b"ISGv"`                               |
+//! | format version  | 1    | currently 2 ("ISGv2")                   |
+//! | flags           | 1    | bit 0: size and checksum in trailer     |
+//! | original size   | 8    | length of the data before encoding      |
+//! | checksum        | 4    | CRC-32 of the data before encoding      |
+//! | descriptor size | 4    | length of the JSON descriptor           |
+//! | descriptor      | n    | `{"strategy": ..., "parameters": ...}`  |
+//!
+//! The encoder-specific payload follows the descriptor. Streaming encoders
+//! don't know the size and checksum up front: they set the trailer flag,
+//! write zeros in the header and append the real values (size then
+//! checksum, 12 bytes) after the payload.
+
+use image::RgbaImage;
+use isg_core::{ByteReader, ByteWriter, EncodingStrategy, Error, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Magic bytes at the start of every container
+pub const MAGIC: [u8; 4] = *b"ISGv";
+
+/// Current container format version
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Size of the fixed part of the header (before the descriptor)
+const FIXED_LEN: usize = 4 + 1 + 1 + 8 + 4 + 4;
+
+/// Upper bound on the descriptor size, to reject garbage early
+const MAX_DESCRIPTOR_LEN: usize = 1024 * 1024;
+
+/// Typical header size, for size estimates
+pub(crate) const HEADER_ESTIMATE: usize = FIXED_LEN + 128;
+
+/// Flag: original size and checksum follow the payload
+const FLAG_TRAILER: u8 = 0x01;
+
+/// Size of the trailer written by streaming encoders
+pub const TRAILER_LEN: usize = 8 + 4;
+
+/// Frame count of a PNG sequence whose length was unknown when written
+pub(crate) const UNKNOWN_FRAME_COUNT: u32 = u32::MAX;
+
+/// Read size for streaming input
+pub(crate) const STREAM_CHUNK: usize = 64 * 1024;
+
+/// Largest frame side accepted, from settings or a header
+pub(crate) const MAX_FRAME_SIDE: u32 = 8192;
+
+/// Parsed container header
+#[derive(Clone, Debug)]
+pub struct ContainerHeader {
+    /// Container format version
+    pub version: u8,
+
+    /// Strategy (with its parameters) used to encode the payload
+    pub strategy: EncodingStrategy,
+
+    /// Encoder-specific parameters not covered by the strategy
+    pub parameters: serde_json::Value,
+
+    /// Length of the original data
+    pub original_size: u64,
+
+    /// CRC-32 of the original data
+    pub checksum: u32,
+
+    /// Size and checksum are stored in a trailer after the payload
+    pub streamed: bool,
+}
+
+/// Fixed-size part of the header, read before the descriptor
+struct FixedFields {
+    version: u8,
+    original_size: u64,
+    checksum: u32,
+    streamed: bool,
+    descriptor_len: usize,
+}
+
+/// JSON part of the header
+#[derive(Serialize, Deserialize)]
+struct Descriptor {
+    strategy: EncodingStrategy,
+    #[serde(default)]
+    parameters: serde_json::Value,
+}
+
+impl ContainerHeader {
+    /// Describe `data` encoded with `strategy`
+    pub fn new(strategy: EncodingStrategy, data: &[u8], parameters: serde_json::Value) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            strategy,
+            parameters,
+            original_size: data.len() as u64,
+            checksum: crc32fast::hash(data),
+            streamed: false,
+        }
+    }
+
+    /// Describe a stream whose size and checksum go in the trailer
+    pub fn streaming(strategy: EncodingStrategy, parameters: serde_json::Value) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            strategy,
+            parameters,
+            original_size: 0,
+            checksum: 0,
+            streamed: true,
+        }
+    }
+
+    /// Check whether `data` is a container: a well-formed header (magic,
+    /// version, flags and descriptor) rather than just the magic bytes, which
+    /// raw data may happen to start with
+    pub fn is_container(data: &[u8]) -> bool {
+        Self::parse(data).is_ok()
+    }
+
+    /// Serialize the header alone
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let descriptor = serde_json::to_vec(&Descriptor {
+            strategy: self.strategy.clone(),
+            parameters: self.parameters.clone(),
+        })
+        .map_err(|e| Error::Encoding(format!("Header serialization failed: {}", e)))?;
+
+        let mut out = Vec::with_capacity(FIXED_LEN + descriptor.len());
+        out.extend_from_slice(&MAGIC);
+        out.push(self.version);
+        out.push(if self.streamed { FLAG_TRAILER } else { 0 });
+        out.extend_from_slice(&self.original_size.to_le_bytes());
+        out.extend_from_slice(&self.checksum.to_le_bytes());
+        out.extend_from_slice(&(descriptor.len() as u32).to_le_bytes());
+        out.extend_from_slice(&descriptor);
+        Ok(out)
+    }
+
+    /// Serialize the header followed by `payload`
+    pub fn wrap(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut out = self.to_bytes()?;
+        out.extend_from_slice(payload);
+        if self.streamed {
+            out.extend_from_slice(&Self::trailer(self.original_size, self.checksum));
+        }
+        Ok(out)
+    }
+
+    /// Trailer closing a streamed container
+    pub fn trailer(original_size: u64, checksum: u32) -> [u8; TRAILER_LEN] {
+        let mut trailer = [0u8; TRAILER_LEN];
+        trailer[..8].copy_from_slice(&original_size.to_le_bytes());
+        trailer[8..].copy_from_slice(&checksum.to_le_bytes());
+        trailer
+    }
+
+    /// Parse a header, returning it along with the payload that follows
+    pub fn parse(data: &[u8]) -> Result<(Self, &[u8])> {
+        if data.len() < FIXED_LEN || !data.starts_with(&MAGIC) {
+            return Err(Error::Decoding("Missing ISG container header".to_string()));
+        }
+
+        let fixed = Self::parse_fixed(&data[..FIXED_LEN])?;
+        let descriptor_end = FIXED_LEN + fixed.descriptor_len;
+        if descriptor_end > data.len() {
+            return Err(Error::Decoding("Container header truncated".to_string()));
+        }
+        let mut header = Self::from_parts(fixed, &data[FIXED_LEN..descriptor_end])?;
+
+        let mut payload = &data[descriptor_end..];
+        if header.streamed {
+            if payload.len() < TRAILER_LEN {
+                return Err(Error::Decoding("Container trailer missing".to_string()));
+            }
+            let (body, trailer) = payload.split_at(payload.len() - TRAILER_LEN);
+            header.read_trailer(trailer);
+            payload = body;
+        }
+
+        Ok((header, payload))
+    }
+
+    /// Read a header from the start of a stream
+    ///
+    /// For streamed containers the size and checksum are only known once the
+    /// payload has been consumed (see [`PayloadReader::finish`]).
+    pub async fn read_from(input: &mut ByteReader<'_>) -> Result<Self> {
+        let mut fixed = [0u8; FIXED_LEN];
+        input
+            .read_exact(&mut fixed)
+            .await
+            .map_err(|_| Error::Decoding("Missing ISG container header".to_string()))?;
+        if !fixed.starts_with(&MAGIC) {
+            return Err(Error::Decoding("Missing ISG container header".to_string()));
+        }
+
+        let fixed = Self::parse_fixed(&fixed)?;
+        let mut descriptor = vec![0u8; fixed.descriptor_len];
+        input
+            .read_exact(&mut descriptor)
+            .await
+            .map_err(|_| Error::Decoding("Container header truncated".to_string()))?;
+
+        Self::from_parts(fixed, &descriptor)
+    }
+
+    /// Parse the fixed-size part of a header
+    fn parse_fixed(fixed: &[u8]) -> Result<FixedFields> {
+        let version = fixed[4];
+        if version != FORMAT_VERSION {
+            return Err(Error::Decoding(format!(
+                "Unsupported container version: {}",
+                version
+            )));
+        }
+
+        let flags = fixed[5];
+        if flags & !FLAG_TRAILER != 0 {
+            return Err(Error::Decoding(format!(
+                "Unknown container flags: {:#04x}",
+                flags
+            )));
+        }
+
+        let descriptor_len = u32::from_le_bytes(fixed[18..22].try_into().unwrap()) as usize;
+        if descriptor_len > MAX_DESCRIPTOR_LEN {
+            return Err(Error::Decoding("Container header truncated".to_string()));
+        }
+
+        Ok(FixedFields {
+            version,
+            original_size: u64::from_le_bytes(fixed[6..14].try_into().unwrap()),
+            checksum: u32::from_le_bytes(fixed[14..18].try_into().unwrap()),
+            streamed: flags & FLAG_TRAILER != 0,
+            descriptor_len,
+        })
+    }
+
+    /// Build a header from its fixed-size part and JSON descriptor
+    fn from_parts(fixed: FixedFields, descriptor: &[u8]) -> Result<Self> {
+        let descriptor: Descriptor = serde_json::from_slice(descriptor)
+            .map_err(|e| Error::Decoding(format!("Invalid container header: {}", e)))?;
+
+        Ok(Self {
+            version: fixed.version,
+            strategy: descriptor.strategy,
+            parameters: descriptor.parameters,
+            original_size: fixed.original_size,
+            checksum: fixed.checksum,
+            streamed: fixed.streamed,
+        })
+    }
+
+    /// Fill in size and checksum from a trailer
+    fn read_trailer(&mut self, trailer: &[u8]) {
+        self.original_size = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        self.checksum = u32::from_le_bytes(trailer[8..].try_into().unwrap());
+    }
+
+    /// Check decoded data against the recorded size and checksum
+    pub fn verify(&self, decoded: &[u8]) -> Result<()> {
+        self.verify_summary(decoded.len() as u64, crc32fast::hash(decoded))
+    }
+
+    /// Check the size and CRC-32 of incrementally decoded data
+    pub fn verify_summary(&self, size: u64, checksum: u32) -> Result<()> {
+        if size != self.original_size {
+            return Err(Error::Corruption(format!(
+                "Decoded {} bytes, expected {}",
+                size, self.original_size
+            )));
+        }
+
+        if checksum != self.checksum {
+            return Err(Error::Corruption("Checksum mismatch".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a container payload from a stream, holding back the trailer
+pub struct PayloadReader<'a, 'r> {
+    input: &'a mut ByteReader<'r>,
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    reserve: usize,
+}
+
+impl<'a, 'r> PayloadReader<'a, 'r> {
+    /// Read the payload following `header` (already consumed from `input`)
+    pub fn new(input: &'a mut ByteReader<'r>, header: &ContainerHeader) -> Self {
+        Self {
+            input,
+            buf: Vec::with_capacity(STREAM_CHUNK),
+            pos: 0,
+            eof: false,
+            reserve: if header.streamed { TRAILER_LEN } else { 0 },
+        }
+    }
+
+    /// Payload bytes buffered and known not to belong to the trailer
+    fn available(&self) -> usize {
+        (self.buf.len() - self.pos).saturating_sub(self.reserve)
+    }
+
+    /// Buffer until `want` payload bytes are available or input ends
+    async fn fill(&mut self, want: usize) -> Result<()> {
+        while !self.eof && self.available() < want {
+            if self.pos > 0 {
+                self.buf.drain(..self.pos);
+                self.pos = 0;
+            }
+
+            self.buf.reserve(STREAM_CHUNK);
+            if self.input.read_buf(&mut self.buf).await? == 0 {
+                self.eof = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read exactly `len` payload bytes
+    pub async fn read_exact(&mut self, len: usize) -> Result<&[u8]> {
+        self.fill(len).await?;
+        if self.available() < len {
+            return Err(Error::Decoding("Unexpected end of data".to_string()));
+        }
+
+        let start = self.pos;
+        self.pos += len;
+        Ok(&self.buf[start..self.pos])
+    }
+
+    /// Read up to `max` payload bytes (empty once the payload is exhausted)
+    pub async fn read_up_to(&mut self, max: usize) -> Result<&[u8]> {
+        self.fill(max).await?;
+        let len = self.available().min(max);
+
+        let start = self.pos;
+        self.pos += len;
+        Ok(&self.buf[start..self.pos])
+    }
+
+    /// Check whether the payload is exhausted
+    pub async fn at_end(&mut self) -> Result<bool> {
+        self.fill(1).await?;
+        Ok(self.available() == 0)
+    }
+
+    /// Check the payload was fully consumed and apply the trailer, if any
+    pub async fn finish(mut self, header: &mut ContainerHeader) -> Result<()> {
+        if !self.at_end().await? {
+            return Err(Error::Decoding("Unexpected data after payload".to_string()));
+        }
+
+        if header.streamed {
+            if self.buf.len() - self.pos != TRAILER_LEN {
+                return Err(Error::Decoding("Container trailer missing".to_string()));
+            }
+            header.read_trailer(&self.buf[self.pos..]);
+        }
+
+        Ok(())
+    }
+}
+
+/// Check a frame resolution and block size before allocating for them
+pub(crate) fn check_frame_size(resolution: (u32, u32), block_size: u32) -> Result<()> {
+    let (width, height) = resolution;
+    if width == 0 || height == 0 || width > MAX_FRAME_SIDE || height > MAX_FRAME_SIDE {
+        return Err(Error::Config(format!(
+            "Resolution must be 1 to {} pixels on each side, got {}x{}",
+            MAX_FRAME_SIDE, width, height
+        )));
+    }
+
+    if block_size == 0 || block_size > width.min(height) {
+        return Err(Error::Config(format!(
+            "Block size must be 1 to {} pixels at {}x{}, got {}",
+            width.min(height),
+            width,
+            height,
+            block_size
+        )));
+    }
+
+    Ok(())
+}
+
+/// Integer parameter `name` recorded in `header`, if present, failing if it
+/// doesn't fit in `T`
+pub(crate) fn parameter<T: TryFrom<u64>>(
+    header: &ContainerHeader,
+    name: &str,
+) -> Result<Option<T>> {
+    let Some(value) = header.parameters[name].as_u64() else {
+        return Ok(None);
+    };
+    T::try_from(value).map(Some).map_err(|_| {
+        Error::Corruption(format!(
+            "Container parameter {} out of range: {}",
+            name, value
+        ))
+    })
+}
+
+/// Turn a configuration error about settings read from a header into
+/// corruption: the header is wrong, not the caller's configuration
+pub(crate) fn corrupt_settings(error: Error) -> Error {
+    match error {
+        Error::Config(message) => Error::Corruption(format!("Container header: {}", message)),
+        other => other,
+    }
+}
+
+/// Encode one frame as PNG
+pub(crate) fn png_bytes(frame: &RgbaImage) -> Result<Vec<u8>> {
+    let mut png_data = Vec::new();
+    frame
+        .write_to(
+            &mut std::io::Cursor::new(&mut png_data),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(|e| Error::Encoding(format!("PNG encoding failed: {}", e)))?;
+    Ok(png_data)
+}
+
+/// Decode one PNG frame
+fn load_png(data: &[u8]) -> Result<RgbaImage> {
+    Ok(
+        image::load_from_memory_with_format(data, image::ImageFormat::Png)
+            .map_err(|e| Error::Decoding(format!("PNG decoding failed: {}", e)))?
+            .to_rgba8(),
+    )
+}
+
+/// Serialize frames as a PNG sequence payload (count, then size-prefixed PNGs)
+pub(crate) fn write_png_sequence(frames: &[RgbaImage]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(frames.len() as u32).to_le_bytes());
+
+    for frame in frames {
+        let png_data = png_bytes(frame)?;
+        out.extend_from_slice(&(png_data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&png_data);
+    }
+
+    Ok(out)
+}
+
+/// Parse a payload written by [`write_png_sequence`] or [`write_frame`]
+pub(crate) fn read_png_sequence(data: &[u8]) -> Result<Vec<RgbaImage>> {
+    if data.len() < 4 {
+        return Err(Error::Decoding("Data too short".to_string()));
+    }
+
+    let frame_count = u32::from_le_bytes(data[..4].try_into().unwrap());
+    read_png_frames(&data[4..], frame_count)
+}
+
+/// Read `count` size-prefixed PNG frames ([`UNKNOWN_FRAME_COUNT`] reads them all)
+pub(crate) fn read_png_frames(data: &[u8], count: u32) -> Result<Vec<RgbaImage>> {
+    let mut frames = Vec::new();
+    let mut cursor = 0;
+
+    while frames.len() < count as usize {
+        if count == UNKNOWN_FRAME_COUNT && cursor == data.len() {
+            break;
+        }
+
+        if cursor + 4 > data.len() {
+            return Err(Error::Decoding("Unexpected end of data".to_string()));
+        }
+
+        let frame_size = u32::from_le_bytes(data[cursor..cursor + 4].try_into().unwrap()) as usize;
+        cursor += 4;
+
+        if cursor + frame_size > data.len() {
+            return Err(Error::Decoding("Frame data truncated".to_string()));
+        }
+
+        frames.push(load_png(&data[cursor..cursor + frame_size])?);
+        cursor += frame_size;
+    }
+
+    Ok(frames)
+}
+
+/// Write one size-prefixed PNG frame to a stream, returning the bytes written
+pub(crate) async fn write_frame(output: &mut ByteWriter<'_>, frame: &RgbaImage) -> Result<usize> {
+    let png_data = png_bytes(frame)?;
+    output
+        .write_all(&(png_data.len() as u32).to_le_bytes())
+        .await?;
+    output.write_all(&png_data).await?;
+    Ok(4 + png_data.len())
+}
+
+/// Read the next size-prefixed PNG frame, or `None` at the end of the payload
+pub(crate) async fn read_frame(payload: &mut PayloadReader<'_, '_>) -> Result<Option<RgbaImage>> {
+    if payload.at_end().await? {
+        return Ok(None);
+    }
+
+    let frame_size = u32::from_le_bytes(payload.read_exact(4).await?.try_into().unwrap()) as usize;
+    let frame = load_png(payload.read_exact(frame_size).await?)?;
+    Ok(Some(frame))
+}
+
+/// Fixtures shared by the encoder tests
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use isg_core::EncodedData;
+
+    /// `len` bytes counting up in steps of `step` (mod 256)
+    pub(crate) fn test_data(len: usize, step: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * step % 256) as u8).collect()
+    }
+
+    /// Frames of a PNG-sequence container
+    pub(crate) fn frames(encoded: &EncodedData) -> Vec<RgbaImage> {
+        let (_, payload) = ContainerHeader::parse(&encoded.data).unwrap();
+        read_png_sequence(payload).unwrap()
+    }
+
+    /// PNG-sequence container with `edit` applied to its frames
+    pub(crate) fn edit_frames(
+        encoded: &EncodedData,
+        edit: impl FnOnce(&mut Vec<RgbaImage>),
+    ) -> EncodedData {
+        let (header, payload) = ContainerHeader::parse(&encoded.data).unwrap();
+        let mut frames = read_png_sequence(payload).unwrap();
+        edit(&mut frames);
+        let payload = write_png_sequence(&frames).unwrap();
+        EncodedData::from_bytes(header.wrap(&payload).unwrap())
+    }
+
+    /// Container with `edit` applied to its header
+    pub(crate) fn edit_header(
+        encoded: &EncodedData,
+        edit: impl FnOnce(&mut ContainerHeader),
+    ) -> EncodedData {
+        let (mut header, payload) = ContainerHeader::parse(&encoded.data).unwrap();
+        edit(&mut header);
+        EncodedData::from_bytes(header.wrap(payload).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isg_core::CompressionCodec;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let data = b"payload source";
+        let strategy = EncodingStrategy::RawCompressed {
+            codec: CompressionCodec::Gzip { level: 6 },
+        };
+        let header = ContainerHeader::new(strategy, data, serde_json::json!({"k": 1}));
+        let wrapped = header.wrap(b"PAYLOAD").unwrap();
+
+        assert!(ContainerHeader::is_container(&wrapped));
+        let (parsed, payload) = ContainerHeader::parse(&wrapped).unwrap();
+        assert_eq!(payload, b"PAYLOAD");
+        assert_eq!(parsed.original_size, data.len() as u64);
+        assert_eq!(parsed.parameters["k"], 1);
+        assert!(matches!(
+            parsed.strategy,
+            EncodingStrategy::RawCompressed {
+                codec: CompressionCodec::Gzip { level: 6 }
+            }
+        ));
+
+        assert!(parsed.verify(data).is_ok());
+        assert!(parsed.verify(b"payload sourcf").is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_headers() {
+        assert!(ContainerHeader::parse(b"not a container at all").is_err());
+
+        let header =
+            ContainerHeader::new(EncodingStrategy::DNAEncoding, b"", serde_json::Value::Null);
+        let mut wrapped = header.wrap(b"").unwrap();
+        wrapped[4] = 9;
+        assert!(ContainerHeader::parse(&wrapped).is_err());
+
+        // Raw data that merely starts with the magic isn't a container
+        assert!(!ContainerHeader::is_container(
+            b"ISGv2 notes, not a header at all"
+        ));
+        let mut flagged = header.wrap(b"").unwrap();
+        flagged[5] = 0x80;
+        assert!(!ContainerHeader::is_container(&flagged));
+        assert!(ContainerHeader::is_container(&header.wrap(b"").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_trailer() {
+        let header =
+            ContainerHeader::streaming(EncodingStrategy::DNAEncoding, serde_json::Value::Null);
+        let mut blob = header.to_bytes().unwrap();
+        blob.extend_from_slice(b"streamed payload");
+        blob.extend_from_slice(&ContainerHeader::trailer(5, crc32fast::hash(b"hello")));
+
+        // Whole-blob parsing splits off the trailer
+        let (parsed, payload) = ContainerHeader::parse(&blob).unwrap();
+        assert_eq!(payload, b"streamed payload");
+        assert!(parsed.verify(b"hello").is_ok());
+
+        // Stream parsing holds it back
+        let mut input = &blob[..];
+        let mut read = ContainerHeader::read_from(&mut input).await.unwrap();
+        assert!(read.streamed);
+        assert!(read.verify(b"hello").is_err());
+
+        let mut payload = PayloadReader::new(&mut input, &read);
+        assert_eq!(payload.read_exact(8).await.unwrap(), b"streamed");
+        assert_eq!(payload.read_up_to(100).await.unwrap(), b" payload");
+        assert!(payload.at_end().await.unwrap());
+        payload.finish(&mut read).await.unwrap();
+        assert!(read.verify(b"hello").is_ok());
+        assert!(read.verify(b"hellO").is_err());
+    }
+}
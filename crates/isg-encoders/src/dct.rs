@@ -0,0 +1,348 @@
+//! DCT-domain embedding for [`crate::StegoEncoder`]
+//!
+//! Low bits don't survive JPEG: recompression requantizes every 8x8 block's
+//! DCT coefficients and rounds them away. [`StegoMethod::DCT`] instead hides
+//! one bit in each of a few mid-frequency luma coefficients per block, by
+//! quantization-index modulation (QIM): the coefficient is moved onto one of
+//! two interleaved lattices of step Δ, picked by the bit, and read back as
+//! whichever lattice it lies closest to.
+//!
+//! A JPEG encoder moves a coefficient by at most half its quantizer step, so
+//! a Δ comfortably above twice the step the standard luma table gives at the
+//! minimum quality keeps bits readable at that quality and any higher one.
+//! Blocks are aligned with JPEG's grid, and the remaining errors (clipped
+//! highlights and shadows, rounding) are scattered by the keyed bit order
+//! and corrected by interleaved Reed–Solomon codewords ([`FrameCode`]).
+//!
+//! Changing only luma means adding the same amount to red, green and blue,
+//! which leaves the colour of each pixel alone.
+//!
+//! [`StegoMethod::DCT`]: isg_core::StegoMethod::DCT
+
+use crate::ecc::FrameCode;
+use crate::stego::KeyedOrder;
+use image::RgbaImage;
+use isg_core::{Error, Result};
+use tracing::debug;
+
+/// Side of a JPEG block
+const BLOCK: usize = 8;
+
+/// Coefficients carrying a bit, as (horizontal, vertical) frequency
+const MID_FREQUENCIES: [(usize, usize); 5] = [(3, 0), (2, 1), (1, 2), (0, 3), (2, 2)];
+
+/// JPEG's standard luma quantization table (Annex K), row by row
+const LUMA_QUANTIZATION: [[u16; BLOCK]; BLOCK] = [
+    [16, 11, 10, 16, 24, 40, 51, 61],
+    [12, 12, 14, 19, 26, 58, 60, 55],
+    [14, 13, 16, 24, 40, 57, 69, 56],
+    [14, 17, 22, 29, 51, 87, 80, 62],
+    [18, 22, 37, 56, 68, 109, 103, 77],
+    [24, 35, 55, 64, 81, 104, 113, 92],
+    [49, 64, 78, 87, 103, 121, 120, 101],
+    [72, 92, 95, 98, 112, 100, 103, 99],
+];
+
+/// Lattice step as a multiple of the JPEG quantizer step
+const STRENGTH: f64 = 3.0;
+
+/// Embedding passes: each re-reads the rounded pixels and corrects drift
+const PASSES: usize = 3;
+
+/// Settings both sides must agree on
+#[derive(Clone, Copy, Debug)]
+pub struct DctParams {
+    /// Lowest JPEG quality the bits must survive (1-100)
+    pub min_quality: u8,
+
+    /// Reed–Solomon check bytes per codeword
+    pub parity: usize,
+}
+
+impl DctParams {
+    /// Lattice step for each of [`MID_FREQUENCIES`]
+    fn steps(&self) -> Result<[f64; MID_FREQUENCIES.len()]> {
+        if !(1..=100).contains(&self.min_quality) {
+            return Err(Error::Config(format!(
+                "Minimum JPEG quality must be 1 to 100, got {}",
+                self.min_quality
+            )));
+        }
+
+        // Quality scaling as in libjpeg
+        let quality = self.min_quality as u32;
+        let scale = if quality < 50 {
+            5000 / quality
+        } else {
+            200 - 2 * quality
+        };
+        Ok(MID_FREQUENCIES.map(|(u, v)| {
+            let step = ((LUMA_QUANTIZATION[v][u] as u32 * scale + 50) / 100).clamp(1, 255);
+            STRENGTH * step as f64
+        }))
+    }
+
+    /// Error correction over all the bits `frames` can carry
+    fn code(&self, frames: &[RgbaImage]) -> Result<FrameCode> {
+        let bytes = slot_count(frames) / 8;
+        if bytes == 0 {
+            return Err(Error::Config(
+                "Cover too small for DCT embedding".to_string(),
+            ));
+        }
+        FrameCode::new(bytes, self.parity)
+    }
+
+    /// Bytes `frames` hold after error correction
+    pub fn capacity(&self, frames: &[RgbaImage]) -> Result<usize> {
+        Ok(self.code(frames)?.data_len())
+    }
+}
+
+/// Whole blocks in a frame
+fn blocks(frame: &RgbaImage) -> (usize, usize) {
+    (
+        frame.width() as usize / BLOCK,
+        frame.height() as usize / BLOCK,
+    )
+}
+
+/// Coefficients carrying bits across all frames
+fn slot_count(frames: &[RgbaImage]) -> usize {
+    frames
+        .iter()
+        .map(|frame| {
+            let (columns, rows) = blocks(frame);
+            columns * rows * MID_FREQUENCIES.len()
+        })
+        .sum()
+}
+
+/// Orthonormal DCT-II basis: `basis[k][n]` is frequency k at sample n
+fn basis() -> [[f64; BLOCK]; BLOCK] {
+    let mut basis = [[0.0; BLOCK]; BLOCK];
+    for (k, row) in basis.iter_mut().enumerate() {
+        let scale = if k == 0 { (1.0 / 8.0f64).sqrt() } else { 0.5 };
+        for (n, value) in row.iter_mut().enumerate() {
+            *value = scale * ((2 * n + 1) as f64 * k as f64 * std::f64::consts::PI / 16.0).cos();
+        }
+    }
+    basis
+}
+
+/// Luma as JPEG computes it (BT.601 full range)
+fn luma(pixel: &image::Rgba<u8>) -> f64 {
+    0.299 * pixel[0] as f64 + 0.587 * pixel[1] as f64 + 0.114 * pixel[2] as f64
+}
+
+/// Reads and moves the carrying coefficients of each block
+struct Blocks<'a> {
+    basis: [[f64; BLOCK]; BLOCK],
+
+    /// Blocks before each frame
+    starts: Vec<usize>,
+
+    frames: &'a [RgbaImage],
+}
+
+impl<'a> Blocks<'a> {
+    fn new(frames: &'a [RgbaImage]) -> Self {
+        let starts = frames
+            .iter()
+            .scan(0, |start, frame| {
+                let first = *start;
+                let (columns, rows) = blocks(frame);
+                *start += columns * rows;
+                Some(first)
+            })
+            .collect();
+        Self {
+            basis: basis(),
+            starts,
+            frames,
+        }
+    }
+
+    /// Frame and top-left pixel of block `block`, numbered across frames
+    fn locate(&self, block: usize) -> (usize, u32, u32) {
+        let frame = self.starts.partition_point(|&start| start <= block) - 1;
+        let (columns, _) = blocks(&self.frames[frame]);
+        let offset = block - self.starts[frame];
+        (
+            frame,
+            (offset % columns * BLOCK) as u32,
+            (offset / columns * BLOCK) as u32,
+        )
+    }
+
+    /// Carrying coefficients of block `block`
+    fn coefficients(&self, block: usize) -> [f64; MID_FREQUENCIES.len()] {
+        let (frame, left, top) = self.locate(block);
+        let frame = &self.frames[frame];
+        let mut luma_block = [[0.0; BLOCK]; BLOCK];
+        for (y, row) in luma_block.iter_mut().enumerate() {
+            for (x, value) in row.iter_mut().enumerate() {
+                *value = luma(frame.get_pixel(left + x as u32, top + y as u32));
+            }
+        }
+
+        MID_FREQUENCIES.map(|(u, v)| {
+            let mut sum = 0.0;
+            for (y, row) in luma_block.iter().enumerate() {
+                for (x, &value) in row.iter().enumerate() {
+                    sum += self.basis[v][y] * self.basis[u][x] * value;
+                }
+            }
+            sum
+        })
+    }
+}
+
+/// Coefficient `value` moved onto the lattice of `bit` with step `step`
+fn quantize(value: f64, bit: bool, step: f64) -> f64 {
+    let offset = if bit { step / 2.0 } else { 0.0 };
+    ((value - offset) / step).round() * step + offset
+}
+
+/// Bit whose lattice `value` lies closest to
+fn dequantize(value: f64, step: f64) -> bool {
+    (2.0 * value / step).round().rem_euclid(2.0) == 1.0
+}
+
+/// Hide `stream` (at most [`DctParams::capacity`] bytes) in the luma of
+/// `frames`, in the order chosen by `key`
+pub fn embed(
+    frames: &mut [RgbaImage],
+    stream: &[u8],
+    params: &DctParams,
+    key: &[u8],
+) -> Result<()> {
+    let steps = params.steps()?;
+    let code = params.code(frames)?;
+    let encoded = code.encode(stream);
+
+    // Target bit for every slot, so each block is adjusted in one go
+    let mut targets = vec![false; slot_count(frames)];
+    let order = KeyedOrder::new(targets.len(), key);
+    let bits = encoded
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 == 1));
+    for (slot, bit) in order.zip(bits) {
+        targets[slot] = bit;
+    }
+
+    let block_count = targets.len() / MID_FREQUENCIES.len();
+    for _ in 0..PASSES {
+        let snapshot = frames.to_vec();
+        let reader = Blocks::new(&snapshot);
+        for block in 0..block_count {
+            let coefficients = reader.coefficients(block);
+            let (frame, left, top) = reader.locate(block);
+
+            let mut delta = [[0.0; BLOCK]; BLOCK];
+            for (i, &(u, v)) in MID_FREQUENCIES.iter().enumerate() {
+                let target = targets[block * MID_FREQUENCIES.len() + i];
+                let change = quantize(coefficients[i], target, steps[i]) - coefficients[i];
+                for (y, row) in delta.iter_mut().enumerate() {
+                    for (x, value) in row.iter_mut().enumerate() {
+                        *value += change * reader.basis[v][y] * reader.basis[u][x];
+                    }
+                }
+            }
+
+            for (y, row) in delta.iter().enumerate() {
+                for (x, &change) in row.iter().enumerate() {
+                    let pixel = frames[frame].get_pixel_mut(left + x as u32, top + y as u32);
+                    for sample in &mut pixel.0[..3] {
+                        *sample = (*sample as f64 + change).round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Bytes hidden in the luma of `frames` by [`embed`], error-corrected
+pub fn extract(frames: &[RgbaImage], params: &DctParams, key: &[u8]) -> Result<Vec<u8>> {
+    let steps = params.steps()?;
+    let code = params.code(frames)?;
+    let reader = Blocks::new(frames);
+    let block_count = slot_count(frames) / MID_FREQUENCIES.len();
+
+    let mut bits = vec![false; block_count * MID_FREQUENCIES.len()];
+    for block in 0..block_count {
+        for (i, coefficient) in reader.coefficients(block).into_iter().enumerate() {
+            bits[block * MID_FREQUENCIES.len() + i] = dequantize(coefficient, steps[i]);
+        }
+    }
+
+    let mut order = KeyedOrder::new(bits.len(), key);
+    let encoded: Vec<u8> = (0..bits.len() / 8)
+        .map(|_| {
+            (0..8).fold(0u8, |byte, _| {
+                let slot = order.next().expect("one slot per bit");
+                (byte << 1) | bits[slot] as u8
+            })
+        })
+        .collect();
+
+    let (data, corrected, failed) = code.decode(&encoded);
+    debug!(
+        "DCT extraction corrected {} bytes, {} codewords beyond repair",
+        corrected, failed
+    );
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basis_is_orthonormal() {
+        let basis = basis();
+        for a in 0..BLOCK {
+            for b in 0..BLOCK {
+                let dot: f64 = (0..BLOCK).map(|n| basis[a][n] * basis[b][n]).sum();
+                let expected = if a == b { 1.0 } else { 0.0 };
+                assert!((dot - expected).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_qim_tolerates_less_than_a_quarter_step() {
+        for value in [-37.2, -3.0, 0.0, 12.9, 150.4] {
+            for bit in [false, true] {
+                let embedded = quantize(value, bit, 20.0);
+                assert!((embedded - value).abs() <= 10.0);
+                for noise in [-4.9, 0.0, 4.9] {
+                    assert_eq!(dequantize(embedded + noise, 20.0), bit);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_steps_follow_quality() {
+        let steps = |min_quality| {
+            DctParams {
+                min_quality,
+                parity: 0,
+            }
+            .steps()
+            .unwrap()
+        };
+        // Quality 50 uses the table as is
+        assert_eq!(steps(50)[0], STRENGTH * 16.0);
+        assert!(steps(90)[0] < steps(75)[0]);
+        assert!(DctParams {
+            min_quality: 0,
+            parity: 0
+        }
+        .steps()
+        .is_err());
+    }
+}
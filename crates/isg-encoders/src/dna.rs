@@ -0,0 +1,770 @@
+//! DNA sequence encoding (experimental)
+//!
+//! Turns data into short DNA strands (oligos) for synthesis, written as
+//! FASTA, after Goldman et al. (2013).
+//!
+//! Bytes become base-3 digits (trits), 21 per 4-byte word, and trits become
+//! bases with a rotating code: each trit picks one of the three bases that
+//! differ from the previous one. No base ever repeats, so there are no
+//! homopolymer runs for synthesis or sequencing to stumble over.
+//!
+//! Each oligo is encoded on its own and reads, in trits,
+//!
+//! | Field   | Trits | Notes                                          |
+//! |---------|-------|------------------------------------------------|
+//! | kind    | 1     | header, data or parity                         |
+//! | index   | 13    | copy, data oligo or parity group number        |
+//! | payload | rest  | oligo length minus 26                          |
+//! | check   | 12    | CRC-32 of the fields above, mod 3^12           |
+//!
+//! Sequencing returns strands in no particular order, some of them damaged
+//! and some not at all, so oligos are addressed by their index and the
+//! check trits weed out misread ones. Every `parity_group` data oligos are
+//! followed by a parity oligo whose trits make each column sum to 0 mod 3,
+//! rebuilding one lost oligo of the group. The data length, checksum and
+//! group size travel in a header oligo written several times.
+//!
+//! A misread that slips past the check trits is outvoted: the header is the
+//! one most copies agree on, and each data oligo the payload most of its
+//! reads agree on, with the parity of its group casting a vote when the
+//! reads disagree.
+
+use async_trait::async_trait;
+use isg_core::{EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Bases, in the cyclic order the rotating code steps through
+const BASES: [u8; 4] = *b"ACGT";
+
+/// Base assumed before the first one of every oligo
+const START: u8 = b'A';
+
+/// Trits per 4-byte word (3^21 > 2^32)
+const WORD_TRITS: usize = 21;
+
+const KIND_TRITS: usize = 1;
+const INDEX_TRITS: usize = 13;
+const CHECK_TRITS: usize = 12;
+
+/// Trits of every oligo besides its payload
+const OVERHEAD: usize = KIND_TRITS + INDEX_TRITS + CHECK_TRITS;
+
+/// Header words: data length, data checksum, parity group size
+const HEADER_WORDS: usize = 3;
+
+/// Copies of the header oligo
+const HEADER_COPIES: u32 = 3;
+
+/// Default bases per oligo, within what synthesis handles cheaply
+const DEFAULT_OLIGO_LENGTH: usize = 120;
+
+/// Default data oligos per parity oligo
+const DEFAULT_PARITY_GROUP: usize = 4;
+
+/// Bases per FASTA sequence line
+const LINE_WIDTH: usize = 80;
+
+/// Missing oligo indices listed in an error
+const REPORTED_MISSING: usize = 8;
+
+/// What an oligo carries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Header = 0,
+    Data = 1,
+    Parity = 2,
+}
+
+impl Kind {
+    fn from_trit(trit: u8) -> Self {
+        match trit {
+            0 => Self::Header,
+            1 => Self::Data,
+            _ => Self::Parity,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Header => "header",
+            Self::Data => "data",
+            Self::Parity => "parity",
+        }
+    }
+}
+
+/// One strand, before the rotating code
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Oligo {
+    kind: Kind,
+    index: u32,
+
+    /// Payload trits
+    payload: Vec<u8>,
+}
+
+impl Oligo {
+    /// Trits of the oligo, check trits included
+    fn trits(&self) -> Vec<u8> {
+        let mut trits = vec![self.kind as u8];
+        trits.extend(digits(self.index as u64, INDEX_TRITS));
+        trits.extend_from_slice(&self.payload);
+        trits.extend(check(&trits));
+        trits
+    }
+
+    /// Oligo from its trits, if the check trits match
+    fn parse(trits: &[u8]) -> Option<Self> {
+        let body_len = trits.len().checked_sub(CHECK_TRITS)?;
+        if body_len < KIND_TRITS + INDEX_TRITS || trits[body_len..] != check(&trits[..body_len]) {
+            return None;
+        }
+
+        Some(Self {
+            kind: Kind::from_trit(trits[0]),
+            index: value(&trits[KIND_TRITS..KIND_TRITS + INDEX_TRITS]) as u32,
+            payload: trits[KIND_TRITS + INDEX_TRITS..body_len].to_vec(),
+        })
+    }
+}
+
+/// `count` base-3 digits of `value`, most significant first
+fn digits(value: u64, count: usize) -> impl Iterator<Item = u8> {
+    (0..count)
+        .rev()
+        .map(move |position| (value / 3u64.pow(position as u32) % 3) as u8)
+}
+
+/// Value of base-3 digits, most significant first
+fn value(trits: &[u8]) -> u64 {
+    trits.iter().fold(0u64, |acc, &trit| acc * 3 + trit as u64)
+}
+
+/// Check trits for the leading trits of an oligo
+fn check(trits: &[u8]) -> Vec<u8> {
+    let remainder = crc32fast::hash(trits) % 3u32.pow(CHECK_TRITS as u32);
+    digits(remainder as u64, CHECK_TRITS).collect()
+}
+
+/// Bases for `trits` with the rotating code
+pub fn to_bases(trits: &[u8]) -> Vec<u8> {
+    let mut previous = START;
+    trits
+        .iter()
+        .map(|&trit| {
+            let position = BASES.iter().position(|&base| base == previous).unwrap_or(0);
+            previous = BASES[(position + 1 + trit as usize) % BASES.len()];
+            previous
+        })
+        .collect()
+}
+
+/// Trits for `bases`, or `None` if a base is unknown or repeats the one
+/// before it (which the rotating code never writes)
+pub fn to_trits(bases: &[u8]) -> Option<Vec<u8>> {
+    let mut previous = 0;
+    std::iter::once(&START)
+        .chain(bases)
+        .map(|base| BASES.iter().position(|b| b == base))
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, position)| {
+            let step = (position + BASES.len() - previous) % BASES.len();
+            previous = position;
+            // The start base only sets the rotation
+            (i > 0).then(|| step.checked_sub(1).map(|trit| trit as u8))
+        })
+        .collect()
+}
+
+/// Trits for `data`, 21 per 4-byte word (the last one zero-padded)
+fn data_trits(data: &[u8]) -> Vec<u8> {
+    data.chunks(4)
+        .flat_map(|chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            digits(u32::from_le_bytes(word) as u64, WORD_TRITS)
+        })
+        .collect()
+}
+
+/// Words read back from trits, or `None` if one is out of range
+fn trit_words(trits: &[u8]) -> Option<Vec<u32>> {
+    trits
+        .chunks_exact(WORD_TRITS)
+        .map(|word| u32::try_from(value(word)).ok())
+        .collect()
+}
+
+/// DNA encoder configuration
+#[derive(Clone, Debug)]
+pub struct DNAEncoder {
+    /// Bases per oligo, addressing and check included
+    oligo_length: usize,
+
+    /// Data oligos per parity oligo (0 for none)
+    parity_group: usize,
+
+    strategy: EncodingStrategy,
+}
+
+impl DNAEncoder {
+    /// Create a DNA encoder (120-base oligos, one parity oligo per 4)
+    pub fn new() -> Self {
+        Self {
+            oligo_length: DEFAULT_OLIGO_LENGTH,
+            parity_group: DEFAULT_PARITY_GROUP,
+            strategy: EncodingStrategy::DNAEncoding,
+        }
+    }
+
+    /// Create with a custom oligo length in bases
+    ///
+    /// Decoding takes the length from the oligos themselves.
+    pub fn with_oligo_length(mut self, oligo_length: usize) -> Self {
+        self.oligo_length = oligo_length;
+        self
+    }
+
+    /// Create with a custom number of data oligos per parity oligo (0 turns
+    /// parity off)
+    pub fn with_parity_group(mut self, parity_group: usize) -> Self {
+        self.parity_group = parity_group;
+        self
+    }
+
+    /// Payload trits per oligo, failing if the header wouldn't fit
+    fn payload_trits(&self) -> Result<usize> {
+        match self.oligo_length.checked_sub(OVERHEAD) {
+            Some(payload) if payload >= HEADER_WORDS * WORD_TRITS => Ok(payload),
+            _ => Err(Error::Config(format!(
+                "Oligos must be at least {} bases long, got {}",
+                OVERHEAD + HEADER_WORDS * WORD_TRITS,
+                self.oligo_length
+            ))),
+        }
+    }
+
+    /// Data oligos needed for `len` bytes
+    fn data_oligos(&self, len: usize) -> Result<usize> {
+        Ok((len.div_ceil(4) * WORD_TRITS).div_ceil(self.payload_trits()?))
+    }
+
+    /// All oligos for `data`: header copies, then each group of data oligos
+    /// followed by its parity oligo
+    fn oligos(&self, data: &[u8]) -> Result<Vec<Oligo>> {
+        let payload_len = self.payload_trits()?;
+        let len = u32::try_from(data.len())
+            .map_err(|_| Error::Encoding("DNA encoding holds at most 4 GiB".to_string()))?;
+        if self.data_oligos(data.len())? as u64 >= 3u64.pow(INDEX_TRITS as u32) {
+            return Err(Error::Encoding(format!(
+                "{} bytes need more oligos than {} index trits can address",
+                data.len(),
+                INDEX_TRITS
+            )));
+        }
+
+        // A group never spans more than the data, so the decoder can reject
+        // headers claiming otherwise
+        let group = self.parity_group.min(self.data_oligos(data.len())?) as u32;
+        let header: Vec<u8> = [len, crc32fast::hash(data), group]
+            .into_iter()
+            .flat_map(|word| digits(word as u64, WORD_TRITS))
+            .chain(std::iter::repeat(0))
+            .take(payload_len)
+            .collect();
+        let mut oligos: Vec<Oligo> = (0..HEADER_COPIES)
+            .map(|copy| Oligo {
+                kind: Kind::Header,
+                index: copy,
+                payload: header.clone(),
+            })
+            .collect();
+
+        let trits = data_trits(data);
+        let data_oligos: Vec<Oligo> = trits
+            .chunks(payload_len)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let mut payload = chunk.to_vec();
+                payload.resize(payload_len, 0);
+                Oligo {
+                    kind: Kind::Data,
+                    index: index as u32,
+                    payload,
+                }
+            })
+            .collect();
+
+        if self.parity_group == 0 {
+            oligos.extend(data_oligos);
+            return Ok(oligos);
+        }
+        for (index, members) in data_oligos.chunks(self.parity_group).enumerate() {
+            oligos.extend_from_slice(members);
+            oligos.push(Oligo {
+                kind: Kind::Parity,
+                index: index as u32,
+                payload: parity(members.iter().map(|oligo| &oligo.payload[..]), payload_len),
+            });
+        }
+        Ok(oligos)
+    }
+
+    fn parameters(&self, oligos: usize, bases: usize, original_size: usize) -> serde_json::Value {
+        serde_json::json!({
+            "oligo_length": self.oligo_length,
+            "parity_group": self.parity_group,
+            "oligos": oligos,
+            "bits_per_base": original_size as f64 * 8.0 / bases.max(1) as f64,
+        })
+    }
+}
+
+impl Default for DNAEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Most common of `copies`, the earliest one on a tie
+fn majority<T: PartialEq>(copies: &[T]) -> Option<&T> {
+    copies
+        .iter()
+        .enumerate()
+        .max_by_key(|&(i, copy)| {
+            let votes = copies.iter().filter(|other| *other == copy).count();
+            (votes, std::cmp::Reverse(i))
+        })
+        .map(|(_, copy)| copy)
+}
+
+/// Trits that bring each column of `payloads` (and themselves) to 0 mod 3
+fn parity<'a>(payloads: impl Iterator<Item = &'a [u8]>, len: usize) -> Vec<u8> {
+    let mut sums = vec![0u32; len];
+    for payload in payloads {
+        for (sum, &trit) in sums.iter_mut().zip(payload) {
+            *sum += trit as u32;
+        }
+    }
+    sums.into_iter()
+        .map(|sum| ((3 - sum % 3) % 3) as u8)
+        .collect()
+}
+
+/// FASTA records for `oligos`
+fn write_fasta(oligos: &[Oligo]) -> String {
+    let mut fasta = String::new();
+    for oligo in oligos {
+        fasta.push_str(&format!(">{}_{}\n", oligo.kind.name(), oligo.index));
+        for line in to_bases(&oligo.trits()).chunks(LINE_WIDTH) {
+            fasta.push_str(std::str::from_utf8(line).unwrap_or_default());
+            fasta.push('\n');
+        }
+    }
+    fasta
+}
+
+/// Sequences of the FASTA records in `data`, uppercased
+fn read_fasta(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut sequences: Vec<Vec<u8>> = Vec::new();
+    for line in data.split(|&byte| byte == b'\n') {
+        match line.first() {
+            Some(b'>') => sequences.push(Vec::new()),
+            Some(b';') => {}
+            _ => {
+                let Some(sequence) = sequences.last_mut() else {
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+                    return Err(Error::Decoding(
+                        "FASTA sequence before the first record header".to_string(),
+                    ));
+                };
+                sequence.extend(
+                    line.iter()
+                        .filter(|byte| !byte.is_ascii_whitespace())
+                        .map(u8::to_ascii_uppercase),
+                );
+            }
+        }
+    }
+
+    if sequences.is_empty() {
+        return Err(Error::Decoding("No FASTA records found".to_string()));
+    }
+    Ok(sequences)
+}
+
+#[async_trait]
+impl Encoder for DNAEncoder {
+    async fn encode(&self, data: &[u8]) -> Result<EncodedData> {
+        let oligos = self.oligos(data)?;
+        debug!(
+            "Encoding {} bytes as {} oligos of {} bases",
+            data.len(),
+            oligos.len(),
+            self.oligo_length
+        );
+
+        let fasta = write_fasta(&oligos).into_bytes();
+        let parameters =
+            self.parameters(oligos.len(), oligos.len() * self.oligo_length, data.len());
+        let metadata = EncodingMetadata {
+            original_size: data.len(),
+            encoded_size: fasta.len(),
+            compression_ratio: fasta.len() as f64 / data.len() as f64,
+            strategy: "dna".to_string(),
+            parameters,
+        };
+
+        Ok(EncodedData {
+            data: fasta,
+            format: "fasta".to_string(),
+            metadata,
+        })
+    }
+
+    async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
+        let sequences = read_fasta(&encoded.data)?;
+
+        let mut unreadable = 0;
+        let mut oligos = Vec::with_capacity(sequences.len());
+        for sequence in &sequences {
+            match to_trits(sequence).and_then(|trits| Oligo::parse(&trits)) {
+                Some(oligo) => oligos.push(oligo),
+                None => unreadable += 1,
+            }
+        }
+
+        // The header copies agree on the data length and the oligo size
+        let headers: Vec<(Vec<u32>, usize)> = oligos
+            .iter()
+            .filter(|oligo| oligo.kind == Kind::Header)
+            .filter_map(|oligo| {
+                let words = trit_words(oligo.payload.get(..HEADER_WORDS * WORD_TRITS)?)?;
+                Some((words, oligo.payload.len()))
+            })
+            .collect();
+        let (header, payload_len) = majority(&headers).cloned().ok_or_else(|| {
+            Error::Corruption(format!(
+                "No readable header oligo ({} unreadable sequences skipped)",
+                unreadable
+            ))
+        })?;
+        let (len, checksum, group) = (header[0] as usize, header[1], header[2]);
+
+        // The header is only as trustworthy as its check trits, so its claims
+        // are held against what was actually read before anything is sized
+        // by them: every data oligo is read or rebuilt from a parity read
+        let count = (len.div_ceil(4) * WORD_TRITS).div_ceil(payload_len) as u32;
+        if count as usize > sequences.len() {
+            return Err(Error::Corruption(format!(
+                "Header claims {} data oligos, but only {} sequences were read",
+                count,
+                sequences.len()
+            )));
+        }
+        if group > count {
+            return Err(Error::Corruption(format!(
+                "Header claims parity groups of {} for {} data oligos",
+                group, count
+            )));
+        }
+
+        // Every readable copy of each oligo, in the order read
+        let mut reads: HashMap<u32, Vec<Vec<u8>>> = HashMap::new();
+        let mut parity_reads: HashMap<u32, Vec<Vec<u8>>> = HashMap::new();
+        for oligo in oligos {
+            if oligo.payload.len() != payload_len {
+                unreadable += 1;
+                continue;
+            }
+            match oligo.kind {
+                Kind::Data => reads.entry(oligo.index).or_default().push(oligo.payload),
+                Kind::Parity => parity_reads
+                    .entry(oligo.index)
+                    .or_default()
+                    .push(oligo.payload),
+                Kind::Header => continue,
+            };
+        }
+
+        if group > 0 {
+            // A data oligo that is lost or read in disagreeing copies gets
+            // one more vote, rebuilt from the rest of its group
+            let mut rebuilt = Vec::new();
+            for (index, parity_copies) in &parity_reads {
+                let Some(parity_payload) = majority(parity_copies) else {
+                    continue;
+                };
+                let Some(first) = index.checked_mul(group).filter(|&first| first < count) else {
+                    continue;
+                };
+                let members = first..(first + group).min(count);
+                for member in members.clone() {
+                    let copies = reads.get(&member).map_or(&[][..], Vec::as_slice);
+                    if !copies.is_empty() && copies.iter().all(|copy| *copy == copies[0]) {
+                        continue;
+                    }
+                    let others: Option<Vec<&Vec<u8>>> = members
+                        .clone()
+                        .filter(|&other| other != member)
+                        .map(|other| reads.get(&other).and_then(|copies| majority(copies)))
+                        .collect();
+                    if let Some(others) = others {
+                        let payload = parity(
+                            others
+                                .into_iter()
+                                .map(|payload| &payload[..])
+                                .chain(std::iter::once(&parity_payload[..])),
+                            payload_len,
+                        );
+                        debug!("Rebuilt data oligo {} from parity", member);
+                        rebuilt.push((member, payload));
+                    }
+                }
+            }
+            for (member, payload) in rebuilt {
+                reads.entry(member).or_default().push(payload);
+            }
+        }
+        let data: HashMap<u32, &Vec<u8>> = reads
+            .iter()
+            .filter_map(|(&index, copies)| Some((index, majority(copies)?)))
+            .collect();
+
+        let missing = (0..count).filter(|index| !data.contains_key(index));
+        let lost = missing.clone().count();
+        if lost > 0 {
+            let first: Vec<u32> = missing.take(REPORTED_MISSING).collect();
+            return Err(Error::Corruption(format!(
+                "Missing {} of {} oligos, starting {:?} ({} unreadable sequences skipped)",
+                lost, count, first, unreadable
+            )));
+        }
+
+        let trits: Vec<u8> = (0..count)
+            .flat_map(|index| data[&index].iter().copied())
+            .collect();
+        let words = trit_words(&trits[..len.div_ceil(4) * WORD_TRITS])
+            .ok_or_else(|| Error::Corruption("Data oligos hold invalid words".to_string()))?;
+        let mut decoded: Vec<u8> = words.into_iter().flat_map(u32::to_le_bytes).collect();
+        decoded.truncate(len);
+
+        let actual = crc32fast::hash(&decoded);
+        if actual != checksum {
+            return Err(Error::Corruption(format!(
+                "Checksum mismatch: expected {:08x}, got {:08x}",
+                checksum, actual
+            )));
+        }
+        Ok(decoded)
+    }
+
+    fn strategy(&self) -> &EncodingStrategy {
+        &self.strategy
+    }
+
+    fn estimate_size(&self, input_size: usize) -> usize {
+        let Ok(data_oligos) = self.data_oligos(input_size) else {
+            return 0;
+        };
+        let parity_oligos = match self.parity_group {
+            0 => 0,
+            group => data_oligos.div_ceil(group),
+        };
+
+        // Record name line, then the sequence wrapped into lines
+        let record = 16 + self.oligo_length + self.oligo_length.div_ceil(LINE_WIDTH);
+        (HEADER_COPIES as usize + data_oligos + parity_oligos) * record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::testing::test_data;
+    use crate::EncoderRegistry;
+
+    /// Records of a FASTA file, name line and sequence lines together
+    fn records(fasta: &[u8]) -> Vec<String> {
+        let text = std::str::from_utf8(fasta).unwrap();
+        text.split('>')
+            .filter(|record| !record.is_empty())
+            .map(|record| format!(">{}", record))
+            .collect()
+    }
+
+    #[test]
+    fn test_rotating_code_avoids_homopolymers() {
+        let trits: Vec<u8> = (0..300).map(|i| (i * 7 % 3) as u8).collect();
+        let bases = to_bases(&trits);
+        assert!(bases.iter().all(|base| BASES.contains(base)));
+        assert!(bases.windows(2).all(|pair| pair[0] != pair[1]));
+        assert_eq!(to_trits(&bases).unwrap(), trits);
+
+        // A run of equal trits still changes base every time
+        assert_eq!(to_bases(&[0; 6]), b"CGTACG");
+        assert!(to_trits(b"CGGT").is_none());
+        assert!(to_trits(b"CGNT").is_none());
+    }
+
+    #[test]
+    fn test_words_fit_in_trits() {
+        for word in [0, 1, 0xDEAD_BEEF, u32::MAX] {
+            let trits: Vec<u8> = digits(word as u64, WORD_TRITS).collect();
+            assert_eq!(trit_words(&trits).unwrap(), vec![word]);
+        }
+        assert!(trit_words(&[2; WORD_TRITS]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fasta_roundtrip() {
+        let encoder = DNAEncoder::new();
+        let data = test_data(600, 89);
+        let encoded = encoder.encode(&data).await.unwrap();
+        assert_eq!(encoded.format, "fasta");
+
+        let sequences = read_fasta(&encoded.data).unwrap();
+        assert_eq!(sequences.len(), encoded.metadata.parameters["oligos"]);
+        for sequence in &sequences {
+            assert_eq!(sequence.len(), DEFAULT_OLIGO_LENGTH);
+            assert!(sequence.windows(2).all(|pair| pair[0] != pair[1]));
+        }
+        assert!(encoded.data.starts_with(b">header_0\n"));
+
+        assert_eq!(encoder.decode(&encoded).await.unwrap(), data);
+        // Oligo length and parity come from the oligos themselves
+        let other = DNAEncoder::new().with_oligo_length(90).with_parity_group(0);
+        assert_eq!(other.decode(&encoded).await.unwrap(), data);
+        assert_eq!(
+            DNAEncoder::new()
+                .decode(&other.encode(&data).await.unwrap())
+                .await
+                .unwrap(),
+            data
+        );
+
+        let registered = EncoderRegistry::new()
+            .build(&EncodingStrategy::DNAEncoding)
+            .unwrap();
+        assert_eq!(registered.decode(&encoded).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_reordered_and_missing_oligos() {
+        let encoder = DNAEncoder::new();
+        let data = test_data(600, 89);
+        let encoded = encoder.encode(&data).await.unwrap();
+        let mut records = records(&encoded.data);
+
+        // One data oligo lost per parity group, two header copies lost, a
+        // substitution in another, and the rest shuffled
+        let lose = |records: &mut Vec<String>, name: &str| {
+            let name = format!(">{}\n", name);
+            records.retain(|record| !record.starts_with(&name));
+        };
+        for name in ["header_0", "header_2", "data_1", "data_6", "data_8"] {
+            lose(&mut records, name);
+        }
+        let damaged = records
+            .iter()
+            .position(|record| record.starts_with(">data_13\n"))
+            .unwrap();
+        let mut bytes = records[damaged].clone().into_bytes();
+        let at = bytes.len() - 30;
+        bytes[at] = if bytes[at] == b'A' { b'C' } else { b'A' };
+        records[damaged] = String::from_utf8(bytes).unwrap();
+        records.reverse();
+        records.swap(3, 10);
+
+        let shuffled = EncodedData::from_bytes(records.concat().into_bytes());
+        assert_eq!(encoder.decode(&shuffled).await.unwrap(), data);
+
+        // Two oligos from one group are beyond its parity
+        lose(&mut records, "data_9");
+        let lost = EncodedData::from_bytes(records.concat().into_bytes());
+        let Err(Error::Corruption(message)) = encoder.decode(&lost).await else {
+            panic!("missing oligos not reported");
+        };
+        assert!(message.contains("Missing 2 of"), "{}", message);
+        assert!(message.contains("[8, 9]"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_misreads_are_outvoted() {
+        let encoder = DNAEncoder::new();
+        let data = test_data(600, 89);
+        let oligos = encoder.oligos(&data).unwrap();
+
+        // Misreads that pass their check trits, read before the real oligos:
+        // a header claiming another length and a data oligo with one trit off
+        let mut header = oligos[0].clone();
+        header.payload[WORD_TRITS - 1] = (header.payload[WORD_TRITS - 1] + 1) % 3;
+        let mut misread = oligos
+            .iter()
+            .find(|oligo| oligo.kind == Kind::Data && oligo.index == 3)
+            .unwrap()
+            .clone();
+        misread.payload[0] = (misread.payload[0] + 1) % 3;
+
+        let fasta = write_fasta(&[vec![header, misread], oligos].concat());
+        let decoded = encoder
+            .decode(&EncodedData::from_bytes(fasta.into_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_crafted_header() {
+        let encoder = DNAEncoder::new();
+        let data = test_data(600, 89);
+        let oligos = encoder.oligos(&data).unwrap();
+        let count = oligos
+            .iter()
+            .filter(|oligo| oligo.kind == Kind::Data)
+            .count() as u32;
+
+        // Headers with valid check trits but claims no read set can back
+        let forge = |len: u32, group: u32| {
+            let mut header = oligos[0].clone();
+            let words = [len, crc32fast::hash(&data), group];
+            header.payload.splice(
+                ..HEADER_WORDS * WORD_TRITS,
+                words
+                    .into_iter()
+                    .flat_map(|word| digits(word as u64, WORD_TRITS)),
+            );
+            let forged: Vec<Oligo> = std::iter::once(header)
+                .chain(
+                    oligos
+                        .iter()
+                        .filter(|oligo| oligo.kind != Kind::Header)
+                        .cloned(),
+                )
+                .collect();
+            EncodedData::from_bytes(write_fasta(&forged).into_bytes())
+        };
+
+        for (len, group) in [(u32::MAX, 4), (600, u32::MAX), (600, count + 1)] {
+            let result = encoder.decode(&forge(len, group)).await;
+            assert!(matches!(result, Err(Error::Corruption(_))), "{:?}", result);
+        }
+        // Parity oligos past the last group are ignored
+        assert_eq!(encoder.decode(&forge(600, count)).await.unwrap(), data);
+    }
+
+    #[test]
+    fn test_oligo_length_must_hold_header() {
+        let encoder = DNAEncoder::new().with_oligo_length(OVERHEAD + 62);
+        assert!(matches!(encoder.payload_trits(), Err(Error::Config(_))));
+        assert!(DNAEncoder::new()
+            .with_oligo_length(OVERHEAD + 63)
+            .payload_trits()
+            .is_ok());
+    }
+}
@@ -0,0 +1,387 @@
+//! Reed–Solomon error correction within a frame
+//!
+//! A frame's data bytes are split into Reed–Solomon codewords over GF(256)
+//! of at most 255 bytes, each carrying `parity` check bytes and correcting up
+//! to `parity / 2` damaged bytes. Codewords are interleaved byte by byte, so
+//! neighbouring bytes of a frame (neighbouring blocks on screen) belong to
+//! different codewords and a blurred region spreads its damage across all of
+//! them instead of wiping out one.
+
+use isg_core::{Error, Result};
+
+/// Longest codeword over GF(256)
+const MAX_CODEWORD: usize = 255;
+
+/// Primitive polynomial x^8 + x^4 + x^3 + x^2 + 1
+const PRIMITIVE: u16 = 0x11d;
+
+/// Powers of the generator α (doubled so products need no reduction) and
+/// their logarithms
+const TABLES: ([u8; 512], [u8; 256]) = tables();
+
+const fn tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= PRIMITIVE;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+/// α^power
+fn exp(power: usize) -> u8 {
+    TABLES.0[power % 255]
+}
+
+fn log(value: u8) -> usize {
+    TABLES.1[value as usize] as usize
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    TABLES.0[log(a) + log(b)]
+}
+
+fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    TABLES.0[log(a) + 255 - log(b)]
+}
+
+/// Value of a polynomial (lowest degree first) at `x`
+fn eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, &coef| mul(acc, x) ^ coef)
+}
+
+/// Systematic Reed–Solomon code with `parity` check bytes
+#[derive(Clone, Debug)]
+pub struct ReedSolomon {
+    /// Generator polynomial ∏ (x - α^i) for i < parity, highest degree first
+    generator: Vec<u8>,
+}
+
+impl ReedSolomon {
+    /// Code with `parity` check bytes per codeword
+    pub fn new(parity: usize) -> Self {
+        let mut generator = vec![1u8];
+        for i in 0..parity {
+            // Multiply by (x + α^i)
+            let root = exp(i);
+            let mut next = vec![0u8; generator.len() + 1];
+            for (j, &coef) in generator.iter().enumerate() {
+                next[j] ^= coef;
+                next[j + 1] ^= mul(coef, root);
+            }
+            generator = next;
+        }
+        Self { generator }
+    }
+
+    /// Check bytes per codeword
+    pub fn parity(&self) -> usize {
+        self.generator.len() - 1
+    }
+
+    /// Check bytes for `data`, to be appended after it
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        // Remainder of data * x^parity divided by the generator
+        let mut remainder = vec![0u8; self.parity()];
+        if remainder.is_empty() {
+            return remainder;
+        }
+        for &byte in data {
+            let factor = byte ^ remainder[0];
+            remainder.rotate_left(1);
+            remainder[self.parity() - 1] = 0;
+            for (slot, &coef) in remainder.iter_mut().zip(&self.generator[1..]) {
+                *slot ^= mul(coef, factor);
+            }
+        }
+        remainder
+    }
+
+    /// Correct a codeword (data followed by check bytes) in place, returning
+    /// how many bytes were corrected
+    pub fn decode(&self, codeword: &mut [u8]) -> Result<usize> {
+        let parity = self.parity();
+        let n = codeword.len();
+
+        // Syndromes: the codeword (highest degree first) at each root
+        let syndromes: Vec<u8> = (0..parity)
+            .map(|i| {
+                let root = exp(i);
+                codeword.iter().fold(0, |acc, &byte| mul(acc, root) ^ byte)
+            })
+            .collect();
+        if syndromes.iter().all(|&s| s == 0) {
+            return Ok(0);
+        }
+
+        let locator = error_locator(&syndromes);
+        let errors = locator.len() - 1;
+        if errors * 2 > parity {
+            return Err(too_many_errors());
+        }
+
+        // Chien search: roots of the locator are inverse error locations
+        let positions: Vec<usize> = (0..n)
+            .filter(|&degree| eval(&locator, exp(255 - degree % 255)) == 0)
+            .collect();
+        if positions.len() != errors {
+            return Err(too_many_errors());
+        }
+
+        // Forney: evaluator Ω = S·Λ mod x^parity
+        let mut evaluator = vec![0u8; parity];
+        for (i, &s) in syndromes.iter().enumerate() {
+            for (j, &l) in locator.iter().enumerate().take(parity - i) {
+                evaluator[i + j] ^= mul(s, l);
+            }
+        }
+        // Formal derivative: only odd powers survive in characteristic 2
+        let derivative: Vec<u8> = locator
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, &coef)| if i % 2 == 1 { coef } else { 0 })
+            .collect();
+
+        for &degree in &positions {
+            let location = exp(degree);
+            let inverse = exp(255 - degree % 255);
+            let denominator = eval(&derivative, inverse);
+            if denominator == 0 {
+                return Err(too_many_errors());
+            }
+            let magnitude = mul(location, div(eval(&evaluator, inverse), denominator));
+            codeword[n - 1 - degree] ^= magnitude;
+        }
+
+        // Guard against miscorrecting a codeword damaged beyond repair
+        let clean = (0..parity).all(|i| {
+            let root = exp(i);
+            codeword.iter().fold(0, |acc, &byte| mul(acc, root) ^ byte) == 0
+        });
+        if !clean {
+            return Err(too_many_errors());
+        }
+
+        Ok(errors)
+    }
+}
+
+/// Error locator Λ (lowest degree first) by Berlekamp–Massey
+fn error_locator(syndromes: &[u8]) -> Vec<u8> {
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut length = 0;
+    let mut shift = 1;
+    let mut last_discrepancy = 1u8;
+
+    for n in 0..syndromes.len() {
+        let discrepancy = (1..=length.min(locator.len() - 1)).fold(syndromes[n], |acc, i| {
+            acc ^ mul(locator[i], syndromes[n - i])
+        });
+
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        // locator -= discrepancy / last_discrepancy * x^shift * previous
+        let scale = div(discrepancy, last_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &coef) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(coef, scale);
+        }
+
+        if 2 * length <= n {
+            previous = std::mem::replace(&mut locator, next);
+            length = n + 1 - length;
+            last_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+
+    locator.truncate(length + 1);
+    locator
+}
+
+fn too_many_errors() -> Error {
+    Error::Corruption("Too many errors to correct in codeword".to_string())
+}
+
+/// Interleaved codewords filling the data bytes of one frame
+#[derive(Clone, Debug)]
+pub struct FrameCode {
+    /// Bytes in a frame
+    len: usize,
+
+    /// Codewords per frame; frame byte i belongs to codeword i % codewords
+    codewords: usize,
+
+    code: ReedSolomon,
+}
+
+impl FrameCode {
+    /// Code for frames of `len` bytes with `parity` check bytes per codeword
+    pub fn new(len: usize, parity: usize) -> Result<Self> {
+        let codewords = len.div_ceil(MAX_CODEWORD).max(1);
+        // The shortest codeword must hold data besides its check bytes
+        if parity > 0 && len / codewords <= parity {
+            return Err(Error::Config(format!(
+                "Frames of {} bytes are too small for {} check bytes per codeword",
+                len, parity
+            )));
+        }
+
+        Ok(Self {
+            len,
+            codewords,
+            code: ReedSolomon::new(parity),
+        })
+    }
+
+    /// Data bytes per frame
+    pub fn data_len(&self) -> usize {
+        self.len - self.codewords.min(self.len) * self.code.parity()
+    }
+
+    /// Fraction of each frame carrying data
+    pub fn rate(&self) -> f64 {
+        self.data_len() as f64 / self.len.max(1) as f64
+    }
+
+    /// Length of codeword `index`
+    fn codeword_len(&self, index: usize) -> usize {
+        (self.len - index).div_ceil(self.codewords)
+    }
+
+    /// Frame byte positions holding data, in data order
+    ///
+    /// Data fills the leading bytes of every codeword, so check bytes end up
+    /// together at the end of the frame.
+    pub fn data_positions(&self) -> impl Iterator<Item = usize> + '_ {
+        let parity = self.code.parity();
+        (0..self.len)
+            .filter(move |&i| i / self.codewords + parity < self.codeword_len(i % self.codewords))
+    }
+
+    /// Frame bytes for `data` (padded with zeros or cut to
+    /// [`FrameCode::data_len`])
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; self.len];
+        for (position, &byte) in self.data_positions().zip(data) {
+            frame[position] = byte;
+        }
+
+        for index in (0..self.codewords).filter(|_| self.code.parity() > 0) {
+            let positions: Vec<usize> = (index..self.len).step_by(self.codewords).collect();
+            let data_len = positions.len() - self.code.parity();
+            let codeword: Vec<u8> = positions[..data_len].iter().map(|&i| frame[i]).collect();
+            for (&position, check) in positions[data_len..]
+                .iter()
+                .zip(self.code.encode(&codeword))
+            {
+                frame[position] = check;
+            }
+        }
+
+        frame
+    }
+
+    /// Data from frame bytes, correcting what can be corrected
+    ///
+    /// Returns the data, bytes corrected, and codewords beyond repair (whose
+    /// data is passed through as read).
+    pub fn decode(&self, frame: &[u8]) -> (Vec<u8>, usize, usize) {
+        let mut frame = frame.to_vec();
+        frame.resize(self.len, 0);
+        let mut corrected = 0;
+        let mut failed = 0;
+
+        if self.code.parity() > 0 {
+            for index in 0..self.codewords {
+                let positions: Vec<usize> = (index..self.len).step_by(self.codewords).collect();
+                let mut codeword: Vec<u8> = positions.iter().map(|&i| frame[i]).collect();
+                match self.code.decode(&mut codeword) {
+                    Ok(count) => {
+                        corrected += count;
+                        for (&position, byte) in positions.iter().zip(codeword) {
+                            frame[position] = byte;
+                        }
+                    }
+                    Err(_) => failed += 1,
+                }
+            }
+        }
+
+        let data = self.data_positions().map(|i| frame[i]).collect();
+        (data, corrected, failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::testing::test_data;
+
+    #[test]
+    fn test_reed_solomon_corrects_up_to_half_parity() {
+        let code = ReedSolomon::new(8);
+        let data = test_data(40, 73);
+        let mut codeword = data.clone();
+        codeword.extend(code.encode(&data));
+
+        let mut damaged = codeword.clone();
+        for (i, position) in [0, 7, 21, 45].into_iter().enumerate() {
+            damaged[position] ^= 0x5a + i as u8;
+        }
+        assert_eq!(code.decode(&mut damaged).unwrap(), 4);
+        assert_eq!(damaged, codeword);
+
+        for position in [1, 2, 3, 4, 5] {
+            damaged[position] ^= 0xff;
+        }
+        assert!(code.decode(&mut damaged).is_err());
+    }
+
+    #[test]
+    fn test_interleaving_spreads_burst_errors() {
+        // Two codewords of 150 bytes, each correcting 5 bytes
+        let code = FrameCode::new(300, 10).unwrap();
+        assert_eq!(code.data_len(), 280);
+
+        let data = test_data(280, 31);
+        let mut frame = code.encode(&data);
+
+        // A burst of 10 consecutive bytes hits each codeword 5 times
+        for byte in &mut frame[100..110] {
+            *byte = !*byte;
+        }
+        let (decoded, corrected, failed) = code.decode(&frame);
+        assert_eq!((corrected, failed), (10, 0));
+        assert_eq!(decoded, data);
+
+        assert!(FrameCode::new(20, 20).is_err());
+    }
+}
@@ -0,0 +1,613 @@
+//! Frame layout and geometry recovery for pixel frames
+//!
+//! Platforms rescale (1080p to 720p), crop borders or letterbox uploaded
+//! video, so a decoder cannot assume blocks sit at their original pixel
+//! offsets. Aligned frames carry a QR-style finder pattern in each corner of
+//! the block grid, inside a one-block quiet zone. The decoder locates the
+//! finders, fits an affine map from grid to pixel coordinates and samples
+//! each block at its mapped centre.
+
+use crate::gray::calibration_level;
+use crate::threshold::{self, Thresholding};
+use image::RgbaImage;
+use isg_core::{Error, Result};
+use tracing::debug;
+
+/// White border (in blocks) around the grid of an aligned frame
+const QUIET_ZONE: u32 = 1;
+
+/// Side of a finder pattern in blocks
+const FINDER_SIZE: u32 = 7;
+
+/// Blocks from a frame edge to the end of a finder's separator
+const FINDER_AREA: u32 = QUIET_ZONE + FINDER_SIZE + 1;
+
+/// Role of a block in a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Cell {
+    /// Carries a data bit
+    Data,
+
+    /// Calibration cell showing a known gray level (0 = white)
+    Calibration(u8),
+
+    /// Part of an alignment marker or its quiet zone (true = black)
+    Marker(bool),
+}
+
+/// Arrangement of data, calibration and marker cells in a frame
+#[derive(Clone, Debug)]
+pub(crate) struct FrameLayout {
+    /// Grid width in blocks
+    pub blocks_x: u32,
+
+    /// Grid height in blocks
+    pub blocks_y: u32,
+
+    /// Whether the frame carries alignment markers
+    pub aligned: bool,
+
+    /// Role of every block, row-major
+    pub cells: Vec<Cell>,
+
+    /// Data cells in bit order
+    pub data: Vec<usize>,
+
+    /// Cells with a known gray level (0 = white), for measuring levels
+    pub known: Vec<(usize, u8)>,
+
+    /// Known black or white cells, for thresholding (true = black)
+    pub reference: Vec<(usize, bool)>,
+}
+
+impl FrameLayout {
+    /// Lay out a `blocks_x` by `blocks_y` grid for blocks with `levels`
+    /// gray levels
+    ///
+    /// Without alignment, calibration cells fill the top row. With alignment,
+    /// they run along the top edge between the two upper finders.
+    pub fn new(
+        blocks_x: u32,
+        blocks_y: u32,
+        levels: u8,
+        calibration: bool,
+        aligned: bool,
+    ) -> Result<Self> {
+        let minimum = 2 * FINDER_AREA + 1;
+        if aligned && (blocks_x < minimum || blocks_y < minimum) {
+            return Err(Error::Config(format!(
+                "A {}x{} block grid is too small for alignment markers (need {}x{})",
+                blocks_x, blocks_y, minimum, minimum
+            )));
+        }
+
+        let mut cells = Vec::with_capacity((blocks_x * blocks_y) as usize);
+        let mut calibration_index = 0;
+
+        for y in 0..blocks_y {
+            for x in 0..blocks_x {
+                let cell = if aligned {
+                    Self::aligned_cell(x, y, blocks_x, blocks_y)
+                } else {
+                    None
+                };
+
+                let calibration_row = if aligned { QUIET_ZONE } else { 0 };
+                let cell = match cell {
+                    Some(cell) => cell,
+                    None if calibration && y == calibration_row => {
+                        calibration_index += 1;
+                        Cell::Calibration(calibration_level(calibration_index - 1, levels))
+                    }
+                    None => Cell::Data,
+                };
+                cells.push(cell);
+            }
+        }
+
+        let data = (0..cells.len())
+            .filter(|&i| cells[i] == Cell::Data)
+            .collect();
+        let known: Vec<(usize, u8)> = cells
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cell)| match *cell {
+                Cell::Calibration(level) => Some((i, level)),
+                Cell::Marker(black) => Some((i, if black { levels - 1 } else { 0 })),
+                Cell::Data => None,
+            })
+            .collect();
+        let reference = known
+            .iter()
+            .filter(|&&(_, level)| level == 0 || level == levels - 1)
+            .map(|&(i, level)| (i, level != 0))
+            .collect();
+
+        Ok(Self {
+            blocks_x,
+            blocks_y,
+            aligned,
+            cells,
+            data,
+            known,
+            reference,
+        })
+    }
+
+    /// Marker role of a block in an aligned frame, if any
+    fn aligned_cell(x: u32, y: u32, blocks_x: u32, blocks_y: u32) -> Option<Cell> {
+        let in_quiet_zone = x < QUIET_ZONE
+            || y < QUIET_ZONE
+            || x >= blocks_x - QUIET_ZONE
+            || y >= blocks_y - QUIET_ZONE;
+        if in_quiet_zone {
+            return Some(Cell::Marker(false));
+        }
+
+        // Finders are symmetric, so mirror the far corners onto the near one
+        let local = |pos: u32, len: u32| {
+            if pos < FINDER_AREA {
+                Some(pos - QUIET_ZONE)
+            } else if pos >= len - FINDER_AREA {
+                Some(len - QUIET_ZONE - 1 - pos)
+            } else {
+                None
+            }
+        };
+
+        let (fx, fy) = (local(x, blocks_x)?, local(y, blocks_y)?);
+        if fx == FINDER_SIZE || fy == FINDER_SIZE {
+            // Separator between the finder and the data
+            return Some(Cell::Marker(false));
+        }
+
+        // Dark outer ring, light inner ring, dark 3x3 core
+        let ring = fx
+            .abs_diff(FINDER_SIZE / 2)
+            .max(fy.abs_diff(FINDER_SIZE / 2));
+        Some(Cell::Marker(ring != 2))
+    }
+
+    /// Finder centres in grid coordinates: top-left, top-right, bottom-left,
+    /// bottom-right
+    fn finder_centres(&self) -> [(f64, f64); 4] {
+        let near = QUIET_ZONE as f64 + FINDER_SIZE as f64 / 2.0;
+        let right = self.blocks_x as f64 - near;
+        let bottom = self.blocks_y as f64 - near;
+        [(near, near), (right, near), (near, bottom), (right, bottom)]
+    }
+}
+
+/// A grid point and the pixel it maps to
+type PointPair = ((f64, f64), (f64, f64));
+
+/// Affine map from grid coordinates (in blocks) to frame pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Transform {
+    x: [f64; 3],
+    y: [f64; 3],
+}
+
+impl Transform {
+    /// Stretch the grid over the whole frame
+    fn stretch(frame: &RgbaImage, layout: &FrameLayout) -> Self {
+        Self {
+            x: [frame.width() as f64 / layout.blocks_x as f64, 0.0, 0.0],
+            y: [0.0, frame.height() as f64 / layout.blocks_y as f64, 0.0],
+        }
+    }
+
+    /// Least-squares fit through `(grid, pixel)` point pairs
+    fn fit(pairs: &[PointPair]) -> Option<Self> {
+        if pairs.len() < 3 {
+            return None;
+        }
+
+        // Normal equations for target = a * gx + b * gy + c
+        let mut matrix = [[0.0; 3]; 3];
+        let mut rhs_x = [0.0; 3];
+        let mut rhs_y = [0.0; 3];
+        for &((gx, gy), (px, py)) in pairs {
+            let row = [gx, gy, 1.0];
+            for i in 0..3 {
+                for j in 0..3 {
+                    matrix[i][j] += row[i] * row[j];
+                }
+                rhs_x[i] += row[i] * px;
+                rhs_y[i] += row[i] * py;
+            }
+        }
+
+        Some(Self {
+            x: solve3(matrix, rhs_x)?,
+            y: solve3(matrix, rhs_y)?,
+        })
+    }
+
+    /// Pixel position of a grid point
+    fn apply(&self, gx: f64, gy: f64) -> (f64, f64) {
+        (
+            self.x[0] * gx + self.x[1] * gy + self.x[2],
+            self.y[0] * gx + self.y[1] * gy + self.y[2],
+        )
+    }
+
+    /// Approximate pixels per block
+    fn module(&self) -> f64 {
+        (self.x[0] * self.y[1] - self.x[1] * self.y[0]).abs().sqrt()
+    }
+}
+
+/// Solve a 3x3 linear system with Cramer's rule
+fn solve3(matrix: [[f64; 3]; 3], rhs: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+
+    let base = det(matrix);
+    if base.abs() < 1e-9 {
+        return None;
+    }
+
+    let mut solution = [0.0; 3];
+    for (column, value) in solution.iter_mut().enumerate() {
+        let mut replaced = matrix;
+        for row in 0..3 {
+            replaced[row][column] = rhs[row];
+        }
+        *value = det(replaced) / base;
+    }
+    Some(solution)
+}
+
+/// Locate the grid of an aligned frame
+///
+/// Tries the fits through the detected finders (all four, then each set of
+/// three in case one was cropped away or misdetected) and a plain stretch,
+/// keeping whichever reads the most marker cells correctly. Pixels are
+/// binarized with the same thresholding mode used to read the blocks.
+pub(crate) fn locate(frame: &RgbaImage, layout: &FrameLayout, mode: Thresholding) -> Transform {
+    let luma = luminance(frame);
+    let thresholds = threshold::pixel_thresholds(&luma, frame.width() as usize, mode);
+    let finders = find_finders(&luma, &thresholds, frame.width() as usize);
+
+    let mut candidates = Vec::new();
+    if !finders.is_empty() {
+        // Each corner finder is the most extreme candidate in its direction
+        let pick = |score: fn(&Finder) -> f64| {
+            let best = finders
+                .iter()
+                .max_by(|a, b| score(a).total_cmp(&score(b)))
+                .unwrap();
+            (best.x, best.y)
+        };
+        let found = [
+            pick(|f| -(f.x + f.y)),
+            pick(|f| f.x - f.y),
+            pick(|f| f.y - f.x),
+            pick(|f| f.x + f.y),
+        ];
+        let pairs: Vec<_> = layout.finder_centres().into_iter().zip(found).collect();
+
+        candidates.extend(Transform::fit(&pairs));
+        for skip in 0..pairs.len() {
+            let subset: Vec<_> = (0..pairs.len())
+                .filter(|&i| i != skip)
+                .map(|i| pairs[i])
+                .collect();
+            candidates.extend(Transform::fit(&subset));
+        }
+    }
+    candidates.push(Transform::stretch(frame, layout));
+
+    let (errors, transform) = candidates
+        .into_iter()
+        .map(|transform| {
+            (
+                marker_errors(frame, layout, &transform, &thresholds),
+                transform,
+            )
+        })
+        .min_by_key(|&(errors, _)| errors)
+        .unwrap();
+
+    debug!(
+        "Located grid from {} finder candidates with {} marker errors",
+        finders.len(),
+        errors
+    );
+    transform
+}
+
+/// Block brightness for every cell, sampled through `transform`
+pub(crate) fn sample(frame: &RgbaImage, layout: &FrameLayout, transform: &Transform) -> Vec<u8> {
+    let radius = (transform.module() * 0.3) as i64;
+    (0..layout.blocks_y)
+        .flat_map(|y| (0..layout.blocks_x).map(move |x| (x, y)))
+        .map(|(x, y)| sample_block(frame, centre(frame, transform, x, y), radius))
+        .collect()
+}
+
+/// Count marker and calibration cells that read back wrong
+fn marker_errors(
+    frame: &RgbaImage,
+    layout: &FrameLayout,
+    transform: &Transform,
+    thresholds: &[u8],
+) -> usize {
+    let radius = (transform.module() * 0.3) as i64;
+    let blocks_x = layout.blocks_x as usize;
+    layout
+        .reference
+        .iter()
+        .filter(|&&(i, bit)| {
+            let (x, y) = ((i % blocks_x) as u32, (i / blocks_x) as u32);
+            let (cx, cy) = centre(frame, transform, x, y);
+            let threshold = thresholds[cy as usize * frame.width() as usize + cx as usize];
+            (sample_block(frame, (cx, cy), radius) < threshold) != bit
+        })
+        .count()
+}
+
+/// Pixel at the mapped centre of a block, clamped to the frame
+fn centre(frame: &RgbaImage, transform: &Transform, x: u32, y: u32) -> (i64, i64) {
+    let (cx, cy) = transform.apply(x as f64 + 0.5, y as f64 + 0.5);
+    (
+        (cx.floor() as i64).clamp(0, frame.width() as i64 - 1),
+        (cy.floor() as i64).clamp(0, frame.height() as i64 - 1),
+    )
+}
+
+/// Average brightness in a window around a pixel
+fn sample_block(frame: &RgbaImage, (cx, cy): (i64, i64), radius: i64) -> u8 {
+    let (width, height) = (frame.width() as i64, frame.height() as i64);
+
+    let mut sum = 0u32;
+    let mut count = 0u32;
+    for py in (cy - radius).max(0)..=(cy + radius).min(height - 1) {
+        for px in (cx - radius).max(0)..=(cx + radius).min(width - 1) {
+            let pixel = frame.get_pixel(px as u32, py as u32);
+            sum += (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3;
+            count += 1;
+        }
+    }
+
+    (sum / count) as u8
+}
+
+/// Grayscale copy of a frame, row-major
+fn luminance(frame: &RgbaImage) -> Vec<u8> {
+    frame
+        .pixels()
+        .map(|p| ((p[0] as u32 + p[1] as u32 + p[2] as u32) / 3) as u8)
+        .collect()
+}
+
+/// Detected finder pattern centre in pixels
+#[derive(Clone, Debug)]
+struct Finder {
+    x: f64,
+    y: f64,
+    module: f64,
+    hits: usize,
+}
+
+/// Scan rows for the 1:1:3:1:1 finder signature, confirm each hit along
+/// its column and merge hits belonging to the same finder
+fn find_finders(luma: &[u8], thresholds: &[u8], width: usize) -> Vec<Finder> {
+    if width == 0 {
+        return Vec::new();
+    }
+    let height = luma.len() / width;
+    let dark = |x: usize, y: usize| luma[y * width + x] < thresholds[y * width + x];
+
+    let mut finders: Vec<Finder> = Vec::new();
+    for y in 0..height {
+        let runs = runs((0..width).map(|x| dark(x, y)));
+
+        for window in runs.windows(5) {
+            if !window[0].dark {
+                continue;
+            }
+            let lengths = [0, 1, 2, 3, 4].map(|i| window[i].len);
+            if !finder_ratio(lengths) {
+                continue;
+            }
+
+            let core = &window[2];
+            let column = core.start + core.len / 2;
+            let Some((centre_y, vertical)) = cross_check(|i| dark(column, i), height, y) else {
+                continue;
+            };
+
+            let horizontal: usize = lengths.iter().sum();
+            if vertical.abs_diff(horizontal) * 2 > horizontal {
+                continue;
+            }
+
+            let hit = Finder {
+                x: core.start as f64 + core.len as f64 / 2.0,
+                y: centre_y,
+                module: (horizontal + vertical) as f64 / 14.0,
+                hits: 1,
+            };
+            merge(&mut finders, hit);
+        }
+    }
+
+    // A real finder is crossed by several rows of its core
+    finders.retain(|finder| finder.hits >= 2);
+    finders
+}
+
+/// Fold a hit into the finder it belongs to, or start a new one
+fn merge(finders: &mut Vec<Finder>, hit: Finder) {
+    let existing = finders.iter_mut().find(|finder| {
+        let reach = 2.0 * finder.module.max(hit.module);
+        (finder.x - hit.x).abs() <= reach && (finder.y - hit.y).abs() <= reach
+    });
+
+    match existing {
+        Some(finder) => {
+            let n = finder.hits as f64;
+            finder.x = (finder.x * n + hit.x) / (n + 1.0);
+            finder.y = (finder.y * n + hit.y) / (n + 1.0);
+            finder.module = (finder.module * n + hit.module) / (n + 1.0);
+            finder.hits += 1;
+        }
+        None => finders.push(hit),
+    }
+}
+
+/// Run of equal pixels along a line
+struct Run {
+    start: usize,
+    len: usize,
+    dark: bool,
+}
+
+/// Split a line of pixels into runs
+fn runs(pixels: impl Iterator<Item = bool>) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    for (i, dark) in pixels.enumerate() {
+        match runs.last_mut() {
+            Some(run) if run.dark == dark => run.len += 1,
+            _ => runs.push(Run {
+                start: i,
+                len: 1,
+                dark,
+            }),
+        }
+    }
+    runs
+}
+
+/// Check run lengths against the 1:1:3:1:1 finder proportions
+fn finder_ratio(lengths: [usize; 5]) -> bool {
+    let total: usize = lengths.iter().sum();
+    if total < 7 || lengths.contains(&0) {
+        return false;
+    }
+
+    let module = total as f64 / 7.0;
+    let tolerance = module / 2.0;
+    lengths.iter().enumerate().all(|(i, &len)| {
+        let expected = if i == 2 { 3.0 } else { 1.0 };
+        (len as f64 - expected * module).abs() < expected * tolerance
+    })
+}
+
+/// Measure the finder runs along a column through dark pixel `start`
+///
+/// Returns the centre of the core run and the total pattern length.
+fn cross_check(dark: impl Fn(usize) -> bool, len: usize, start: usize) -> Option<(f64, usize)> {
+    // Walk from `start` one way, counting the core, light ring and dark ring
+    let walk = |step: isize| {
+        let mut counts = [0usize; 3];
+        let mut pos = start as isize;
+        for (run, count) in counts.iter_mut().enumerate() {
+            let want_dark = run != 1;
+            while pos >= 0 && (pos as usize) < len && dark(pos as usize) == want_dark {
+                *count += 1;
+                pos += step;
+            }
+        }
+        counts
+    };
+
+    let (up, down) = (walk(-1), walk(1));
+    if up[0] == 0 {
+        return None;
+    }
+
+    // Both walks counted `start` in the core
+    let lengths = [up[2], up[1], up[0] + down[0] - 1, down[1], down[2]];
+    if !finder_ratio(lengths) {
+        return None;
+    }
+
+    let top = start + 1 - up[0];
+    Some((top as f64 + lengths[2] as f64 / 2.0, lengths.iter().sum()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Draw a layout with one pixel square per block scaled by `scale`
+    fn draw(layout: &FrameLayout, scale: u32) -> RgbaImage {
+        RgbaImage::from_fn(layout.blocks_x * scale, layout.blocks_y * scale, |x, y| {
+            let index = ((y / scale) * layout.blocks_x + x / scale) as usize;
+            let black = match layout.cells[index] {
+                Cell::Data => (index * 7 + index / 3) % 5 < 2,
+                Cell::Calibration(level) => level != 0,
+                Cell::Marker(bit) => bit,
+            };
+            let level = if black { 0 } else { 255 };
+            Rgba([level, level, level, 255])
+        })
+    }
+
+    #[test]
+    fn test_layout_reserves_markers() {
+        let layout = FrameLayout::new(40, 30, 2, true, true).unwrap();
+        assert_eq!(layout.cells.len(), 1200);
+        assert_eq!(layout.cells[0], Cell::Marker(false));
+        // Outer ring, light ring and core of the top-left finder
+        assert_eq!(layout.cells[41], Cell::Marker(true));
+        assert_eq!(layout.cells[2 * 40 + 2], Cell::Marker(false));
+        assert_eq!(layout.cells[4 * 40 + 4], Cell::Marker(true));
+        assert_eq!(layout.cells[40 + 9], Cell::Calibration(1));
+        assert_eq!(
+            layout.data.len() + layout.reference.len(),
+            layout.cells.len()
+        );
+
+        assert!(FrameLayout::new(18, 30, 2, false, true).is_err());
+        let plain = FrameLayout::new(10, 10, 2, true, false).unwrap();
+        assert_eq!(plain.data.len(), 90);
+    }
+
+    #[test]
+    fn test_locate_finds_scaled_and_shifted_grid() {
+        let layout = FrameLayout::new(40, 30, 2, true, true).unwrap();
+        let grid = draw(&layout, 3);
+
+        // Paste onto a larger black canvas at an offset
+        let mut frame = RgbaImage::from_pixel(150, 110, Rgba([0, 0, 0, 255]));
+        image::imageops::overlay(&mut frame, &grid, 17, 9);
+
+        let transform = locate(&frame, &layout, Thresholding::Frame);
+        let (x, y) = transform.apply(20.0, 15.0);
+        assert!((x - (17.0 + 60.0)).abs() < 1.0, "{}", x);
+        assert!((y - (9.0 + 45.0)).abs() < 1.0, "{}", y);
+        let thresholds = vec![128; frame.len() / 4];
+        assert_eq!(marker_errors(&frame, &layout, &transform, &thresholds), 0);
+    }
+
+    #[test]
+    fn test_fit_is_exact_for_affine_points() {
+        let truth = Transform {
+            x: [2.5, 0.1, 7.0],
+            y: [-0.2, 1.5, 3.0],
+        };
+        let pairs: Vec<_> = [(0.0, 0.0), (10.0, 0.0), (0.0, 8.0), (10.0, 8.0)]
+            .into_iter()
+            .map(|(gx, gy)| ((gx, gy), truth.apply(gx, gy)))
+            .collect();
+
+        let fitted = Transform::fit(&pairs).unwrap();
+        for (a, b) in fitted
+            .x
+            .iter()
+            .chain(&fitted.y)
+            .zip(truth.x.iter().chain(&truth.y))
+        {
+            assert!((a - b).abs() < 1e-9);
+        }
+        assert!(Transform::fit(&pairs[..2]).is_none());
+    }
+}
@@ -0,0 +1,233 @@
+//! Multi-level gray symbols for pixel frames
+//!
+//! Instead of black/white, each block can show one of N evenly spaced gray
+//! levels and carry log2(N) bits. Symbols are Gray-coded, so mistaking a
+//! block for a neighbouring level (the usual error after lossy re-encoding)
+//! costs a single bit. The decoder measures where each level actually landed
+//! from the calibration cells and reports a soft decision per bit.
+
+use crate::threshold::{erfc, FrameReport, MIN_NOISE, UNCERTAIN_MARGIN};
+use isg_core::{Error, Result};
+
+/// Most gray levels a block can use
+const MAX_LEVELS: u8 = 16;
+
+/// Bits carried by a block with `levels` gray levels
+pub(crate) fn bits_per_block(levels: u8) -> Result<u32> {
+    if !(2..=MAX_LEVELS).contains(&levels) || !levels.is_power_of_two() {
+        return Err(Error::Config(format!(
+            "Gray levels must be a power of two from 2 to {}, got {}",
+            MAX_LEVELS, levels
+        )));
+    }
+    Ok(levels.trailing_zeros())
+}
+
+/// Gray code of `value`
+pub(crate) fn to_gray(value: u8) -> u8 {
+    value ^ (value >> 1)
+}
+
+/// Level (0 = white, `levels - 1` = black) that shows `symbol`
+pub(crate) fn symbol_level(symbol: u8) -> u8 {
+    // Inverse Gray code
+    let mut level = symbol;
+    let mut shift = symbol >> 1;
+    while shift != 0 {
+        level ^= shift;
+        shift >>= 1;
+    }
+    level
+}
+
+/// Nominal brightness of a level
+pub(crate) fn level_luma(level: u8, levels: u8) -> u8 {
+    255 - (level as u32 * 255 / (levels as u32 - 1)) as u8
+}
+
+/// Level of calibration cell `index`: cycles from black to white, so with
+/// two levels the cells alternate black and white
+pub(crate) fn calibration_level(index: usize, levels: u8) -> u8 {
+    levels - 1 - (index % levels as usize) as u8
+}
+
+/// Where each level landed in one frame
+pub(crate) struct LevelModel {
+    /// Measured brightness of each level
+    centroids: Vec<f64>,
+
+    /// Standard deviation of blocks around their level
+    noise: f64,
+
+    /// Bits per block
+    bits: u32,
+}
+
+impl LevelModel {
+    /// Measure levels from `known` cells (index, level), filling levels
+    /// without any known cell by spacing them evenly between black and white
+    pub fn measure(brightness: &[u8], known: &[(usize, u8)], levels: u8) -> Self {
+        let mut sums = vec![(0.0, 0u64); levels as usize];
+        for &(i, level) in known {
+            sums[level as usize].0 += brightness[i] as f64;
+            sums[level as usize].1 += 1;
+        }
+
+        let mean = |(sum, count): (f64, u64)| (count > 0).then(|| sum / count as f64);
+        let white = mean(sums[0]).unwrap_or(255.0);
+        let black = mean(sums[levels as usize - 1]).unwrap_or(0.0);
+        let step = (black - white) / (levels - 1) as f64;
+
+        let centroids: Vec<f64> = sums
+            .iter()
+            .enumerate()
+            .map(|(level, &sum)| mean(sum).unwrap_or(white + step * level as f64))
+            .collect();
+
+        let squares: f64 = known
+            .iter()
+            .map(|&(i, level)| (brightness[i] as f64 - centroids[level as usize]).powi(2))
+            .sum();
+        let noise = (squares / known.len().max(1) as f64).sqrt().max(MIN_NOISE);
+
+        Self {
+            centroids,
+            noise,
+            bits: (levels as u32).trailing_zeros(),
+        }
+    }
+
+    /// Nearest level to `brightness`, and the runner-up
+    fn nearest(&self, brightness: u8) -> (usize, usize) {
+        let distance = |level: usize| (brightness as f64 - self.centroids[level]).abs();
+        let mut order: Vec<usize> = (0..self.centroids.len()).collect();
+        order.sort_by(|&a, &b| distance(a).total_cmp(&distance(b)));
+        (order[0], order[1])
+    }
+
+    /// Log-likelihood ratio per bit (most significant first) that it is 1
+    ///
+    /// Uses the max-log approximation: the distance to the nearest level
+    /// with the bit clear against the nearest level with it set.
+    pub fn soft_bits(&self, brightness: u8, out: &mut Vec<f32>) {
+        for bit in (0..self.bits).rev() {
+            let mut nearest = [f64::INFINITY; 2];
+            for (level, &centroid) in self.centroids.iter().enumerate() {
+                let value = (to_gray(level as u8) >> bit) & 1;
+                let distance = (brightness as f64 - centroid).powi(2);
+                nearest[value as usize] = nearest[value as usize].min(distance);
+            }
+            out.push(((nearest[0] - nearest[1]) / (2.0 * self.noise.powi(2))) as f32);
+        }
+    }
+
+    /// Diagnostics for a frame decoded with this model
+    pub fn report(&self, brightness: &[u8], known: &[(usize, u8)], data: &[usize]) -> FrameReport {
+        let white_level = self.centroids[0];
+        let black_level = self.centroids[self.centroids.len() - 1];
+
+        let calibration_errors = known
+            .iter()
+            .filter(|&&(i, level)| self.nearest(brightness[i]).0 != level as usize)
+            .count();
+
+        // Margin: 1.0 on a level, 0.0 on the boundary with the runner-up
+        let mut margin_sum = 0.0;
+        let mut uncertain_blocks = 0;
+        for &i in data {
+            let (first, second) = self.nearest(brightness[i]);
+            let value = brightness[i] as f64;
+            let spacing = (self.centroids[first] - self.centroids[second])
+                .abs()
+                .max(1.0);
+            let margin = ((value - self.centroids[second]).abs()
+                - (value - self.centroids[first]).abs())
+                / spacing;
+            margin_sum += margin.clamp(0.0, 1.0);
+            if margin < UNCERTAIN_MARGIN {
+                uncertain_blocks += 1;
+            }
+        }
+        let confidence = if data.is_empty() {
+            1.0
+        } else {
+            margin_sum / data.len() as f64
+        };
+
+        FrameReport {
+            threshold: ((white_level + black_level) / 2.0).clamp(0.0, 255.0) as u8,
+            black_level,
+            white_level,
+            confidence,
+            uncertain_blocks,
+            calibration_errors,
+            estimated_bit_error_rate: self.bit_error_rate(),
+            corrected_symbols: 0,
+        }
+    }
+
+    /// Gaussian estimate: blocks cross into a neighbouring level when noise
+    /// exceeds half the spacing, and Gray coding makes that one bit error
+    fn bit_error_rate(&self) -> f64 {
+        let mut sorted = self.centroids.clone();
+        sorted.sort_by(f64::total_cmp);
+
+        let crossings: f64 = sorted
+            .windows(2)
+            .map(|pair| {
+                let half_spacing = (pair[1] - pair[0]) / 2.0;
+                // A boundary can be crossed from either side
+                2.0 * 0.5 * erfc(half_spacing / (self.noise * std::f64::consts::SQRT_2))
+            })
+            .sum();
+
+        crossings / sorted.len() as f64 / self.bits as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gray_mapping_neighbours_differ_by_one_bit() {
+        for levels in [2u8, 4, 8, 16] {
+            let bits = bits_per_block(levels).unwrap();
+            assert_eq!(1 << bits, levels as u32);
+
+            let symbols: Vec<u8> = (0..levels).map(to_gray).collect();
+            for pair in symbols.windows(2) {
+                assert_eq!((pair[0] ^ pair[1]).count_ones(), 1);
+            }
+            for symbol in 0..levels {
+                assert_eq!(to_gray(symbol_level(symbol)), symbol);
+            }
+        }
+
+        assert!(bits_per_block(3).is_err());
+        assert!(bits_per_block(32).is_err());
+    }
+
+    #[test]
+    fn test_soft_bits_follow_measured_levels() {
+        // Four levels squeezed into 60..200 by a re-encode
+        let brightness = [200u8, 153, 107, 60, 150, 62];
+        let known = [(0, 0), (1, 1), (2, 2), (3, 3)];
+        let model = LevelModel::measure(&brightness, &known, 4);
+
+        let mut soft = Vec::new();
+        model.soft_bits(brightness[4], &mut soft);
+        // Level 1 shows symbol 0b01
+        assert!(soft[0] < 0.0 && soft[1] > 0.0);
+
+        soft.clear();
+        model.soft_bits(brightness[5], &mut soft);
+        // Level 3 shows symbol 0b10
+        assert!(soft[0] > 0.0 && soft[1] < 0.0);
+
+        let report = model.report(&brightness, &known, &[4, 5]);
+        assert_eq!(report.calibration_errors, 0);
+        assert_eq!(report.uncertain_blocks, 0);
+        assert!(report.confidence > 0.8);
+    }
+}
@@ -0,0 +1,231 @@
+//! Hybrid encoding pipeline
+//!
+//! Chains encoders so the output of each stage is the input of the next
+//! (e.g. zstd, then pixel frames). Decoding runs the stages in reverse.
+//!
+//! The container header records each stage's strategy and parameters, so a
+//! pipeline rebuilt from the header decodes without the original metadata.
+
+use crate::container::ContainerHeader;
+use async_trait::async_trait;
+use isg_core::{EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result};
+use tracing::debug;
+
+/// Encoder that runs a sequence of stages
+pub struct HybridEncoder {
+    /// Stages in encoding order
+    stages: Vec<Box<dyn Encoder>>,
+
+    /// Hybrid strategy listing each stage's strategy
+    strategy: EncodingStrategy,
+}
+
+impl HybridEncoder {
+    /// Create a pipeline from stages in encoding order
+    pub fn new(stages: Vec<Box<dyn Encoder>>) -> Self {
+        let strategy = EncodingStrategy::Hybrid {
+            strategies: stages
+                .iter()
+                .map(|stage| Box::new(stage.strategy().clone()))
+                .collect(),
+        };
+
+        Self { stages, strategy }
+    }
+
+    /// Add a stage to the end of the pipeline
+    pub fn then(self, stage: impl Encoder + 'static) -> Self {
+        let mut stages = self.stages;
+        stages.push(Box::new(stage));
+        Self::new(stages)
+    }
+
+    /// Number of stages
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Check whether the pipeline has no stages
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+}
+
+#[async_trait]
+impl Encoder for HybridEncoder {
+    async fn encode(&self, data: &[u8]) -> Result<EncodedData> {
+        debug!(
+            "Encoding {} bytes through {} stages",
+            data.len(),
+            self.stages.len()
+        );
+
+        let mut current = data.to_vec();
+        let mut format = "raw".to_string();
+        let mut stage_metadata = Vec::with_capacity(self.stages.len());
+
+        for stage in &self.stages {
+            let encoded = stage.encode(&current).await?;
+            current = encoded.data;
+            format = encoded.format;
+            stage_metadata.push(encoded.metadata);
+        }
+
+        let parameters = serde_json::json!({ "stages": stage_metadata });
+        let header = ContainerHeader::new(self.strategy.clone(), data, parameters.clone());
+        let encoded = header.wrap(&current)?;
+
+        let metadata = EncodingMetadata {
+            original_size: data.len(),
+            encoded_size: encoded.len(),
+            compression_ratio: encoded.len() as f64 / data.len() as f64,
+            strategy: "hybrid".to_string(),
+            parameters,
+        };
+
+        Ok(EncodedData {
+            data: encoded,
+            format,
+            metadata,
+        })
+    }
+
+    async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
+        let (header, payload) = ContainerHeader::parse(&encoded.data)?;
+        let EncodingStrategy::Hybrid { strategies } = &header.strategy else {
+            return Err(Error::Decoding(format!(
+                "Expected hybrid encoding, found {}",
+                header.strategy.kind()
+            )));
+        };
+        if strategies.len() != self.stages.len() {
+            return Err(Error::Decoding(format!(
+                "Expected hybrid encoding with {} stages, found {}",
+                self.stages.len(),
+                strategies.len()
+            )));
+        }
+        for (index, (recorded, stage)) in strategies.iter().zip(&self.stages).enumerate() {
+            if recorded.kind() != stage.strategy().kind() {
+                return Err(Error::Decoding(format!(
+                    "Stage {} was encoded with {}, not {}",
+                    index,
+                    recorded.kind(),
+                    stage.strategy().kind()
+                )));
+            }
+        }
+
+        // Containers written before stages were recorded in the header only
+        // carry them in the encoded metadata
+        let stages = if header.parameters["stages"].is_array() {
+            &header.parameters["stages"]
+        } else {
+            &encoded.metadata.parameters["stages"]
+        };
+        let stage_metadata: Vec<EncodingMetadata> = match stages {
+            serde_json::Value::Null => Vec::new(),
+            stages => serde_json::from_value(stages.clone())
+                .map_err(|e| Error::Corruption(format!("Invalid hybrid stage list: {}", e)))?,
+        };
+        if !stage_metadata.is_empty() && stage_metadata.len() != self.stages.len() {
+            return Err(Error::Corruption(format!(
+                "Hybrid header lists {} stages for a {} stage pipeline",
+                stage_metadata.len(),
+                self.stages.len()
+            )));
+        }
+
+        let mut current = payload.to_vec();
+        for (index, stage) in self.stages.iter().enumerate().rev() {
+            debug!("Unwinding stage {} ({})", index, stage.strategy().kind());
+
+            let mut intermediate = EncodedData::from_bytes(current);
+            if let Some(metadata) = stage_metadata.get(index) {
+                intermediate.metadata = metadata.clone();
+            }
+            current = stage.decode(&intermediate).await?;
+        }
+
+        header.verify(&current)?;
+        Ok(current)
+    }
+
+    fn strategy(&self) -> &EncodingStrategy {
+        &self.strategy
+    }
+
+    fn estimate_size(&self, input_size: usize) -> usize {
+        self.stages
+            .iter()
+            .fold(input_size, |size, stage| stage.estimate_size(size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressionEncoder, EncoderRegistry, PixelEncoder};
+
+    fn zstd_then_pixel() -> HybridEncoder {
+        HybridEncoder::new(vec![
+            Box::new(CompressionEncoder::zstd(3)),
+            Box::new(PixelEncoder::new().with_resolution(320, 240)),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_roundtrip() {
+        let encoder = zstd_then_pixel();
+        let data = b"compressed, then drawn as pixels ".repeat(20);
+
+        let encoded = encoder.encode(&data).await.unwrap();
+        assert_eq!(encoded.format, "png_sequence");
+
+        let stages = encoded.metadata.parameters["stages"].as_array().unwrap();
+        assert_eq!(stages.len(), 2);
+        assert_eq!(stages[0]["strategy"], "compression");
+        assert_eq!(stages[1]["strategy"], "pixel");
+
+        let decoded = encoder.decode(&encoded).await.unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[tokio::test]
+    async fn test_registry_rebuilds_pipeline() {
+        let encoder = HybridEncoder::new(vec![Box::new(CompressionEncoder::gzip(6))])
+            .then(CompressionEncoder::brotli(4));
+        let data = b"two compression passes".repeat(8);
+        let mut encoded = encoder.encode(&data).await.unwrap();
+
+        // Stage parameters travel in the container header
+        encoded.metadata.parameters = serde_json::Value::Null;
+        let (header, _) = ContainerHeader::parse(&encoded.data).unwrap();
+        let stages = header.parameters["stages"].as_array().unwrap();
+        assert_eq!(stages.len(), 2);
+        assert_eq!(stages[1]["parameters"]["codec"], "brotli-4");
+
+        let registry = EncoderRegistry::new();
+        assert_eq!(registry.decode(&encoded).await.unwrap(), data);
+
+        let rebuilt = registry.build(encoder.strategy()).unwrap();
+        assert_eq!(
+            serde_json::to_value(rebuilt.strategy()).unwrap(),
+            serde_json::to_value(encoder.strategy()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_mismatched_stages() {
+        let data = b"gzip, then brotli".repeat(4);
+        let encoded = HybridEncoder::new(vec![Box::new(CompressionEncoder::gzip(6))])
+            .then(CompressionEncoder::brotli(4))
+            .encode(&data)
+            .await
+            .unwrap();
+
+        let swapped = HybridEncoder::new(vec![Box::new(PixelEncoder::new())])
+            .then(CompressionEncoder::brotli(4));
+        assert!(swapped.decode(&encoded).await.is_err());
+    }
+}
@@ -5,16 +5,44 @@
 //! This crate provides multiple encoding strategies:
 //! - Pixel encoding (black/white)
 //! - Color encoding (RGB-based)
-//! - QR code encoding
+//! - QR code encoding, one code per frame or tiled into mosaics
+//! - Steganography in user-supplied cover images (LSB, or DCT to survive JPEG)
 //! - Raw compression
+//! - DNA oligos in FASTA (experimental)
+//! - Hybrid pipelines chaining the above
+//! - A lossy channel simulator for measuring robustness
 //! - And more!
 
-pub mod pixel;
+pub mod channel;
 pub mod color;
-pub mod qr;
 pub mod compression;
+pub mod container;
+pub mod dct;
+pub mod dna;
+pub mod ecc;
+pub mod geometry;
+pub mod gray;
+pub mod hybrid;
+pub mod mosaic;
+pub mod pixel;
+pub mod qr;
+pub mod registry;
+pub mod sequence;
+pub mod stego;
+pub mod threshold;
+pub mod video;
 
-pub use pixel::PixelEncoder;
+pub use channel::{Channel, ChannelReport, Impairment, RawDecoder};
 pub use color::ColorEncoder;
-pub use qr::QREncoder;
 pub use compression::CompressionEncoder;
+pub use container::ContainerHeader;
+pub use dna::DNAEncoder;
+pub use hybrid::HybridEncoder;
+pub use mosaic::MosaicLayout;
+pub use pixel::{Combining, PixelEncoder};
+pub use qr::QREncoder;
+pub use registry::{EncoderFactory, EncoderRegistry};
+pub use sequence::FrameHeader;
+pub use stego::StegoEncoder;
+pub use threshold::{FrameReport, Thresholding};
+pub use video::{AviCodec, VideoFormat, VideoReader};
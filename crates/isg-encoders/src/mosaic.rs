@@ -0,0 +1,329 @@
+//! Tiled QR mosaic frames
+//!
+//! One QR code per image wastes most of a video frame. In mosaic mode the
+//! [`crate::QREncoder`] tiles each full-resolution frame with a grid of
+//! codes, each surrounded by its quiet zone. Every code starts with a
+//! [`FrameHeader`] (stream id, sequence number, checksum), so codes can be
+//! read in whatever order the scanner finds them and put back in sequence,
+//! across frames, by a [`Reassembler`].
+//!
+//! The last cell of each frame holds a parity code: the XOR of the frame's
+//! data codes, headers included. Its own header marks it with
+//! [`PARITY_FLAG`] and the frame number, and records how many data codes it
+//! covers, so one unreadable code per frame is rebuilt from the others.
+//!
+//! Data codes are padded to full size so every code covered by a parity code
+//! has the same length; the container's original size trims the padding.
+
+use crate::sequence::{FrameHeader, Reassembler, FRAME_HEADER_LEN};
+use isg_core::{Error, Result};
+use std::collections::BTreeMap;
+use tracing::{debug, trace};
+
+/// Sequence number bit marking a parity code (the rest is the frame number)
+pub const PARITY_FLAG: u32 = 1 << 31;
+
+/// Modules of quiet zone on each side of a code
+const QUIET_ZONE: u32 = 4;
+
+/// Grid of QR codes tiling a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MosaicLayout {
+    /// Codes per row
+    pub columns: u32,
+
+    /// Rows of codes
+    pub rows: u32,
+
+    /// Side of a code in pixels, quiet zone included
+    pub side: u32,
+
+    /// Top-left corner of the grid, which is centred in the frame
+    origin: (u32, u32),
+}
+
+impl MosaicLayout {
+    /// Grid of codes `modules` wide drawn at `module_size` pixels per module
+    /// in a frame of `resolution`
+    pub fn new(resolution: (u32, u32), modules: u32, module_size: u32) -> Result<Self> {
+        if module_size == 0 {
+            return Err(Error::Config("Module size must be at least 1".to_string()));
+        }
+
+        let (width, height) = resolution;
+        let side = (modules + 2 * QUIET_ZONE)
+            .checked_mul(module_size)
+            .ok_or_else(|| Error::Config(format!("Module size {} is too large", module_size)))?;
+        let (columns, rows) = (width / side, height / side);
+        if columns * rows < 2 {
+            return Err(Error::Config(format!(
+                "A {}x{} frame fits {} codes of {} pixels; a mosaic needs at least 2",
+                width,
+                height,
+                columns * rows,
+                side
+            )));
+        }
+
+        Ok(Self {
+            columns,
+            rows,
+            side,
+            origin: ((width - columns * side) / 2, (height - rows * side) / 2),
+        })
+    }
+
+    /// Codes per frame, parity code included
+    pub fn cells(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    /// Data codes per frame
+    pub fn data_codes(&self) -> usize {
+        self.cells() - 1
+    }
+
+    /// Top-left pixel of cell `cell`, in reading order
+    pub fn position(&self, cell: usize) -> (u32, u32) {
+        let (column, row) = (cell as u32 % self.columns, cell as u32 / self.columns);
+        (
+            self.origin.0 + column * self.side,
+            self.origin.1 + row * self.side,
+        )
+    }
+}
+
+/// Bytes of a data code: its header, then `payload` padded to `len` bytes
+pub fn data_code(header: FrameHeader, payload: &[u8], len: usize) -> Vec<u8> {
+    let mut padded = payload.to_vec();
+    padded.resize(len - FRAME_HEADER_LEN, 0);
+    header.wrap(&padded)
+}
+
+/// Bytes of the parity code for frame `frame`, covering `codes` (data codes
+/// of equal length)
+pub fn parity_code(stream_id: u32, frame: u32, codes: &[Vec<u8>]) -> Vec<u8> {
+    let header = FrameHeader {
+        stream_id,
+        index: PARITY_FLAG | frame,
+        total: Some(codes.len() as u32),
+    };
+    header.wrap(&xor(codes.iter().map(Vec::as_slice)))
+}
+
+/// XOR of equal-length byte strings
+fn xor<'a>(parts: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+    for part in parts {
+        out.resize(out.len().max(part.len()), 0);
+        for (o, &b) in out.iter_mut().zip(part) {
+            *o ^= b;
+        }
+    }
+    out
+}
+
+/// Puts the codes found in mosaic frames back in order, rebuilding one
+/// missing data code per frame from its parity code
+pub struct MosaicReader {
+    /// Orders data codes across frames
+    reassembler: Reassembler,
+
+    /// Stream the codes must belong to
+    stream_id: u32,
+
+    /// Data codes per frame
+    data_codes: usize,
+
+    /// Data codes rebuilt from parity
+    recovered: usize,
+}
+
+impl MosaicReader {
+    /// Read codes of stream `stream_id`, laid out `data_codes` per frame
+    pub fn new(stream_id: u32, data_codes: usize) -> Self {
+        Self {
+            reassembler: Reassembler::new(stream_id),
+            stream_id,
+            data_codes,
+            recovered: 0,
+        }
+    }
+
+    /// Add the codes read from one frame, in any order, returning the
+    /// payloads that are now next in sequence
+    pub fn push_frame(&mut self, codes: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        let mut data: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+        let mut parities = Vec::new();
+        let mut others = Vec::new();
+
+        for code in codes {
+            match FrameHeader::parse(&code) {
+                Ok((header, _)) if header.stream_id == self.stream_id => {
+                    if header.index & PARITY_FLAG != 0 {
+                        parities.push((header, code));
+                    } else {
+                        data.insert(header.index, code);
+                    }
+                }
+                // The reassembler counts or skips these
+                _ => others.push(code),
+            }
+        }
+
+        for (header, code) in parities {
+            // A parity code covers at most one frame of data codes, all of
+            // them addressable
+            let covered = header.total.unwrap_or(0) as usize;
+            let range = ((header.index & !PARITY_FLAG) as usize)
+                .checked_mul(self.data_codes)
+                .filter(|_| covered <= self.data_codes)
+                .and_then(|first| {
+                    let end = u32::try_from(first + covered).ok()?;
+                    Some(first as u32..end)
+                });
+            let Some(range) = range else {
+                trace!(
+                    "Ignoring parity code {} claiming {} codes",
+                    header.index & !PARITY_FLAG,
+                    covered
+                );
+                continue;
+            };
+            let missing: Vec<u32> = range
+                .clone()
+                .filter(|index| !data.contains_key(index))
+                .collect();
+
+            match missing[..] {
+                [] => {}
+                [index] => {
+                    let present = data.range(range);
+                    let rebuilt = xor(std::iter::once(&code[FRAME_HEADER_LEN..])
+                        .chain(present.map(|(_, code)| code.as_slice())));
+                    debug!("Rebuilt QR code {} from parity", index);
+                    self.recovered += 1;
+                    data.insert(index, rebuilt);
+                }
+                _ => trace!(
+                    "Parity for codes {:?} can't replace {} missing codes",
+                    range,
+                    missing.len()
+                ),
+            }
+        }
+
+        let mut ready = Vec::new();
+        for code in data.into_values().chain(others) {
+            ready.extend(self.reassembler.push(&code));
+        }
+        ready
+    }
+
+    /// Check that no data code is missing
+    pub fn finish(&self) -> Result<()> {
+        self.reassembler.finish()
+    }
+
+    /// Data codes rebuilt from parity so far
+    pub fn recovered(&self) -> usize {
+        self.recovered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(index: u32, total: Option<u32>, payload: &[u8]) -> Vec<u8> {
+        let header = FrameHeader {
+            stream_id: 5,
+            index,
+            total,
+        };
+        data_code(header, payload, 24)
+    }
+
+    #[test]
+    fn test_layout_centres_grid() {
+        let layout = MosaicLayout::new((640, 360), 37, 3).unwrap();
+        assert_eq!((layout.columns, layout.rows, layout.side), (4, 2, 135));
+        assert_eq!(layout.data_codes(), 7);
+        assert_eq!(layout.position(0), (50, 45));
+        assert_eq!(layout.position(5), (185, 180));
+
+        assert!(MosaicLayout::new((200, 200), 37, 3).is_err());
+        assert!(MosaicLayout::new((640, 360), 37, 0).is_err());
+    }
+
+    #[test]
+    fn test_parity_rebuilds_one_code_per_frame() {
+        // Two frames of three data codes, the second only partly filled
+        let codes: Vec<Vec<u8>> = (0..5)
+            .map(|i| code(i, (i == 4).then_some(5), &[i as u8; 3]))
+            .collect();
+        let parities = [
+            parity_code(5, 0, &codes[..3]),
+            parity_code(5, 1, &codes[3..]),
+        ];
+
+        let mut reader = MosaicReader::new(5, 3);
+        let mut out = Vec::new();
+        // Frame 0 lost code 1; frame 1 arrives first and lost code 4
+        out.extend(reader.push_frame(vec![codes[3].clone(), parities[1].clone()]));
+        assert!(out.is_empty());
+        out.extend(reader.push_frame(vec![
+            parities[0].clone(),
+            codes[2].clone(),
+            codes[0].clone(),
+        ]));
+
+        assert_eq!(out.len(), 5);
+        for (i, payload) in out.iter().enumerate() {
+            assert_eq!(&payload[..3], &[i as u8; 3]);
+        }
+        assert_eq!(reader.recovered(), 2);
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn test_two_missing_codes_are_reported() {
+        let codes: Vec<Vec<u8>> = (0..3)
+            .map(|i| code(i, (i == 2).then_some(3), b"x"))
+            .collect();
+
+        let mut reader = MosaicReader::new(5, 3);
+        reader.push_frame(vec![codes[2].clone(), parity_code(5, 0, &codes)]);
+
+        let Err(Error::Corruption(message)) = reader.finish() else {
+            panic!("missing codes not reported");
+        };
+        assert!(message.contains("[0, 1]"), "{}", message);
+    }
+
+    #[test]
+    fn test_oversized_parity_is_ignored() {
+        let codes: Vec<Vec<u8>> = (0..3)
+            .map(|i| code(i, (i == 2).then_some(3), b"x"))
+            .collect();
+        let parity = xor(codes.iter().map(Vec::as_slice));
+        let forged = |index: u32, total: u32| {
+            FrameHeader {
+                stream_id: 5,
+                index: PARITY_FLAG | index,
+                total: Some(total),
+            }
+            .wrap(&parity)
+        };
+
+        let mut reader = MosaicReader::new(5, 3);
+        reader.push_frame(vec![
+            codes[0].clone(),
+            codes[2].clone(),
+            forged(0, u32::MAX),
+            forged(PARITY_FLAG - 1, 3),
+        ]);
+        assert_eq!(reader.recovered(), 0);
+        assert!(reader.finish().is_err());
+    }
+}
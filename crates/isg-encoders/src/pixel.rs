@@ -1,27 +1,102 @@
 //! Black/white pixel encoding
 //!
 //! This encoder converts binary data into black (1) and white (0) pixels,
-//! then generates image frames that can be combined into a video.
-
+//! then generates image frames that can be combined into a video. Blocks can
+//! also use 4 to 16 gray levels to carry several bits each (see
+//! [`crate::gray`]).
+//!
+//! The top row of blocks in each frame holds calibration cells (alternating
+//! black and white) that the decoder uses to pick its threshold per frame.
+//! By default each corner also carries an alignment marker, so frames can be
+//! decoded after rescaling, cropping or letterboxing (see [`crate::geometry`]).
+//! Each frame's data starts with a frame header (see [`crate::sequence`]), so
+//! frames dropped, duplicated or reordered by a platform are detected or put
+//! back in order. Frames can also carry Reed–Solomon check bytes (see
+//! [`crate::ecc`]) to correct misread blocks.
+//!
+//! For compression resistance each frame can be shown several times in a
+//! row, optionally shifted by a fraction of a block so codec artifacts land
+//! differently on every copy. The decoder combines the copies of each frame,
+//! by averaging block brightness or by majority vote, before classifying.
+//!
+//! Frames are written as a PNG sequence by default, or as a Y4M or AVI video
+//! at the configured frame rate (see [`crate::video`]).
+
+use crate::container::{self, ContainerHeader};
+use crate::ecc::FrameCode;
+use crate::geometry::{self, Cell, FrameLayout};
+use crate::gray::{self, LevelModel};
+use crate::sequence::{self, FrameHeader, Reassembler, FRAME_HEADER_LEN};
+use crate::threshold::{self, FrameReport, Thresholding};
+use crate::video::{self, FrameReader, FrameWriter, VideoFormat};
 use async_trait::async_trait;
-use image::{ImageBuffer, Luma, Rgba, RgbaImage};
-use isg_core::{EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result};
+use image::{Rgba, RgbaImage};
+use isg_core::{
+    ByteReader, ByteWriter, EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result,
+};
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::{debug, trace};
 
+/// How the copies of a repeated frame are combined when decoding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Combining {
+    /// Average the brightness of each block across copies, then classify
+    #[default]
+    Average,
+
+    /// Classify each copy, then take the majority decision for each bit
+    Majority,
+}
+
 /// Pixel encoder configuration
+///
+/// Settings are changed through the `with_*` builders and read through
+/// getters, which keeps [`Encoder::strategy`] in step with them.
 #[derive(Clone, Debug)]
 pub struct PixelEncoder {
     /// Block size (e.g., 4 means 4x4 pixels per bit)
-    pub block_size: u32,
+    block_size: u32,
 
     /// Resolution (width, height)
-    pub resolution: (u32, u32),
+    resolution: (u32, u32),
+
+    /// Frames per second of video output
+    fps: u32,
+
+    /// Gray levels per block (2 = black/white)
+    levels: u8,
+
+    /// How blocks are classified when decoding
+    thresholding: Thresholding,
+
+    /// Write a row of calibration cells at the top of each frame
+    calibration: bool,
+
+    /// Write alignment markers in the corners of each frame
+    alignment: bool,
 
-    /// Frames per second (for video generation)
-    pub fps: u32,
+    /// Start each frame's data with a frame header
+    frame_headers: bool,
 
-    /// Threshold for reading pixels (0-255)
-    pub threshold: u8,
+    /// Reed–Solomon check bytes per codeword (0 = no error correction)
+    parity: usize,
+
+    /// Times each frame is shown in a row
+    redundancy: u32,
+
+    /// Shift each copy of a frame by a fraction of a block
+    shifts: bool,
+
+    /// How copies are combined when decoding
+    combining: Combining,
+
+    /// How frames are written
+    video_format: VideoFormat,
+
+    /// Strategy describing the settings above
+    strategy: EncodingStrategy,
 }
 
 impl PixelEncoder {
@@ -31,41 +106,444 @@ impl PixelEncoder {
             block_size: 4,
             resolution: (1920, 1080),
             fps: 30,
-            threshold: 128,
+            levels: 2,
+            thresholding: Thresholding::default(),
+            calibration: true,
+            alignment: true,
+            frame_headers: true,
+            parity: 0,
+            redundancy: 1,
+            shifts: false,
+            combining: Combining::default(),
+            video_format: VideoFormat::default(),
+            strategy: EncodingStrategy::PixelEncoding {
+                block_size: 4,
+                fps: 30,
+                resolution: (1920, 1080),
+                levels: 2,
+            },
         }
     }
 
     /// Create with custom block size
     pub fn with_block_size(mut self, block_size: u32) -> Self {
         self.block_size = block_size;
-        self
+        self.refresh_strategy()
     }
 
     /// Create with custom resolution
     pub fn with_resolution(mut self, width: u32, height: u32) -> Self {
         self.resolution = (width, height);
-        self
+        self.refresh_strategy()
     }
 
     /// Create with custom FPS
     pub fn with_fps(mut self, fps: u32) -> Self {
         self.fps = fps;
+        self.refresh_strategy()
+    }
+
+    /// Create with a custom number of gray levels per block
+    ///
+    /// Must be a power of two from 2 to 16; each block then carries log2 of
+    /// that many bits. Multi-level frames are read against the levels
+    /// measured from the calibration cells rather than a threshold.
+    pub fn with_levels(mut self, levels: u8) -> Self {
+        self.levels = levels;
+        self.refresh_strategy()
+    }
+
+    /// Decode with a fixed read threshold (0-255) instead of an adaptive one
+    pub fn with_threshold(self, threshold: u8) -> Self {
+        self.with_thresholding(Thresholding::Fixed(threshold))
+    }
+
+    /// Create with a custom thresholding mode for decoding
+    pub fn with_thresholding(mut self, thresholding: Thresholding) -> Self {
+        self.thresholding = thresholding;
+        self
+    }
+
+    /// Enable or disable calibration cells in encoded frames
+    pub fn with_calibration(mut self, calibration: bool) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Enable or disable alignment markers in encoded frames
+    ///
+    /// Markers cost a border of blocks but let frames survive rescaling and
+    /// small shifts. Frames need at least 19x19 blocks to fit them.
+    pub fn with_alignment(mut self, alignment: bool) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Enable or disable frame headers in encoded frames
+    ///
+    /// Headers cost 16 bytes per frame but let the decoder reorder frames,
+    /// skip duplicates and detect missing ones.
+    pub fn with_frame_headers(mut self, frame_headers: bool) -> Self {
+        self.frame_headers = frame_headers;
+        self
+    }
+
+    /// Protect each frame with Reed–Solomon codewords carrying `parity` check
+    /// bytes each (0 disables error correction)
+    ///
+    /// Each codeword corrects up to `parity / 2` misread bytes. Codewords are
+    /// interleaved across the frame, so a damaged region is shared out
+    /// between them.
+    pub fn with_error_correction(mut self, parity: usize) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Show each frame `copies` times in a row
+    ///
+    /// At `fps` frames per second, data then moves at `fps / copies` frames
+    /// per second. With frame headers, copies are grouped by the header they
+    /// read back when decoding, so lost or reordered copies are tolerated;
+    /// without headers they are grouped by position.
+    pub fn with_redundancy(mut self, copies: u32) -> Self {
+        self.redundancy = copies;
+        self
+    }
+
+    /// Shift copies of a frame by evenly spaced fractions of a block
+    pub fn with_shifts(mut self, shifts: bool) -> Self {
+        self.shifts = shifts;
+        self
+    }
+
+    /// Create with a custom way of combining copies when decoding
+    pub fn with_combining(mut self, combining: Combining) -> Self {
+        self.combining = combining;
         self
     }
 
+    /// Write frames as a video file instead of a PNG sequence
+    ///
+    /// Y4M streams one frame at a time. AVI headers record sizes known only
+    /// once every frame is drawn, so `encode_stream` and `decode_stream`
+    /// refuse AVI; use `encode` and `decode` for it.
+    pub fn with_video_format(mut self, video_format: VideoFormat) -> Self {
+        self.video_format = video_format;
+        self
+    }
+
+    /// Block size in pixels
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Frame resolution (width, height)
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    /// Frames per second of video output
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Fixed read threshold, if one was set with [`PixelEncoder::with_threshold`]
+    pub fn threshold(&self) -> Option<u8> {
+        match self.thresholding {
+            Thresholding::Fixed(threshold) => Some(threshold),
+            _ => None,
+        }
+    }
+
+    /// How blocks are classified when decoding
+    pub fn thresholding(&self) -> Thresholding {
+        self.thresholding
+    }
+
+    /// Gray levels per block (2 = black/white)
+    pub fn levels(&self) -> u8 {
+        self.levels
+    }
+
+    /// Whether encoded frames carry calibration cells
+    pub fn calibration(&self) -> bool {
+        self.calibration
+    }
+
+    /// Whether encoded frames carry alignment markers
+    pub fn alignment(&self) -> bool {
+        self.alignment
+    }
+
+    /// Whether encoded frames carry frame headers
+    pub fn frame_headers(&self) -> bool {
+        self.frame_headers
+    }
+
+    /// Reed–Solomon check bytes per codeword (0 = no error correction)
+    pub fn error_correction(&self) -> usize {
+        self.parity
+    }
+
+    /// Times each frame is shown in a row
+    pub fn redundancy(&self) -> u32 {
+        self.redundancy
+    }
+
+    /// Whether copies of a frame are shifted by fractions of a block
+    pub fn shifts(&self) -> bool {
+        self.shifts
+    }
+
+    /// How copies are combined when decoding
+    pub fn combining(&self) -> Combining {
+        self.combining
+    }
+
+    /// How frames are written
+    pub fn video_format(&self) -> VideoFormat {
+        self.video_format
+    }
+
+    /// Recompute the strategy after a setting changed
+    fn refresh_strategy(mut self) -> Self {
+        self.strategy = EncodingStrategy::PixelEncoding {
+            block_size: self.block_size,
+            fps: self.fps,
+            resolution: self.resolution,
+            levels: self.levels,
+        };
+        self
+    }
+
+    /// Decoder for data encoded with the settings recorded in `header`
+    ///
+    /// The settings come from untrusted data, so they are validated before
+    /// anything is allocated for them.
+    fn decoder_for(&self, header: &ContainerHeader) -> Result<Self> {
+        let EncodingStrategy::PixelEncoding {
+            block_size,
+            fps,
+            resolution,
+            levels,
+        } = header.strategy
+        else {
+            return Err(Error::Decoding(format!(
+                "Expected pixel encoding, found {:?}",
+                header.strategy
+            )));
+        };
+
+        let decoder = Self {
+            block_size,
+            resolution,
+            fps,
+            levels,
+            // Containers written before calibration cells or alignment
+            // markers record no flag
+            calibration: header.parameters["calibration"].as_bool().unwrap_or(false),
+            alignment: header.parameters["alignment"].as_bool().unwrap_or(false),
+            frame_headers: header.parameters["frame_headers"]
+                .as_bool()
+                .unwrap_or(false),
+            parity: container::parameter(header, "ecc_parity")?.unwrap_or(0),
+            redundancy: container::parameter(header, "redundancy")?.unwrap_or(1),
+            shifts: header.parameters["shifts"].as_bool().unwrap_or(false),
+            ..self.clone()
+        }
+        .refresh_strategy();
+
+        decoder.validate().map_err(container::corrupt_settings)?;
+        Ok(decoder)
+    }
+
+    /// Parameters recorded alongside the strategy
+    fn parameters(&self) -> serde_json::Value {
+        serde_json::json!({
+            "block_size": self.block_size,
+            "resolution": self.resolution,
+            "fps": self.fps,
+            "levels": self.levels,
+            "calibration": self.calibration,
+            "alignment": self.alignment,
+            "frame_headers": self.frame_headers,
+            "ecc_parity": self.parity,
+            "ecc_rate": self
+                .frame_layout()
+                .and_then(|layout| self.frame_code(&layout))
+                .map_or(1.0, |code| code.rate()),
+            "redundancy": self.redundancy,
+            "shifts": self.shifts,
+        })
+    }
+
+    /// Copies of each frame, failing on zero
+    fn copies(&self) -> Result<usize> {
+        if self.redundancy == 0 {
+            return Err(Error::Config("Redundancy must be at least 1".to_string()));
+        }
+        Ok(self.redundancy as usize)
+    }
+
+    /// Offset in pixels (right and down) of copy `copy` of a frame
+    fn copy_shift(&self, copy: usize) -> u32 {
+        if !self.shifts {
+            return 0;
+        }
+        copy as u32 * self.block_size / self.redundancy.max(1)
+    }
+
+    /// Every copy of a frame, each shifted as configured
+    fn repeat(&self, frame: RgbaImage) -> Result<Vec<RgbaImage>> {
+        let copies = (0..self.copies()?)
+            .map(|copy| shifted(&frame, self.copy_shift(copy)))
+            .collect();
+        Ok(copies)
+    }
+
+    /// Reassembler for the frames of the stream recorded in `header`, if
+    /// frames carry headers
+    fn reassembler(&self, header: &ContainerHeader) -> Result<Option<Reassembler>> {
+        if !self.frame_headers {
+            return Ok(None);
+        }
+
+        let stream_id = header.parameters["stream_id"]
+            .as_u64()
+            .ok_or_else(|| Error::Decoding("Pixel container is missing stream_id".to_string()))?;
+        Ok(Some(Reassembler::new(stream_id as u32)))
+    }
+
+    /// Check the settings before allocating frames for them
+    fn validate(&self) -> Result<()> {
+        container::check_frame_size(self.resolution, self.block_size)?;
+        gray::bits_per_block(self.levels)?;
+        self.copies()?;
+        Ok(())
+    }
+
+    /// Arrangement of cells in a frame
+    fn layout(&self) -> Result<FrameLayout> {
+        self.validate()?;
+
+        let (width, height) = self.resolution;
+        FrameLayout::new(
+            width / self.block_size,
+            height / self.block_size,
+            self.levels,
+            self.calibration,
+            self.alignment,
+        )
+    }
+
+    /// Bits carried by each data block
+    fn bits_per_block(&self) -> usize {
+        self.levels.trailing_zeros() as usize
+    }
+
     /// Calculate how many bits fit in one frame
     fn bits_per_frame(&self) -> usize {
-        let (width, height) = self.resolution;
-        let blocks_x = width / self.block_size;
-        let blocks_y = height / self.block_size;
-        (blocks_x * blocks_y) as usize
+        self.layout()
+            .map_or(0, |layout| layout.data.len() * self.bits_per_block())
+    }
+
+    /// Frame layout, failing if a frame cannot hold any data
+    fn frame_layout(&self) -> Result<FrameLayout> {
+        let layout = self.layout()?;
+        if layout.data.is_empty() {
+            return Err(Error::Config(format!(
+                "Resolution {:?} holds no data blocks of size {}",
+                self.resolution, self.block_size
+            )));
+        }
+        Ok(layout)
+    }
+
+    /// Whether frames hold whole bytes (with a header or check bytes) rather
+    /// than a plain run of bits
+    fn framed(&self) -> bool {
+        self.frame_headers || self.parity > 0
+    }
+
+    /// Error correction code over the bytes of a frame
+    fn frame_code(&self, layout: &FrameLayout) -> Result<FrameCode> {
+        FrameCode::new(layout.data.len() * self.bits_per_block() / 8, self.parity)
+    }
+
+    /// Payload bytes per frame after the frame header and check bytes
+    fn payload_per_frame(&self, code: &FrameCode) -> Result<usize> {
+        let header_len = if self.frame_headers {
+            FRAME_HEADER_LEN
+        } else {
+            0
+        };
+        if code.data_len() <= header_len {
+            return Err(Error::Config(format!(
+                "Resolution {:?} with block size {} leaves no room after the frame header",
+                self.resolution, self.block_size
+            )));
+        }
+        Ok(code.data_len() - header_len)
+    }
+
+    /// Frame bytes for one payload: header (if enabled), payload padded to
+    /// fill the frame, then check bytes
+    fn frame_bytes(
+        &self,
+        code: &FrameCode,
+        header: FrameHeader,
+        payload: &[u8],
+    ) -> Result<Vec<u8>> {
+        let payload = padded(payload, self.payload_per_frame(code)?);
+        if self.frame_headers {
+            Ok(code.encode(&header.wrap(&payload)))
+        } else {
+            Ok(code.encode(&payload))
+        }
+    }
+
+    /// Payloads made ready by one frame: corrects its bytes, then puts it in
+    /// order by its header (or takes it as it comes without headers)
+    fn frame_payloads(
+        &self,
+        code: &FrameCode,
+        reassembler: &mut Option<Reassembler>,
+        soft: &[f32],
+        report: &mut FrameReport,
+    ) -> Vec<Vec<u8>> {
+        let (content, corrected, failed) = code.decode(&pack_bytes(soft));
+        report.corrected_symbols = corrected;
+        if failed > 0 {
+            debug!("{} codewords beyond repair", failed);
+        }
+
+        match reassembler {
+            Some(reassembler) => reassembler.push(&content),
+            None => vec![content],
+        }
     }
 
     /// Encode binary data into image frames
-    fn encode_to_frames(&self, data: &[u8]) -> Result<Vec<RgbaImage>> {
-        let bits_per_frame = self.bits_per_frame();
+    fn encode_to_frames(&self, data: &[u8], stream_id: u32) -> Result<Vec<RgbaImage>> {
+        let layout = self.frame_layout()?;
+        let frames = if self.framed() {
+            self.encode_framed(&layout, data, stream_id)?
+        } else {
+            self.encode_bits(&layout, data)?
+        };
+
+        let mut repeated = Vec::with_capacity(frames.len() * self.copies()?);
+        for frame in frames {
+            repeated.extend(self.repeat(frame)?);
+        }
+        Ok(repeated)
+    }
+
+    /// Encode binary data into frames holding a plain run of bits
+    fn encode_bits(&self, layout: &FrameLayout, data: &[u8]) -> Result<Vec<RgbaImage>> {
+        let bits_per_frame = layout.data.len() * self.bits_per_block();
         let total_bits = data.len() * 8;
-        let num_frames = (total_bits + bits_per_frame - 1) / bits_per_frame;
+        let num_frames = total_bits.div_ceil(bits_per_frame);
 
         debug!(
             "Encoding {} bytes ({} bits) into {} frames",
@@ -78,7 +556,7 @@ impl PixelEncoder {
         let mut bit_index = 0;
 
         for frame_idx in 0..num_frames {
-            let frame = self.create_frame(data, &mut bit_index)?;
+            let frame = self.create_frame(layout, data, &mut bit_index)?;
             frames.push(frame);
 
             trace!("Created frame {}/{}", frame_idx + 1, num_frames);
@@ -87,22 +565,76 @@ impl PixelEncoder {
         Ok(frames)
     }
 
+    /// Encode binary data into frames of whole bytes
+    fn encode_framed(
+        &self,
+        layout: &FrameLayout,
+        data: &[u8],
+        stream_id: u32,
+    ) -> Result<Vec<RgbaImage>> {
+        let code = self.frame_code(layout)?;
+        let payload_len = self.payload_per_frame(&code)?;
+        // Even empty data gets a frame, so the decoder learns the total
+        let total = data.len().div_ceil(payload_len).max(1);
+
+        debug!(
+            "Encoding {} bytes into {} frames of stream {:08x}",
+            data.len(),
+            total,
+            stream_id
+        );
+
+        (0..total)
+            .map(|index| {
+                let start = (index * payload_len).min(data.len());
+                let end = (start + payload_len).min(data.len());
+                let header = FrameHeader {
+                    stream_id,
+                    index: index as u32,
+                    total: Some(total as u32),
+                };
+                let bytes = self.frame_bytes(&code, header, &data[start..end])?;
+                self.create_frame(layout, &bytes, &mut 0)
+            })
+            .collect()
+    }
+
     /// Create a single frame from data
-    fn create_frame(&self, data: &[u8], bit_index: &mut usize) -> Result<RgbaImage> {
+    fn create_frame(
+        &self,
+        layout: &FrameLayout,
+        data: &[u8],
+        bit_index: &mut usize,
+    ) -> Result<RgbaImage> {
         let (width, height) = self.resolution;
         let mut img = RgbaImage::new(width, height);
 
-        let blocks_x = width / self.block_size;
-        let blocks_y = height / self.block_size;
-
-        for block_y in 0..blocks_y {
-            for block_x in 0..blocks_x {
-                // Get the bit value (0 or 1)
-                let bit = self.get_bit(data, *bit_index);
-                *bit_index += 1;
-
-                // Color: 0 = white (255), 1 = black (0)
-                let color = if bit { 0 } else { 255 };
+        for block_y in 0..layout.blocks_y {
+            for block_x in 0..layout.blocks_x {
+                let index = (block_y * layout.blocks_x + block_x) as usize;
+
+                // Gray level: 0 = white, levels - 1 = black (a 1 bit when
+                // black/white)
+                let level = match layout.cells[index] {
+                    Cell::Data => {
+                        let mut symbol = 0u8;
+                        for _ in 0..self.bits_per_block() {
+                            symbol = (symbol << 1) | self.get_bit(data, *bit_index) as u8;
+                            *bit_index += 1;
+                        }
+                        gray::symbol_level(symbol)
+                    }
+                    Cell::Calibration(level) => level,
+                    Cell::Marker(black) => {
+                        if black {
+                            self.levels - 1
+                        } else {
+                            0
+                        }
+                    }
+                };
+
+                let color = gray::level_luma(level, self.levels);
                 let pixel = Rgba([color, color, color, 255]);
 
                 // Fill the block
@@ -142,17 +674,60 @@ impl PixelEncoder {
         }
     }
 
-    /// Decode frames back to binary data
-    fn decode_from_frames(&self, frames: &[RgbaImage], expected_size: usize) -> Result<Vec<u8>> {
+    /// Decode frames back to binary data, with a report per frame
+    ///
+    /// With a reassembler, frames are put in stream order from their headers;
+    /// otherwise they are taken in the order given.
+    fn decode_from_frames(
+        &self,
+        frames: &[RgbaImage],
+        expected_size: usize,
+        mut reassembler: Option<Reassembler>,
+    ) -> Result<(Vec<u8>, Vec<FrameReport>)> {
+        let layout = self.frame_layout()?;
+        let code = if self.framed() {
+            Some(self.frame_code(&layout)?)
+        } else {
+            None
+        };
         let mut bits = Vec::new();
+        let mut reports = Vec::with_capacity(frames.len());
+        let mut data = Vec::new();
 
+        let mut reader = CopyReader::new(self, &layout, code.as_ref())?;
         for frame in frames {
-            let frame_bits = self.read_frame(frame)?;
-            bits.extend(frame_bits);
+            reader.push(Cow::Borrowed(frame));
+        }
+
+        while let Some((soft, mut report)) = reader.next(true) {
+            match &code {
+                Some(code) => {
+                    for payload in self.frame_payloads(code, &mut reassembler, &soft, &mut report) {
+                        data.extend(payload);
+                    }
+                }
+                None => bits.extend(hard_decisions(&soft)),
+            }
+            log_report(reports.len(), &report);
+            reports.push(report);
+        }
+
+        if code.is_some() {
+            if let Some(reassembler) = &reassembler {
+                reassembler.finish()?;
+            }
+            if data.len() < expected_size {
+                return Err(Error::Corruption(format!(
+                    "Decoded {} bytes, expected {}",
+                    data.len(),
+                    expected_size
+                )));
+            }
+            data.truncate(expected_size);
+            return Ok((data, reports));
         }
 
         // Convert bits to bytes
-        let mut data = Vec::new();
         for chunk in bits.chunks(8) {
             let mut byte = 0u8;
             for (i, &bit) in chunk.iter().enumerate() {
@@ -168,31 +743,111 @@ impl PixelEncoder {
         }
 
         data.truncate(expected_size);
-        Ok(data)
+        Ok((data, reports))
     }
 
-    /// Read bits from a frame
-    fn read_frame(&self, frame: &RgbaImage) -> Result<Vec<bool>> {
-        let (width, height) = (frame.width(), frame.height());
-        let blocks_x = width / self.block_size;
-        let blocks_y = height / self.block_size;
+    /// Soft decisions and a report from the block brightness of several
+    /// copies of a frame
+    fn combine(&self, layout: &FrameLayout, samples: &[Vec<u8>]) -> (Vec<f32>, FrameReport) {
+        if let [sample] = samples {
+            return self.classify(layout, sample);
+        }
 
-        let mut bits = Vec::new();
+        match self.combining {
+            Combining::Average => {
+                let copies = samples.len() as u32;
+                let averaged: Vec<u8> = (0..samples[0].len())
+                    .map(|i| {
+                        let sum: u32 = samples.iter().map(|sample| sample[i] as u32).sum();
+                        ((sum + copies / 2) / copies) as u8
+                    })
+                    .collect();
+                self.classify(layout, &averaged)
+            }
+            Combining::Majority => {
+                // Votes per bit, and summed soft decisions to break ties
+                let mut votes: Vec<(f32, f32)> = Vec::new();
+                let mut best: Option<FrameReport> = None;
+                for sample in samples {
+                    let (soft, report) = self.classify(layout, sample);
+                    votes.resize(soft.len(), (0.0, 0.0));
+                    for ((vote, sum), llr) in votes.iter_mut().zip(soft) {
+                        *vote += if llr > 0.0 { 1.0 } else { -1.0 };
+                        *sum += llr;
+                    }
+                    if best
+                        .as_ref()
+                        .is_none_or(|best| report.confidence > best.confidence)
+                    {
+                        best = Some(report);
+                    }
+                }
 
-        for block_y in 0..blocks_y {
-            for block_x in 0..blocks_x {
-                let bit = self.read_block(frame, block_x, block_y);
-                bits.push(bit);
+                let soft = votes
+                    .into_iter()
+                    .map(|(vote, sum)| {
+                        if vote != 0.0 {
+                            vote
+                        } else {
+                            sum.signum() * 0.5
+                        }
+                    })
+                    .collect();
+                (soft, best.expect("at least two copies"))
             }
         }
+    }
 
-        Ok(bits)
+    /// Brightness of every block of a frame drawn `shift` pixels right and
+    /// down
+    fn sample_frame(&self, layout: &FrameLayout, frame: &RgbaImage, shift: u32) -> Vec<u8> {
+        if layout.aligned {
+            // Markers move with the frame, so the fitted transform absorbs
+            // the shift
+            let transform = geometry::locate(frame, layout, self.thresholding);
+            geometry::sample(frame, layout, &transform)
+        } else {
+            (0..layout.blocks_y)
+                .flat_map(|y| (0..layout.blocks_x).map(move |x| (x, y)))
+                .map(|(x, y)| self.read_block(frame, x, y, shift))
+                .collect()
+        }
     }
 
-    /// Read a bit from a block by averaging pixels
-    fn read_block(&self, frame: &RgbaImage, block_x: u32, block_y: u32) -> bool {
-        let start_x = block_x * self.block_size;
-        let start_y = block_y * self.block_size;
+    /// Soft decisions for the data bits, and a report, from block brightness
+    fn classify(&self, layout: &FrameLayout, brightness: &[u8]) -> (Vec<f32>, FrameReport) {
+        let mut soft = Vec::with_capacity(layout.data.len() * self.bits_per_block());
+        let report = if self.levels == 2 {
+            let thresholds = threshold::analyze(
+                brightness,
+                layout.blocks_x as usize,
+                &layout.reference,
+                &layout.data,
+                self.thresholding,
+            );
+            soft.extend(
+                layout
+                    .data
+                    .iter()
+                    .map(|&i| thresholds.soft(i, brightness[i])),
+            );
+            thresholds.report
+        } else {
+            let model = LevelModel::measure(brightness, &layout.known, self.levels);
+            for &i in &layout.data {
+                model.soft_bits(brightness[i], &mut soft);
+            }
+            model.report(brightness, &layout.known, &layout.data)
+        };
+
+        (soft, report)
+    }
+
+    /// Read the brightness of a block drawn `shift` pixels right and down by
+    /// averaging pixels
+    fn read_block(&self, frame: &RgbaImage, block_x: u32, block_y: u32, shift: u32) -> u8 {
+        let start_x = block_x * self.block_size + shift;
+        let start_y = block_y * self.block_size + shift;
 
         let mut sum = 0u32;
         let mut count = 0u32;
@@ -212,10 +867,273 @@ impl PixelEncoder {
             }
         }
 
-        let avg = if count > 0 { sum / count } else { 255 };
+        sum.checked_div(count).unwrap_or(255) as u8
+    }
+
+    /// Decode data, also returning a quality report for every frame
+    pub fn decode_with_report(&self, encoded: &EncodedData) -> Result<(Vec<u8>, Vec<FrameReport>)> {
+        let data = &encoded.data;
+
+        if !ContainerHeader::is_container(data) && !video::is_video(data) {
+            // Blobs written before the container header: frame count (4 bytes),
+            // original size (8 bytes), frames; decoded with our own settings
+            if data.len() < 12 {
+                return Err(Error::Decoding("Data too short".to_string()));
+            }
+
+            let frame_count = u32::from_le_bytes(data[..4].try_into().unwrap());
+            let original_size = u64::from_le_bytes(data[4..12].try_into().unwrap()) as usize;
+            let frames = container::read_png_frames(&data[12..], frame_count)?;
+            let legacy = self
+                .clone()
+                .with_levels(2)
+                .with_calibration(false)
+                .with_alignment(false)
+                .with_frame_headers(false)
+                .with_error_correction(0)
+                .with_redundancy(1);
+            return legacy.decode_from_frames(&frames, original_size, None);
+        }
+
+        let (header, frames) = video::read_frames(data)?;
+        let decoder = self.decoder_for(&header)?;
+
+        debug!(
+            "Decoding with block size {} at {:?}, expecting {} bytes",
+            decoder.block_size, decoder.resolution, header.original_size
+        );
+
+        let reassembler = decoder.reassembler(&header)?;
+        let (decoded, reports) =
+            decoder.decode_from_frames(&frames, header.original_size as usize, reassembler)?;
+        header.verify(&decoded)?;
+        Ok((decoded, reports))
+    }
+
+    /// Decode soft decisions: a log-likelihood ratio per data bit, positive
+    /// when the bit is more likely 1
+    ///
+    /// Meant for error-correcting decoders, so the checksum is not verified.
+    /// Frame header and check bits are dropped and frames are kept in the
+    /// order given, skipping copies of a frame already read.
+    pub fn decode_soft(&self, encoded: &EncodedData) -> Result<Vec<f32>> {
+        let (header, frames) = video::read_frames(&encoded.data)?;
+        let decoder = self.decoder_for(&header)?;
+        let layout = decoder.frame_layout()?;
+
+        // Frame bytes holding payload, in order
+        let code = if decoder.framed() {
+            Some(decoder.frame_code(&layout)?)
+        } else {
+            None
+        };
+        let payload_bytes: Option<Vec<usize>> = match &code {
+            Some(code) => {
+                let header_len = code.data_len() - decoder.payload_per_frame(code)?;
+                Some(code.data_positions().skip(header_len).collect())
+            }
+            None => None,
+        };
+
+        let mut reader = CopyReader::new(&decoder, &layout, code.as_ref())?;
+        for frame in &frames {
+            reader.push(Cow::Borrowed(frame));
+        }
+
+        let mut soft = Vec::new();
+        while let Some((frame_soft, _)) = reader.next(true) {
+            match &payload_bytes {
+                Some(positions) => {
+                    for &position in positions {
+                        soft.extend_from_slice(&frame_soft[position * 8..position * 8 + 8]);
+                    }
+                }
+                None => soft.extend(frame_soft),
+            }
+        }
+
+        soft.truncate(header.original_size as usize * 8);
+        Ok(soft)
+    }
+}
+
+/// Bits from soft decisions (positive = 1)
+fn hard_decisions(soft: &[f32]) -> impl Iterator<Item = bool> + '_ {
+    soft.iter().map(|&llr| llr > 0.0)
+}
+
+/// Reads frames shown several times, grouping their copies
+///
+/// With frame headers, copies are grouped by the header they read back, so a
+/// lost or reordered copy doesn't shift the grouping of later frames: a copy
+/// whose header reads back on its own stands for its frame, and otherwise the
+/// most copies that combine into a readable frame are taken together. Without
+/// headers copies are grouped by position.
+struct CopyReader<'a> {
+    decoder: &'a PixelEncoder,
+    layout: &'a FrameLayout,
+
+    /// Frame code, when frames carry headers to group by
+    code: Option<&'a FrameCode>,
+
+    /// Copies of each frame as encoded
+    copies: usize,
+
+    /// Frames not yet read
+    queue: VecDeque<Cow<'a, RgbaImage>>,
+
+    /// Stream id and index of every frame read so far
+    seen: HashSet<(u32, u32)>,
+}
+
+impl<'a> CopyReader<'a> {
+    fn new(
+        decoder: &'a PixelEncoder,
+        layout: &'a FrameLayout,
+        code: Option<&'a FrameCode>,
+    ) -> Result<Self> {
+        Ok(Self {
+            decoder,
+            layout,
+            code: code.filter(|_| decoder.frame_headers),
+            copies: decoder.copies()?,
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+        })
+    }
+
+    /// Queue the next frame
+    fn push(&mut self, frame: Cow<'a, RgbaImage>) {
+        self.queue.push_back(frame);
+    }
+
+    /// Soft decisions and a report for the next frame, once enough copies are
+    /// queued to read it (or any are, with `flush`)
+    ///
+    /// Copies of a frame already read are skipped.
+    fn next(&mut self, flush: bool) -> Option<(Vec<f32>, FrameReport)> {
+        while !self.queue.is_empty() && (flush || self.queue.len() >= self.copies) {
+            let (taken, soft, report, id) = match self.code {
+                Some(code) => self.read_front(code),
+                None => {
+                    let taken = self.copies.min(self.queue.len());
+                    let samples: Vec<Vec<u8>> = (0..taken)
+                        .map(|copy| self.sample(copy, self.decoder.copy_shift(copy)))
+                        .collect();
+                    let (soft, report) = self.decoder.combine(self.layout, &samples);
+                    (taken, soft, report, None)
+                }
+            };
+            self.queue.drain(..taken);
+
+            if id.is_none_or(|id| self.seen.insert(id)) {
+                return Some((soft, report));
+            }
+        }
+        None
+    }
+
+    /// Read the frame at the front of the queue: its first copy alone if the
+    /// header reads back, else the most copies that combine into a readable
+    /// frame, else the first copy as it is
+    ///
+    /// Returns how many copies were used and the frame's stream id and index.
+    fn read_front(&self, code: &FrameCode) -> (usize, Vec<f32>, FrameReport, Option<(u32, u32)>) {
+        let decoder = self.decoder;
+        // Copies drawn with shifts, read without markers, may have lost the
+        // copies before them, so every offset is tried
+        let shifted = decoder.shifts && !self.layout.aligned;
+        let alone = if shifted { self.copies } else { 1 };
+
+        let mut first = None;
+        for copy in 0..alone {
+            let (soft, report) =
+                decoder.classify(self.layout, &self.sample(0, decoder.copy_shift(copy)));
+            if let Some(id) = frame_id(code, &soft) {
+                return (1, soft, report, Some(id));
+            }
+            first.get_or_insert((soft, report));
+        }
+
+        for taken in (2..=self.copies.min(self.queue.len())).rev() {
+            let offsets = if shifted { self.copies - taken + 1 } else { 1 };
+            for offset in 0..offsets {
+                let samples: Vec<Vec<u8>> = (0..taken)
+                    .map(|copy| self.sample(copy, decoder.copy_shift(offset + copy)))
+                    .collect();
+                let (soft, report) = decoder.combine(self.layout, &samples);
+                if let Some(id) = frame_id(code, &soft) {
+                    return (taken, soft, report, Some(id));
+                }
+            }
+        }
+
+        let (soft, report) = first.expect("at least one copy");
+        (1, soft, report, None)
+    }
+
+    /// Block brightness of queued copy `copy`, drawn `shift` pixels right and
+    /// down
+    fn sample(&self, copy: usize, shift: u32) -> Vec<u8> {
+        self.decoder
+            .sample_frame(self.layout, &self.queue[copy], shift)
+    }
+}
+
+/// Stream id and index of a frame, if its header reads back intact
+fn frame_id(code: &FrameCode, soft: &[f32]) -> Option<(u32, u32)> {
+    let (content, _, _) = code.decode(&pack_bytes(soft));
+    FrameHeader::parse(&content)
+        .ok()
+        .map(|(header, _)| (header.stream_id, header.index))
+}
 
-        // Below threshold = black (1), above = white (0)
-        avg < self.threshold as u32
+/// `frame` moved `shift` pixels right and down, with white filling the gap
+fn shifted(frame: &RgbaImage, shift: u32) -> RgbaImage {
+    if shift == 0 {
+        return frame.clone();
+    }
+
+    let white = Rgba([255, 255, 255, 255]);
+    let mut moved = RgbaImage::from_pixel(frame.width(), frame.height(), white);
+    image::imageops::replace(&mut moved, frame, shift as i64, shift as i64);
+    moved
+}
+
+/// `payload` padded to fill a frame, so the frame checksum covers every byte
+/// the decoder reads back
+///
+/// Padding alternates bits rather than repeating zeros: a run of blank
+/// blocks gives regional thresholds no contrast to work with.
+fn padded(payload: &[u8], len: usize) -> Vec<u8> {
+    let mut padded = payload.to_vec();
+    padded.resize(len, 0x55);
+    padded
+}
+
+/// Whole bytes from soft decisions, dropping trailing bits
+fn pack_bytes(soft: &[f32]) -> Vec<u8> {
+    soft.chunks_exact(8)
+        .map(|byte| hard_decisions(byte).fold(0u8, |acc, bit| (acc << 1) | bit as u8))
+        .collect()
+}
+
+/// Log frames that decoded with low confidence
+fn log_report(index: usize, report: &FrameReport) {
+    if report.calibration_errors > 0 || report.uncertain_blocks > 0 || report.corrected_symbols > 0
+    {
+        debug!(
+            "Frame {}: threshold {}, confidence {:.2}, {} uncertain blocks, {} calibration errors, est. BER {:.2e}, {} bytes corrected",
+            index,
+            report.threshold,
+            report.confidence,
+            report.uncertain_blocks,
+            report.calibration_errors,
+            report.estimated_bit_error_rate,
+            report.corrected_symbols
+        );
+    } else {
+        trace!("Frame {}: {:?}", index, report);
     }
 }
 
@@ -230,133 +1148,262 @@ impl Encoder for PixelEncoder {
     async fn encode(&self, data: &[u8]) -> Result<EncodedData> {
         debug!("Encoding {} bytes with pixel encoder", data.len());
 
-        let frames = self.encode_to_frames(data)?;
-
-        // Serialize frames to PNG sequence
-        let mut encoded = Vec::new();
+        // Content-derived, so encoding the same data gives the same output
+        let stream_id = crc32fast::hash(data);
+        let frames = self.encode_to_frames(data, stream_id)?;
         let frame_count = frames.len() as u32;
 
-        // Store metadata: frame count (4 bytes) + original size (8 bytes)
-        encoded.extend_from_slice(&frame_count.to_le_bytes());
-        encoded.extend_from_slice(&(data.len() as u64).to_le_bytes());
-
-        // Encode each frame as PNG
-        for frame in &frames {
-            let mut png_data = Vec::new();
-            frame
-                .write_to(
-                    &mut std::io::Cursor::new(&mut png_data),
-                    image::ImageOutputFormat::Png,
-                )
-                .map_err(|e| Error::Encoding(format!("PNG encoding failed: {}", e)))?;
-
-            // Store frame size and data
-            let frame_size = png_data.len() as u32;
-            encoded.extend_from_slice(&frame_size.to_le_bytes());
-            encoded.extend_from_slice(&png_data);
+        let mut parameters = self.parameters();
+        parameters["frame_count"] = frame_count.into();
+        if self.frame_headers {
+            parameters["stream_id"] = stream_id.into();
         }
+        let header = ContainerHeader::new(self.strategy.clone(), data, parameters.clone());
+        let encoded = match self.video_format {
+            VideoFormat::PngSequence => header.wrap(&container::write_png_sequence(&frames)?)?,
+            format => format.write(&frames, self.fps, &header.to_bytes()?)?,
+        };
 
         let metadata = EncodingMetadata {
             original_size: data.len(),
             encoded_size: encoded.len(),
             compression_ratio: encoded.len() as f64 / data.len() as f64,
             strategy: "pixel".to_string(),
-            parameters: serde_json::json!({
-                "block_size": self.block_size,
-                "resolution": self.resolution,
-                "fps": self.fps,
-                "frame_count": frame_count,
-            }),
+            parameters,
         };
 
         Ok(EncodedData {
             data: encoded,
-            format: "png_sequence".to_string(),
+            format: self.video_format.name().to_string(),
             metadata,
         })
     }
 
     async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
         debug!("Decoding pixel-encoded data");
+        self.decode_with_report(encoded).map(|(data, _)| data)
+    }
 
-        let data = &encoded.data;
-        let mut cursor = 0;
-
-        // Read metadata
-        if data.len() < 12 {
-            return Err(Error::Decoding("Data too short".to_string()));
+    async fn encode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<EncodingMetadata> {
+        // Data isn't known up front, so the stream id is random
+        let stream_id = sequence::random_stream_id();
+        let mut parameters = self.parameters();
+        if self.frame_headers {
+            parameters["stream_id"] = stream_id.into();
         }
 
-        let frame_count = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
-        let original_size = u64::from_le_bytes([
-            data[4], data[5], data[6], data[7], data[8], data[9], data[10], data[11],
-        ]) as usize;
-        cursor = 12;
+        let header = ContainerHeader::streaming(self.strategy.clone(), parameters.clone());
+        let (mut writer, mut encoded_size) = FrameWriter::start(
+            self.video_format,
+            output,
+            &header,
+            self.resolution,
+            self.fps,
+        )
+        .await?;
+
+        let layout = self.frame_layout()?;
+        let bits_per_frame = layout.data.len() * self.bits_per_block();
+        let code = self.frame_code(&layout)?;
+        let payload_len = if self.framed() {
+            Some(self.payload_per_frame(&code)?)
+        } else {
+            None
+        };
+        let mut crc = crc32fast::Hasher::new();
+        let mut original_size = 0u64;
+        // Frames drawn, and frames written counting every copy
+        let mut data_frames = 0u32;
+        let mut frame_count = 0u32;
+
+        // Only the bits of the frame being drawn are buffered
+        let mut pending = Vec::new();
+        let mut bit_index = 0;
+        let mut eof = false;
+
+        loop {
+            // With whole-byte frames, one byte past the payload tells whether
+            // this is the last frame
+            let wanted = payload_len.map_or(bit_index + bits_per_frame, |len| (len + 1) * 8);
+            while !eof && pending.len() * 8 < wanted {
+                let start = pending.len();
+                pending.reserve(container::STREAM_CHUNK);
+                if input.read_buf(&mut pending).await? == 0 {
+                    eof = true;
+                }
+                crc.update(&pending[start..]);
+                original_size += (pending.len() - start) as u64;
+            }
 
-        debug!("Reading {} frames, expecting {} bytes", frame_count, original_size);
+            if let Some(len) = payload_len {
+                let end = len.min(pending.len());
+                let last = eof && pending.len() <= len;
+                let header = FrameHeader {
+                    stream_id,
+                    index: data_frames,
+                    total: last.then_some(data_frames + 1),
+                };
+                let bytes = self.frame_bytes(&code, header, &pending[..end])?;
+
+                let frame = self.create_frame(&layout, &bytes, &mut 0)?;
+                for copy in self.repeat(frame)? {
+                    encoded_size += writer.push(output, copy).await?;
+                    frame_count += 1;
+                }
+                data_frames += 1;
+                trace!("Streamed frame {}", data_frames);
 
-        // Decode frames
-        let mut frames = Vec::new();
-        for _ in 0..frame_count {
-            if cursor + 4 > data.len() {
-                return Err(Error::Decoding("Unexpected end of data".to_string()));
+                pending.drain(..end);
+                if last {
+                    break;
+                }
+                continue;
             }
 
-            let frame_size = u32::from_le_bytes([
-                data[cursor],
-                data[cursor + 1],
-                data[cursor + 2],
-                data[cursor + 3],
-            ]) as usize;
-            cursor += 4;
+            if eof && bit_index >= pending.len() * 8 {
+                break;
+            }
 
-            if cursor + frame_size > data.len() {
-                return Err(Error::Decoding("Frame data truncated".to_string()));
+            let frame = self.create_frame(&layout, &pending, &mut bit_index)?;
+            for copy in self.repeat(frame)? {
+                encoded_size += writer.push(output, copy).await?;
+                frame_count += 1;
             }
+            data_frames += 1;
+            trace!("Streamed frame {}", data_frames);
+
+            pending.drain(..(bit_index / 8).min(pending.len()));
+            bit_index %= 8;
+        }
 
-            let frame_data = &data[cursor..cursor + frame_size];
-            let img = image::load_from_memory_with_format(frame_data, image::ImageFormat::Png)
-                .map_err(|e| Error::Decoding(format!("PNG decoding failed: {}", e)))?
-                .to_rgba8();
+        encoded_size += writer.finish(output, original_size, crc.finalize()).await?;
+        output.flush().await?;
 
-            frames.push(img);
-            cursor += frame_size;
+        parameters["frame_count"] = frame_count.into();
+
+        Ok(EncodingMetadata {
+            original_size: original_size as usize,
+            encoded_size,
+            compression_ratio: encoded_size as f64 / original_size as f64,
+            strategy: "pixel".to_string(),
+            parameters,
+        })
+    }
+
+    async fn decode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<u64> {
+        // Enough to tell a video file from a container
+        let mut magic = [0u8; 12];
+        input
+            .read_exact(&mut magic)
+            .await
+            .map_err(|_| Error::Decoding("Missing ISG container header".to_string()))?;
+        let mut input = (&magic[..]).chain(input);
+        let input: &mut ByteReader<'_> = &mut input;
+
+        let (mut frames, mut header) = FrameReader::start(input, &magic).await?;
+        let decoder = self.decoder_for(&header)?;
+        let layout = decoder.frame_layout()?;
+        let code = decoder.frame_code(&layout)?;
+        let mut reassembler = decoder.reassembler(&header)?;
+
+        let mut crc = crc32fast::Hasher::new();
+        let mut written = 0u64;
+        let mut bits = Vec::new();
+        // Bytes of the latest frame in order are held back until we know
+        // whether it was the last one (whose tail is padding)
+        let mut held: Vec<u8> = Vec::new();
+        let mut reader = CopyReader::new(&decoder, &layout, Some(&code))?;
+        let mut index = 0;
+
+        loop {
+            let frame = frames.next().await?;
+            let done = frame.is_none();
+            if let Some(frame) = frame {
+                reader.push(Cow::Owned(frame));
+            }
+
+            while let Some((soft, mut report)) = reader.next(done) {
+                let ready = if decoder.framed() {
+                    decoder.frame_payloads(&code, &mut reassembler, &soft, &mut report)
+                } else {
+                    bits.extend(hard_decisions(&soft));
+                    let whole = bits.len() / 8 * 8;
+                    let bytes = bits[..whole]
+                        .chunks(8)
+                        .map(|byte| byte.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+                        .collect();
+                    bits.drain(..whole);
+                    vec![bytes]
+                };
+                log_report(index, &report);
+                index += 1;
+
+                for bytes in ready {
+                    output.write_all(&held).await?;
+                    crc.update(&held);
+                    written += held.len() as u64;
+                    held = bytes;
+                }
+            }
+            if done {
+                break;
+            }
         }
 
-        // Decode frames to data
-        self.decode_from_frames(&frames, original_size)
+        frames.finish(&mut header).await?;
+        if let Some(reassembler) = &reassembler {
+            reassembler.finish()?;
+        }
+
+        let remaining = header.original_size.saturating_sub(written) as usize;
+        if remaining > held.len() {
+            return Err(Error::Corruption(format!(
+                "Decoded {} bytes, expected {}",
+                written + held.len() as u64,
+                header.original_size
+            )));
+        }
+
+        output.write_all(&held[..remaining]).await?;
+        output.flush().await?;
+        crc.update(&held[..remaining]);
+        written += remaining as u64;
+
+        header.verify_summary(written, crc.finalize())?;
+        Ok(written)
     }
 
     fn strategy(&self) -> &EncodingStrategy {
-        // Return a static reference - in real implementation, cache this
-        static STRATEGY: once_cell::sync::Lazy<EncodingStrategy> =
-            once_cell::sync::Lazy::new(|| {
-                EncodingStrategy::PixelEncoding {
-                    block_size: 4,
-                    fps: 30,
-                    resolution: (1920, 1080),
-                }
-            });
-        &STRATEGY
+        &self.strategy
     }
 
     fn estimate_size(&self, input_size: usize) -> usize {
         let total_bits = input_size * 8;
-        let bits_per_frame = self.bits_per_frame();
-        let num_frames = (total_bits + bits_per_frame - 1) / bits_per_frame;
+        let bits_per_frame = self.bits_per_frame().max(1);
+        let num_frames = total_bits.div_ceil(bits_per_frame) * self.redundancy.max(1) as usize;
 
         // Rough estimate: PNG compression ratio ~1.5x for pixel patterns
         let (width, height) = self.resolution;
         let bytes_per_frame = (width * height * 4) as usize; // RGBA
         let estimated = num_frames * bytes_per_frame * 3 / 2;
 
-        estimated + 12 + (num_frames * 4) // metadata overhead
+        estimated + container::HEADER_ESTIMATE + 4 + (num_frames * 4) // metadata overhead
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::container::testing::{edit_frames, edit_header, test_data};
+    use crate::video::{AviCodec, VideoReader};
 
     #[tokio::test]
     async fn test_pixel_encoder_roundtrip() {
@@ -364,7 +1411,11 @@ mod tests {
         let data = b"Hello, ISG World! This is a test of the pixel encoder.";
 
         let encoded = encoder.encode(data).await.unwrap();
-        println!("Encoded {} bytes to {} bytes", data.len(), encoded.data.len());
+        println!(
+            "Encoded {} bytes to {} bytes",
+            data.len(),
+            encoded.data.len()
+        );
 
         let decoded = encoder.decode(&encoded).await.unwrap();
 
@@ -382,6 +1433,143 @@ mod tests {
         assert_eq!(data.as_slice(), decoded.as_slice());
     }
 
+    #[tokio::test]
+    async fn test_decode_uses_header_settings() {
+        let encoder = PixelEncoder::new()
+            .with_block_size(3)
+            .with_resolution(320, 240);
+        let data = b"Decoded without knowing the block size";
+
+        let encoded = encoder.encode(data).await.unwrap();
+        let decoded = PixelEncoder::new().decode(&encoded).await.unwrap();
+
+        assert_eq!(data.as_slice(), decoded.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_settings() {
+        let data = b"Settings are checked before use";
+        for encoder in [
+            PixelEncoder::new().with_block_size(0),
+            PixelEncoder::new().with_resolution(100_000, 100_000),
+        ] {
+            assert!(matches!(encoder.encode(data).await, Err(Error::Config(_))));
+        }
+
+        // Settings read from a header fail as corruption instead of panicking
+        // or allocating without bound
+        let encoded = PixelEncoder::new()
+            .with_resolution(320, 240)
+            .encode(data)
+            .await
+            .unwrap();
+        let mut tampered: Vec<_> = [(0, (320, 240)), (4, (u32::MAX, u32::MAX))]
+            .into_iter()
+            .map(|(block_size, resolution)| {
+                edit_header(&encoded, |header| {
+                    header.strategy = EncodingStrategy::PixelEncoding {
+                        block_size,
+                        fps: 30,
+                        resolution,
+                        levels: 2,
+                    };
+                })
+            })
+            .collect();
+        tampered.push(edit_header(&encoded, |header| {
+            header.parameters["redundancy"] = u64::MAX.into();
+        }));
+
+        for encoded in tampered {
+            let result = PixelEncoder::new().decode(&encoded).await;
+            assert!(matches!(result, Err(Error::Corruption(_))), "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_strategy_reflects_settings() {
+        let encoder = PixelEncoder::new().with_block_size(2).with_fps(60);
+        assert_eq!((encoder.block_size(), encoder.fps()), (2, 60));
+        assert_eq!(encoder.resolution(), (1920, 1080));
+        assert_eq!(encoder.threshold(), None);
+        assert_eq!(encoder.clone().with_threshold(100).threshold(), Some(100));
+
+        let tuned = encoder
+            .clone()
+            .with_levels(4)
+            .with_calibration(false)
+            .with_alignment(false)
+            .with_frame_headers(false)
+            .with_error_correction(8)
+            .with_redundancy(3)
+            .with_shifts(true)
+            .with_combining(Combining::Majority)
+            .with_video_format(VideoFormat::Y4m);
+        assert_eq!(tuned.levels(), 4);
+        assert!(!tuned.calibration() && !tuned.alignment() && !tuned.frame_headers());
+        assert_eq!((tuned.error_correction(), tuned.redundancy()), (8, 3));
+        assert!(tuned.shifts());
+        assert_eq!(tuned.combining(), Combining::Majority);
+        assert_eq!(tuned.video_format(), VideoFormat::Y4m);
+
+        match encoder.strategy() {
+            EncodingStrategy::PixelEncoding {
+                block_size, fps, ..
+            } => {
+                assert_eq!(*block_size, 2);
+                assert_eq!(*fps, 60);
+            }
+            other => panic!("Wrong strategy: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_roundtrip() {
+        for frame_headers in [true, false] {
+            stream_roundtrip(frame_headers).await;
+        }
+    }
+
+    async fn stream_roundtrip(frame_headers: bool) {
+        // 225 data bits per frame, so without headers bytes straddle frame
+        // boundaries
+        let encoder = PixelEncoder::new()
+            .with_block_size(4)
+            .with_resolution(100, 40)
+            .with_alignment(false)
+            .with_frame_headers(frame_headers);
+        let data = test_data(1000, 7);
+
+        let mut streamed = Vec::new();
+        let metadata = encoder
+            .encode_stream(&mut &data[..], &mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(metadata.original_size, data.len());
+        assert_eq!(metadata.encoded_size, streamed.len());
+
+        // Buffered decoding understands the streamed container
+        let buffered = EncodedData::from_bytes(streamed.clone());
+        assert_eq!(encoder.decode(&buffered).await.unwrap(), data);
+
+        let mut decoded = Vec::new();
+        let len = PixelEncoder::new()
+            .decode_stream(&mut &streamed[..], &mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(len, data.len() as u64);
+        assert_eq!(decoded, data);
+
+        // Blobs from `encode` stream-decode too
+        let encoded = encoder.encode(&data).await.unwrap();
+        let mut decoded = Vec::new();
+        encoder
+            .decode_stream(&mut &encoded.data[..], &mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
     #[tokio::test]
     async fn test_large_block_size() {
         let encoder = PixelEncoder::new().with_block_size(10);
@@ -392,4 +1580,461 @@ mod tests {
 
         assert_eq!(data.as_slice(), decoded.as_slice());
     }
+
+    /// Rebuild `encoded` from some of its frames, by index
+    fn reorder(encoded: &EncodedData, order: &[usize]) -> EncodedData {
+        edit_frames(encoded, |frames| {
+            *frames = order.iter().map(|&i| frames[i].clone()).collect();
+        })
+    }
+
+    #[tokio::test]
+    async fn test_frame_headers_survive_dropped_and_duplicated_frames() {
+        let encoder = PixelEncoder::new()
+            .with_resolution(100, 40)
+            .with_alignment(false);
+        let data = test_data(60, 23);
+        let encoded = encoder.encode(&data).await.unwrap();
+        // 12 payload bytes per frame
+        assert_eq!(encoded.metadata.parameters["frame_count"], 5);
+
+        // Shuffled, with a frame shown twice
+        let shuffled = reorder(&encoded, &[1, 0, 2, 2, 4, 3]);
+        assert_eq!(encoder.decode(&shuffled).await.unwrap(), data);
+
+        let mut streamed = Vec::new();
+        encoder
+            .decode_stream(&mut &shuffled.data[..], &mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, data);
+
+        // A lost frame is reported, not decoded into garbage
+        let dropped = reorder(&encoded, &[0, 1, 3, 4]);
+        match encoder.decode(&dropped).await {
+            Err(Error::Corruption(message)) => assert!(message.contains("[2]"), "{}", message),
+            other => panic!("Dropped frame not detected: {:?}", other),
+        }
+
+        // Without headers the same shuffle corrupts the data
+        let bare = encoder.clone().with_frame_headers(false);
+        let encoded = bare.encode(&data).await.unwrap();
+        let frames = encoded.metadata.parameters["frame_count"].as_u64().unwrap() as usize;
+        let mut order: Vec<usize> = (0..frames).collect();
+        order.swap(0, 1);
+        assert!(bare.decode(&reorder(&encoded, &order)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_error_correction_repairs_damaged_region() {
+        let encoder = PixelEncoder::new()
+            .with_resolution(320, 180)
+            .with_error_correction(16);
+        let data = test_data(600, 53);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        let parameters = &encoded.metadata.parameters;
+        assert_eq!(parameters["ecc_parity"], 16);
+        let rate = parameters["ecc_rate"].as_f64().unwrap();
+        assert!(rate > 0.8 && rate < 1.0);
+
+        // A smudge over a 40x12 pixel patch of data blocks
+        let smudge = |x: u32, y: u32, v: u8| {
+            if (100..140).contains(&x) && (80..92).contains(&y) {
+                128
+            } else {
+                v
+            }
+        };
+        let smudged = distort(&encoded, smudge);
+
+        let (decoded, reports) = encoder.decode_with_report(&smudged).unwrap();
+        assert_eq!(decoded, data);
+        assert!(reports.iter().all(|r| r.corrected_symbols > 0));
+
+        let unprotected = encoder.with_error_correction(0);
+        let encoded = unprotected.encode(&data).await.unwrap();
+        assert!(unprotected
+            .decode(&distort(&encoded, smudge))
+            .await
+            .is_err());
+    }
+
+    /// Re-encode the frames of `encoded` with a per-pixel transform
+    fn distort(encoded: &EncodedData, f: impl Fn(u32, u32, u8) -> u8) -> EncodedData {
+        edit_frames(encoded, |frames| {
+            for frame in frames {
+                for (x, y, pixel) in frame.enumerate_pixels_mut() {
+                    let level = f(x, y, pixel[0]);
+                    *pixel = Rgba([level, level, level, 255]);
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_threshold_survives_brightness_shift() {
+        let encoder = PixelEncoder::new().with_resolution(160, 120);
+        let data = b"washed out by a re-encode".repeat(4);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // Brighter, flatter video: black lands at 140, white at 242
+        let shifted = distort(&encoded, |_, _, v| (140.0 + 0.4 * v as f64) as u8);
+
+        let fixed = PixelEncoder::new().with_threshold(128);
+        assert!(fixed.decode(&shifted).await.is_err());
+
+        let (decoded, reports) = encoder.decode_with_report(&shifted).unwrap();
+        assert_eq!(decoded, data);
+        assert!(reports
+            .iter()
+            .all(|r| r.calibration_errors == 0 && r.threshold > 140));
+    }
+
+    #[tokio::test]
+    async fn test_calibration_anchors_uniform_data() {
+        let encoder = PixelEncoder::new()
+            .with_resolution(80, 60)
+            .with_alignment(false);
+        let data = vec![0u8; 64];
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        let (decoded, reports) = encoder.decode_with_report(&encoded).unwrap();
+        assert_eq!(decoded, data);
+        assert!(reports.iter().all(|r| r.confidence > 0.9
+            && r.calibration_errors == 0
+            && r.estimated_bit_error_rate < 1e-6));
+    }
+
+    #[tokio::test]
+    async fn test_calibration_sets_threshold_for_biased_data() {
+        let encoder = PixelEncoder::new()
+            .with_resolution(320, 240)
+            .with_alignment(false);
+        let data = vec![0u8; 1500];
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // Dim, flat video with +-30 of noise per block; nearly every data block
+        // is white, so Otsu over the frame would split the white noise
+        let noisy = distort(&encoded, |x, y, v| {
+            let noise = ((x / 4 * 31 + y / 4 * 17) % 61) as f64 - 30.0;
+            (60.0 + 0.5 * v as f64 + noise).clamp(0.0, 255.0) as u8
+        });
+
+        let (decoded, reports) = encoder.decode_with_report(&noisy).unwrap();
+        assert_eq!(decoded, data);
+        assert!(reports.iter().all(|r| (110..140).contains(&r.threshold)));
+    }
+
+    #[tokio::test]
+    async fn test_regional_threshold_handles_uneven_lighting() {
+        let encoder = PixelEncoder::new()
+            .with_resolution(160, 120)
+            .with_thresholding(Thresholding::Regional(8));
+        let data: Vec<u8> = (0..200u32).map(|i| (i * 37 % 251) as u8).collect();
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // Light falls off across the frame, so black on the right is brighter
+        // than white on the left
+        let graded = distort(&encoded, |x, _, v| {
+            (0.4 * v as f64 + x as f64 / 160.0 * 150.0) as u8
+        });
+
+        assert!(PixelEncoder::new().decode(&graded).await.is_err());
+        assert_eq!(encoder.decode(&graded).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_alignment_survives_rescaling() {
+        let encoder = PixelEncoder::new().with_resolution(384, 216);
+        let data = test_data(400, 131);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // 1080p to 720p is a 2/3 downscale
+        let rescale = |frames: &mut Vec<RgbaImage>| {
+            for frame in frames {
+                *frame =
+                    image::imageops::resize(frame, 256, 144, image::imageops::FilterType::Triangle);
+            }
+        };
+        let rescaled = edit_frames(&encoded, rescale);
+
+        let unaligned = PixelEncoder::new().with_alignment(false);
+        let unaligned_encoded = unaligned
+            .with_resolution(384, 216)
+            .encode(&data)
+            .await
+            .unwrap();
+        let unaligned_rescaled = edit_frames(&unaligned_encoded, rescale);
+        assert!(encoder.decode(&unaligned_rescaled).await.is_err());
+
+        assert_eq!(encoder.decode(&rescaled).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_alignment_survives_letterbox_and_crop() {
+        let encoder = PixelEncoder::new().with_resolution(320, 180);
+        let data = b"boxed in and trimmed".repeat(10);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // Shrink into a black 4:3 frame, then trim a few pixels off the edges
+        let boxed = edit_frames(&encoded, |frames| {
+            for frame in frames {
+                let shrunk =
+                    image::imageops::resize(frame, 288, 162, image::imageops::FilterType::Triangle);
+                let mut canvas = RgbaImage::from_pixel(320, 240, Rgba([0, 0, 0, 255]));
+                image::imageops::overlay(&mut canvas, &shrunk, 19, 43);
+                *frame = image::imageops::crop_imm(&canvas, 3, 2, 314, 235).to_image();
+            }
+        });
+
+        let (decoded, reports) = encoder.decode_with_report(&boxed).unwrap();
+        assert_eq!(decoded, data);
+        assert!(reports.iter().all(|r| r.calibration_errors == 0));
+    }
+
+    #[tokio::test]
+    async fn test_gray_levels_pack_more_bits() {
+        let data = test_data(3000, 97);
+        let binary = PixelEncoder::new().with_resolution(320, 180);
+        let frames = |encoded: &EncodedData| encoded.metadata.parameters["frame_count"].as_u64();
+        let binary_frames = frames(&binary.encode(&data).await.unwrap()).unwrap();
+
+        for levels in [4, 8] {
+            let encoder = binary.clone().with_levels(levels);
+            let encoded = encoder.encode(&data).await.unwrap();
+            assert!(frames(&encoded).unwrap() < binary_frames);
+
+            // Settings come from the header
+            assert_eq!(PixelEncoder::new().decode(&encoded).await.unwrap(), data);
+        }
+
+        assert!(binary.with_levels(6).encode(&data).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gray_levels_survive_contrast_shift() {
+        let encoder = PixelEncoder::new().with_resolution(320, 180).with_levels(4);
+        let data = b"four shades of gray".repeat(12);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // Squeezed and brightened: level spacing no longer matches 85
+        let shifted = distort(&encoded, |_, _, v| (60.0 + 0.6 * v as f64) as u8);
+
+        let (decoded, reports) = encoder.decode_with_report(&shifted).unwrap();
+        assert_eq!(decoded, data);
+        assert!(reports
+            .iter()
+            .all(|r| r.calibration_errors == 0 && r.black_level > 55.0));
+    }
+
+    #[tokio::test]
+    async fn test_soft_decisions() {
+        let encoder = PixelEncoder::new().with_resolution(320, 180).with_levels(8);
+        let data = test_data(200, 29);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        // Noise of about a third of the level spacing
+        let noisy = distort(&encoded, |x, y, v| {
+            let noise = ((x * 7 + y * 13) % 23) as i32 - 11;
+            (v as i32 + noise).clamp(0, 255) as u8
+        });
+
+        let soft = encoder.decode_soft(&noisy).unwrap();
+        assert_eq!(soft.len(), data.len() * 8);
+        for (i, llr) in soft.iter().enumerate() {
+            let bit = data[i / 8] >> (7 - i % 8) & 1 == 1;
+            assert_eq!(*llr > 0.0, bit, "bit {}", i);
+        }
+
+        let clean = encoder.decode_soft(&encoded).unwrap();
+        let strength = |values: &[f32]| values.iter().map(|v| v.abs()).sum::<f32>();
+        assert!(strength(&clean) > strength(&soft));
+    }
+
+    #[tokio::test]
+    async fn test_redundancy_repeats_and_combines_frames() {
+        let data = test_data(300, 41);
+        let single = PixelEncoder::new()
+            .with_resolution(160, 90)
+            .with_alignment(false);
+        let frames = |encoded: &EncodedData| encoded.metadata.parameters["frame_count"].as_u64();
+        let single_frames = frames(&single.encode(&data).await.unwrap()).unwrap();
+
+        // Every copy inverted over a different band of the frame
+        let smear = |index: u32| {
+            move |_: u32, y: u32, v: u8| {
+                if y / 18 == index % 3 * 2 {
+                    255 - v
+                } else {
+                    v
+                }
+            }
+        };
+
+        for combining in [Combining::Average, Combining::Majority] {
+            for shifts in [false, true] {
+                let encoder = single
+                    .clone()
+                    .with_redundancy(3)
+                    .with_shifts(shifts)
+                    .with_combining(combining);
+                let encoded = encoder.encode(&data).await.unwrap();
+                assert_eq!(frames(&encoded).unwrap(), single_frames * 3);
+
+                // Settings other than combining come from the header
+                let decoder = PixelEncoder::new().with_combining(combining);
+                assert_eq!(decoder.decode(&encoded).await.unwrap(), data);
+
+                let damaged = edit_frames(&encoded, |frames| {
+                    for (i, frame) in frames.iter_mut().enumerate() {
+                        let f = smear(i as u32);
+                        for (x, y, pixel) in frame.enumerate_pixels_mut() {
+                            let level = f(x, y, pixel[0]);
+                            *pixel = Rgba([level, level, level, 255]);
+                        }
+                    }
+                });
+                assert_eq!(decoder.decode(&damaged).await.unwrap(), data);
+
+                let mut streamed = Vec::new();
+                decoder
+                    .decode_stream(&mut &damaged.data[..], &mut streamed)
+                    .await
+                    .unwrap();
+                assert_eq!(streamed, data);
+            }
+        }
+
+        assert!(single.with_redundancy(0).encode(&data).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redundancy_survives_lost_and_reordered_copies() {
+        let data = test_data(300, 41);
+        for combining in [Combining::Average, Combining::Majority] {
+            let encoder = PixelEncoder::new()
+                .with_resolution(160, 90)
+                .with_alignment(false)
+                .with_redundancy(3)
+                .with_shifts(true)
+                .with_combining(combining);
+            let encoded = encoder.encode(&data).await.unwrap();
+            assert_eq!(encoded.metadata.parameters["frame_count"], 12);
+
+            // One copy lost, then the copies of two frames swapped over
+            let mut order: Vec<usize> = (0..12).filter(|&i| i != 1).collect();
+            order.swap(3, 6);
+            let shuffled = reorder(&encoded, &order);
+            assert_eq!(encoder.decode(&shuffled).await.unwrap(), data);
+
+            let mut streamed = Vec::new();
+            encoder
+                .decode_stream(&mut &shuffled.data[..], &mut streamed)
+                .await
+                .unwrap();
+            assert_eq!(streamed, data);
+            let soft = encoder.decode_soft(&shuffled).unwrap();
+            assert_eq!(soft.len(), data.len() * 8);
+
+            // Every copy inverted over a different band, so none reads alone;
+            // the loss costs the first frame but not the grouping of the rest
+            let damaged = edit_frames(&encoded, |frames| {
+                for (i, frame) in frames.iter_mut().enumerate() {
+                    for (_, y, pixel) in frame.enumerate_pixels_mut() {
+                        if y / 18 == i as u32 % 3 * 2 {
+                            let level = 255 - pixel[0];
+                            *pixel = Rgba([level, level, level, 255]);
+                        }
+                    }
+                }
+            });
+            let dropped: Vec<usize> = (0..12).filter(|&i| i != 1).collect();
+            match encoder.decode(&reorder(&damaged, &dropped)).await {
+                Err(Error::Corruption(message)) => {
+                    assert!(message.contains("frames [0] of 4"), "{}", message)
+                }
+                other => panic!("Lost copy not reported: {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_video_output_roundtrip() {
+        let data = test_data(500, 61);
+        let formats = [
+            VideoFormat::Y4m,
+            VideoFormat::Avi(AviCodec::Uncompressed),
+            VideoFormat::Avi(AviCodec::Mjpeg { quality: 90 }),
+        ];
+
+        for format in formats {
+            let encoder = PixelEncoder::new()
+                .with_resolution(320, 180)
+                .with_fps(24)
+                .with_levels(4)
+                .with_video_format(format);
+            let encoded = encoder.encode(&data).await.unwrap();
+            assert_eq!(encoded.format, format.name());
+
+            // A real video at the configured frame rate
+            let reader = VideoReader::parse(&encoded.data).unwrap();
+            assert_eq!(reader.fps(), 24.0);
+            assert_eq!(
+                reader.remaining() as u64,
+                encoded.metadata.parameters["frame_count"].as_u64().unwrap()
+            );
+
+            // Settings come from the header embedded in the video
+            assert_eq!(PixelEncoder::new().decode(&encoded).await.unwrap(), data);
+
+            // Y4M streams both ways; AVI is written and read in one piece
+            let mut decoded = Vec::new();
+            let result = PixelEncoder::new()
+                .decode_stream(&mut &encoded.data[..], &mut decoded)
+                .await;
+            let mut streamed = Vec::new();
+            let metadata = encoder.encode_stream(&mut &data[..], &mut streamed).await;
+
+            if format != VideoFormat::Y4m {
+                assert!(result.is_err());
+                assert!(decoded.is_empty());
+                assert!(metadata.is_err());
+                assert!(streamed.is_empty());
+                continue;
+            }
+            assert_eq!(result.unwrap(), data.len() as u64);
+            assert_eq!(decoded, data);
+
+            assert_eq!(metadata.unwrap().encoded_size, streamed.len());
+            let reader = VideoReader::parse(&streamed).unwrap();
+            assert_eq!(
+                reader.remaining(),
+                VideoReader::parse(&encoded.data).unwrap().remaining()
+            );
+            let streamed = EncodedData::from_bytes(streamed);
+            assert_eq!(PixelEncoder::new().decode(&streamed).await.unwrap(), data);
+
+            let mut decoded = Vec::new();
+            PixelEncoder::new()
+                .decode_stream(&mut &streamed.data[..], &mut decoded)
+                .await
+                .unwrap();
+            assert_eq!(decoded, data);
+
+            // The last frame carries the size and checksum
+            let mut tampered = streamed.data.clone();
+            let end = tampered.windows(8).rposition(|w| w == b"XISGEND=").unwrap();
+            tampered[end + 8] ^= 1;
+            assert!(PixelEncoder::new()
+                .decode_stream(&mut &tampered[..], &mut Vec::new())
+                .await
+                .is_err());
+        }
+
+        let stopped = PixelEncoder::new()
+            .with_fps(0)
+            .with_video_format(VideoFormat::Y4m);
+        assert!(stopped.encode(&data).await.is_err());
+    }
 }
@@ -1,55 +1,447 @@
 //! QR code encoding
 //!
 //! Encode data as a grid of QR codes with built-in error correction.
+//!
+//! Codes use the version (size) and error correction level of the encoder's
+//! [`EncodingStrategy::QREncoding`]; the bytes each code holds follow from
+//! those. Decoding scans each frame for codes with a pure-Rust reader, so
+//! frames can be read back after light rescaling or recompression.
+//!
+//! By default each code fills its own frame. [`QREncoder::with_mosaic`] tiles
+//! full-resolution frames with a grid of smaller codes instead, each tagged
+//! with a sequence number and covered by a per-frame parity code (see
+//! [`crate::mosaic`]).
 
+use crate::container::{self, ContainerHeader, PayloadReader};
+use crate::mosaic::{self, MosaicLayout, MosaicReader};
+use crate::sequence::{self, FrameHeader, FRAME_HEADER_LEN};
+use crate::video;
 use async_trait::async_trait;
 use image::RgbaImage;
-use isg_core::{EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result};
-use qrcode::QrCode;
-use tracing::debug;
+use isg_core::{
+    ByteReader, ByteWriter, ECCLevel, EncodedData, Encoder, EncodingMetadata, EncodingStrategy,
+    Error, Result,
+};
+use qrcode::bits::Bits;
+use qrcode::{EcLevel, QrCode, Version};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, trace};
+
+/// Largest QR code version
+const MAX_VERSION: u8 = 40;
+
+/// Smallest side of a rendered code in pixels
+const MIN_SIDE: u32 = 512;
+
+/// Default pixels per module in mosaic frames
+const DEFAULT_MODULE_SIZE: u32 = 4;
 
 /// QR code encoder configuration
 #[derive(Clone, Debug)]
 pub struct QREncoder {
-    /// Maximum bytes per QR code
-    pub max_bytes_per_qr: usize,
+    /// QR code version (1-40): codes are 17 + 4 x version modules wide
+    version: u8,
+
+    /// Share of each code spent on error correction
+    ecc_level: ECCLevel,
+
+    /// Frame resolution when codes are tiled into mosaic frames
+    mosaic: Option<(u32, u32)>,
+
+    /// Pixels per module in mosaic frames
+    module_size: u32,
+
+    /// Strategy describing the generated codes
+    strategy: EncodingStrategy,
 }
 
 impl QREncoder {
-    /// Create a new QR encoder
+    /// Create a new QR encoder (version 40, medium error correction)
     pub fn new() -> Self {
         Self {
-            // QR code can hold up to ~2953 bytes in binary mode
-            max_bytes_per_qr: 2000, // Conservative limit
+            version: MAX_VERSION,
+            ecc_level: ECCLevel::Medium,
+            mosaic: None,
+            module_size: DEFAULT_MODULE_SIZE,
+            strategy: EncodingStrategy::QREncoding {
+                version: MAX_VERSION,
+                ecc_level: ECCLevel::Medium,
+            },
         }
     }
 
-    /// Create QR code images from data
-    fn encode_to_qr_codes(&self, data: &[u8]) -> Result<Vec<RgbaImage>> {
-        let chunks = data.chunks(self.max_bytes_per_qr);
-        let mut qr_images = Vec::new();
+    /// Create with a custom QR code version (1-40)
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self.refresh_strategy()
+    }
+
+    /// Create with a custom error correction level
+    pub fn with_ecc_level(mut self, ecc_level: ECCLevel) -> Self {
+        self.ecc_level = ecc_level;
+        self.refresh_strategy()
+    }
+
+    /// Tile codes into mosaic frames of `width` x `height` pixels
+    ///
+    /// Each frame holds as many codes as fit, the last of them a parity code
+    /// that rebuilds one unreadable code of the frame.
+    pub fn with_mosaic(mut self, width: u32, height: u32) -> Self {
+        self.mosaic = Some((width, height));
+        self
+    }
+
+    /// Create with a custom module size for mosaic frames (in pixels)
+    pub fn with_module_size(mut self, module_size: u32) -> Self {
+        self.module_size = module_size;
+        self
+    }
+
+    /// Recompute the strategy after a setting changed
+    fn refresh_strategy(mut self) -> Self {
+        self.strategy = EncodingStrategy::QREncoding {
+            version: self.version,
+            ecc_level: self.ecc_level.clone(),
+        };
+        self
+    }
+
+    /// Encoder matching the settings recorded in `header`
+    ///
+    /// The mosaic settings come from untrusted data, so they are validated
+    /// before any frame is scanned.
+    fn decoder_for(&self, header: &ContainerHeader) -> Result<Self> {
+        let EncodingStrategy::QREncoding { version, ecc_level } = &header.strategy else {
+            return Err(Error::Decoding(format!(
+                "Expected QR encoding, found {:?}",
+                header.strategy
+            )));
+        };
+
+        let mut decoder = self
+            .clone()
+            .with_version(*version)
+            .with_ecc_level(ecc_level.clone());
+        let size = &header.parameters["mosaic"];
+        decoder.mosaic = match (size[0].as_u64(), size[1].as_u64()) {
+            (Some(width), Some(height)) => match (u32::try_from(width), u32::try_from(height)) {
+                (Ok(width), Ok(height)) => Some((width, height)),
+                _ => {
+                    return Err(Error::Corruption(format!(
+                        "QR mosaic size out of range: {}x{}",
+                        width, height
+                    )))
+                }
+            },
+            _ => None,
+        };
+        if let Some(module_size) = container::parameter(header, "module_size")? {
+            decoder.module_size = module_size;
+        }
+
+        decoder
+            .qr_version()
+            .and(decoder.mosaic_layout())
+            .map_err(container::corrupt_settings)?;
+        Ok(decoder)
+    }
+
+    /// QR code version, failing outside 1-40
+    fn qr_version(&self) -> Result<Version> {
+        if !(1..=MAX_VERSION).contains(&self.version) {
+            return Err(Error::Config(format!(
+                "QR code version must be 1 to {}, got {}",
+                MAX_VERSION, self.version
+            )));
+        }
+        Ok(Version::Normal(self.version as i16))
+    }
+
+    /// Error correction level in `qrcode` terms
+    fn ec_level(&self) -> EcLevel {
+        match self.ecc_level {
+            ECCLevel::Low => EcLevel::L,
+            ECCLevel::Medium => EcLevel::M,
+            ECCLevel::Quartile => EcLevel::Q,
+            ECCLevel::High => EcLevel::H,
+        }
+    }
+
+    /// Bytes each code holds in byte mode at this version and level
+    pub fn max_bytes_per_qr(&self) -> Result<usize> {
+        let data_bits = Bits::new(self.qr_version()?)
+            .max_len(self.ec_level())
+            .map_err(|e| Error::Config(format!("Invalid QR settings: {}", e)))?;
+
+        // Mode indicator, then the byte count (8 bits below version 10)
+        let count_bits = if self.version < 10 { 8 } else { 16 };
+        Ok((data_bits - 4 - count_bits) / 8)
+    }
+
+    fn parameters(&self, qr_count: u32) -> Result<serde_json::Value> {
+        let mut parameters = self.settings()?;
+        parameters["qr_count"] = qr_count.into();
+        Ok(parameters)
+    }
+
+    /// Parameters the decoder needs, known before any data is read
+    fn settings(&self) -> Result<serde_json::Value> {
+        let mut parameters = serde_json::json!({ "max_bytes_per_qr": self.max_bytes_per_qr()? });
+        if let Some(layout) = self.mosaic_layout()? {
+            let (width, height) = self.mosaic.unwrap_or_default();
+            parameters["mosaic"] = serde_json::json!([width, height]);
+            parameters["module_size"] = self.module_size.into();
+            parameters["codes_per_frame"] = layout.cells().into();
+        }
+        Ok(parameters)
+    }
+
+    /// Grid of codes in each mosaic frame, if codes are tiled
+    fn mosaic_layout(&self) -> Result<Option<MosaicLayout>> {
+        let Some(resolution) = self.mosaic else {
+            return Ok(None);
+        };
+        self.qr_version()?;
+        container::check_frame_size(resolution, self.module_size)?;
+        let modules = 17 + 4 * self.version as u32;
+        MosaicLayout::new(resolution, modules, self.module_size).map(Some)
+    }
+
+    /// Data bytes in each mosaic code, after its own header and the header
+    /// of the parity code covering it
+    fn mosaic_chunk_len(&self) -> Result<usize> {
+        let capacity = self.max_bytes_per_qr()?;
+        match capacity.checked_sub(2 * FRAME_HEADER_LEN) {
+            Some(len) if len > 0 => Ok(len),
+            _ => Err(Error::Config(format!(
+                "Mosaic codes need more than {} bytes, version {} holds {}",
+                2 * FRAME_HEADER_LEN,
+                self.version,
+                capacity
+            ))),
+        }
+    }
+
+    /// Grid and code reader for a mosaic container, `None` if each code
+    /// fills its own frame
+    fn mosaic_reader(
+        &self,
+        header: &ContainerHeader,
+    ) -> Result<Option<(MosaicLayout, MosaicReader)>> {
+        let Some(layout) = self.mosaic_layout()? else {
+            return Ok(None);
+        };
+        let stream_id = header.parameters["stream_id"]
+            .as_u64()
+            .ok_or_else(|| Error::Decoding("QR mosaic is missing stream_id".to_string()))?;
+        let reader = MosaicReader::new(stream_id as u32, layout.data_codes());
+        Ok(Some((layout, reader)))
+    }
+
+    /// Tile `data` into mosaic frames
+    fn encode_mosaic(
+        &self,
+        layout: &MosaicLayout,
+        data: &[u8],
+        stream_id: u32,
+    ) -> Result<Vec<RgbaImage>> {
+        let chunks: Vec<&[u8]> = data.chunks(self.mosaic_chunk_len()?).collect();
+        let total = chunks.len() as u32;
+        let codes: Vec<Vec<u8>> = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| self.mosaic_code(stream_id, index as u32, Some(total), chunk))
+            .collect::<Result<_>>()?;
+
+        let frames = codes
+            .chunks(layout.data_codes())
+            .enumerate()
+            .map(|(frame, codes)| self.render_mosaic(layout, stream_id, frame as u32, codes))
+            .collect::<Result<Vec<_>>>()?;
+        debug!(
+            "Tiled {} QR codes into {} frames of {}",
+            total,
+            frames.len(),
+            layout.cells()
+        );
+        Ok(frames)
+    }
+
+    /// Bytes of data code `index`, sized so parity over it fills a code
+    fn mosaic_code(
+        &self,
+        stream_id: u32,
+        index: u32,
+        total: Option<u32>,
+        chunk: &[u8],
+    ) -> Result<Vec<u8>> {
+        let header = FrameHeader {
+            stream_id,
+            index,
+            total,
+        };
+        Ok(mosaic::data_code(
+            header,
+            chunk,
+            self.max_bytes_per_qr()? - FRAME_HEADER_LEN,
+        ))
+    }
 
-        for (idx, chunk) in chunks.enumerate() {
-            let qr = QrCode::new(chunk)
-                .map_err(|e| Error::Encoding(format!("QR code generation failed: {}", e)))?;
+    /// Contents of the codes in each cell of a mosaic frame
+    ///
+    /// Cells are scanned one at a time: across a whole frame of aligned
+    /// codes the reader pairs up finder patterns of neighbouring codes. Cell
+    /// bounds scale with the frame, in case it was resized since.
+    fn scan_mosaic(&self, layout: &MosaicLayout, frame: &RgbaImage) -> Vec<Vec<u8>> {
+        let (width, height) = self.mosaic.unwrap_or_default();
+        let scale_x = frame.width() as f64 / width as f64;
+        let scale_y = frame.height() as f64 / height as f64;
+        let scaled =
+            |value: u32, scale: f64, limit: u32| ((value as f64 * scale) as u32).min(limit);
 
-            // Render as image with scaling
-            let image = qr.render::<image::Luma<u8>>()
-                .min_dimensions(512, 512)
+        (0..layout.cells())
+            .flat_map(|cell| {
+                let (left, top) = layout.position(cell);
+                let x = scaled(left, scale_x, frame.width());
+                let y = scaled(top, scale_y, frame.height());
+                let right = scaled(left + layout.side, scale_x, frame.width());
+                let bottom = scaled(top + layout.side, scale_y, frame.height());
+                let tile = image::imageops::crop_imm(frame, x, y, right - x, bottom - y);
+                scan(&tile.to_image())
+            })
+            .collect()
+    }
+
+    /// Draw one mosaic frame: `codes` in reading order, then their parity
+    /// code in the last cell
+    fn render_mosaic(
+        &self,
+        layout: &MosaicLayout,
+        stream_id: u32,
+        frame: u32,
+        codes: &[Vec<u8>],
+    ) -> Result<RgbaImage> {
+        let (width, height) = self.mosaic.unwrap_or_default();
+        let mut image = RgbaImage::from_pixel(width, height, image::Rgba([255, 255, 255, 255]));
+
+        let parity = mosaic::parity_code(stream_id, frame, codes);
+        let cells = codes
+            .iter()
+            .enumerate()
+            .chain(std::iter::once((layout.cells() - 1, &parity)));
+        for (cell, code) in cells {
+            let qr = self.qr_code(code)?;
+            let tile = qr
+                .render::<image::Luma<u8>>()
+                .module_dimensions(self.module_size, self.module_size)
                 .build();
 
-            // Convert to RGBA
-            let rgba = RgbaImage::from_fn(image.width(), image.height(), |x, y| {
-                let luma = image.get_pixel(x, y)[0];
-                image::Rgba([luma, luma, luma, 255])
-            });
+            let (left, top) = layout.position(cell);
+            for (x, y, pixel) in tile.enumerate_pixels() {
+                let luma = pixel[0];
+                image.put_pixel(left + x, top + y, image::Rgba([luma, luma, luma, 255]));
+            }
+        }
+        Ok(image)
+    }
+
+    /// Create QR code images from data
+    fn encode_to_qr_codes(&self, data: &[u8]) -> Result<Vec<RgbaImage>> {
+        let capacity = self.max_bytes_per_qr()?;
+        let count = data.len().div_ceil(capacity);
+        let mut qr_images = Vec::new();
 
-            qr_images.push(rgba);
-            debug!("Created QR code {}/{}", idx + 1, chunks.len());
+        for (idx, chunk) in data.chunks(capacity).enumerate() {
+            qr_images.push(self.encode_qr(chunk)?);
+            debug!("Created QR code {}/{}", idx + 1, count);
         }
 
         Ok(qr_images)
     }
+
+    /// Byte-mode QR code holding `data`
+    ///
+    /// Mixed-mode segmenting can spend more bits than plain byte mode on
+    /// binary data, so a full chunk wouldn't always fit.
+    fn qr_code(&self, data: &[u8]) -> Result<QrCode> {
+        let failed = |e| Error::Encoding(format!("QR code generation failed: {}", e));
+        let mut bits = Bits::new(self.qr_version()?);
+        bits.push_byte_data(data).map_err(failed)?;
+        bits.push_terminator(self.ec_level()).map_err(failed)?;
+        QrCode::with_bits(bits, self.ec_level()).map_err(failed)
+    }
+
+    /// Render one chunk as a QR code image
+    fn encode_qr(&self, chunk: &[u8]) -> Result<RgbaImage> {
+        let qr = self.qr_code(chunk)?;
+
+        // Render as image with scaling
+        let image = qr
+            .render::<image::Luma<u8>>()
+            .min_dimensions(MIN_SIDE, MIN_SIDE)
+            .build();
+
+        // Convert to RGBA
+        Ok(RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+            let luma = image.get_pixel(x, y)[0];
+            image::Rgba([luma, luma, luma, 255])
+        }))
+    }
+}
+
+/// Up to `len` bytes of `input`, fewer only at its end
+async fn read_chunk(input: &mut ByteReader<'_>, len: usize) -> Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(len);
+    (&mut *input)
+        .take(len as u64)
+        .read_to_end(&mut chunk)
+        .await?;
+    Ok(chunk)
+}
+
+/// Contents of every QR code found in `frame`
+///
+/// Codes that are found but can't be read are skipped.
+pub(crate) fn scan(frame: &RgbaImage) -> Vec<Vec<u8>> {
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        frame.width() as usize,
+        frame.height() as usize,
+        |x, y| {
+            let pixel = frame.get_pixel(x as u32, y as u32);
+            ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000) as u8
+        },
+    );
+
+    prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| {
+            let mut content = Vec::new();
+            match grid.decode_to(&mut content) {
+                Ok(_) => Some(content),
+                Err(e) => {
+                    trace!("Skipping unreadable QR code: {:?}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Contents of the one QR code in `frame`
+fn read_qr(frame: &RgbaImage, index: usize) -> Result<Vec<u8>> {
+    let mut codes = scan(frame);
+    match codes.len() {
+        1 => Ok(codes.remove(0)),
+        0 => Err(Error::Corruption(format!(
+            "No readable QR code in frame {}",
+            index
+        ))),
+        found => Err(Error::Decoding(format!(
+            "Expected one QR code in frame {}, found {}",
+            index, found
+        ))),
+    }
 }
 
 impl Default for QREncoder {
@@ -63,40 +455,31 @@ impl Encoder for QREncoder {
     async fn encode(&self, data: &[u8]) -> Result<EncodedData> {
         debug!("Encoding {} bytes as QR codes", data.len());
 
-        let qr_images = self.encode_to_qr_codes(data)?;
-
-        // Serialize QR images to PNG sequence (similar to pixel encoder)
-        let mut encoded = Vec::new();
-        let qr_count = qr_images.len() as u32;
-
-        // Store metadata: QR count (4 bytes) + original size (8 bytes)
-        encoded.extend_from_slice(&qr_count.to_le_bytes());
-        encoded.extend_from_slice(&(data.len() as u64).to_le_bytes());
-
-        // Encode each QR as PNG
-        for qr_img in &qr_images {
-            let mut png_data = Vec::new();
-            qr_img
-                .write_to(
-                    &mut std::io::Cursor::new(&mut png_data),
-                    image::ImageOutputFormat::Png,
-                )
-                .map_err(|e| Error::Encoding(format!("PNG encoding failed: {}", e)))?;
+        let (qr_images, parameters) = match self.mosaic_layout()? {
+            Some(layout) => {
+                let stream_id = crc32fast::hash(data);
+                let frames = self.encode_mosaic(&layout, data, stream_id)?;
+                let qr_count = data.len().div_ceil(self.mosaic_chunk_len()?) as u32;
+                let mut parameters = self.parameters(qr_count)?;
+                parameters["stream_id"] = stream_id.into();
+                (frames, parameters)
+            }
+            None => {
+                let qr_images = self.encode_to_qr_codes(data)?;
+                let qr_count = qr_images.len() as u32;
+                (qr_images, self.parameters(qr_count)?)
+            }
+        };
 
-            let qr_size = png_data.len() as u32;
-            encoded.extend_from_slice(&qr_size.to_le_bytes());
-            encoded.extend_from_slice(&png_data);
-        }
+        let header = ContainerHeader::new(self.strategy.clone(), data, parameters.clone());
+        let encoded = header.wrap(&container::write_png_sequence(&qr_images)?)?;
 
         let metadata = EncodingMetadata {
             original_size: data.len(),
             encoded_size: encoded.len(),
             compression_ratio: encoded.len() as f64 / data.len() as f64,
             strategy: "qr".to_string(),
-            parameters: serde_json::json!({
-                "qr_count": qr_count,
-                "max_bytes_per_qr": self.max_bytes_per_qr,
-            }),
+            parameters,
         };
 
         Ok(EncodedData {
@@ -109,34 +492,175 @@ impl Encoder for QREncoder {
     async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
         debug!("Decoding QR-encoded data");
 
-        // TODO: Implement QR decoding using rqrr or similar
-        // For now, this is a placeholder
-        Err(Error::Decoding(
-            "QR decoding not yet implemented - requires QR scanner library".to_string(),
-        ))
+        let (header, frames) = video::read_frames(&encoded.data)?;
+        let decoder = self.decoder_for(&header)?;
+
+        let mut decoded = Vec::with_capacity(header.original_size as usize);
+        if let Some((layout, mut reader)) = decoder.mosaic_reader(&header)? {
+            for frame in &frames {
+                let codes = decoder.scan_mosaic(&layout, frame);
+                decoded.extend(reader.push_frame(codes).concat());
+            }
+            reader.finish()?;
+            if reader.recovered() > 0 {
+                debug!("Rebuilt {} QR codes from parity", reader.recovered());
+            }
+            decoded.truncate(header.original_size as usize);
+        } else {
+            for (index, frame) in frames.iter().enumerate() {
+                decoded.extend(read_qr(frame, index)?);
+            }
+        }
+
+        header.verify(&decoded)?;
+        Ok(decoded)
     }
 
-    fn strategy(&self) -> &EncodingStrategy {
-        static STRATEGY: once_cell::sync::Lazy<EncodingStrategy> =
-            once_cell::sync::Lazy::new(|| {
-                EncodingStrategy::QREncoding {
-                    version: 40, // Max version
-                    ecc_level: isg_core::ECCLevel::Medium,
+    async fn encode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<EncodingMetadata> {
+        let layout = self.mosaic_layout()?;
+        let capacity = match layout {
+            Some(_) => self.mosaic_chunk_len()?,
+            None => self.max_bytes_per_qr()?,
+        };
+        let stream_id = sequence::random_stream_id();
+        let mut parameters = self.settings()?;
+        if layout.is_some() {
+            parameters["stream_id"] = stream_id.into();
+        }
+        let header = ContainerHeader::streaming(self.strategy.clone(), parameters);
+        let prefix = header.to_bytes()?;
+        output.write_all(&prefix).await?;
+        output
+            .write_all(&container::UNKNOWN_FRAME_COUNT.to_le_bytes())
+            .await?;
+
+        let mut encoded_size = prefix.len() + 4;
+        let mut crc = crc32fast::Hasher::new();
+        let mut original_size = 0u64;
+        let mut qr_count = 0u32;
+        // Codes of the mosaic frame being filled
+        let mut codes = Vec::new();
+        let mut frame = 0u32;
+
+        // Read one chunk ahead so the last code can carry the total
+        let mut chunk = read_chunk(input, capacity).await?;
+        while !chunk.is_empty() {
+            let next = read_chunk(input, capacity).await?;
+            crc.update(&chunk);
+            original_size += chunk.len() as u64;
+
+            match &layout {
+                Some(layout) => {
+                    let last = next.is_empty();
+                    let total = last.then_some(qr_count + 1);
+                    codes.push(self.mosaic_code(stream_id, qr_count, total, &chunk)?);
+                    if codes.len() == layout.data_codes() || last {
+                        let image = self.render_mosaic(layout, stream_id, frame, &codes)?;
+                        encoded_size += container::write_frame(output, &image).await?;
+                        codes.clear();
+                        frame += 1;
+                    }
                 }
-            });
-        &STRATEGY
+                None => {
+                    encoded_size += container::write_frame(output, &self.encode_qr(&chunk)?).await?
+                }
+            }
+            qr_count += 1;
+            chunk = next;
+        }
+
+        let checksum = crc.finalize();
+        output
+            .write_all(&ContainerHeader::trailer(original_size, checksum))
+            .await?;
+        output.flush().await?;
+        encoded_size += container::TRAILER_LEN;
+
+        Ok(EncodingMetadata {
+            original_size: original_size as usize,
+            encoded_size,
+            compression_ratio: encoded_size as f64 / original_size as f64,
+            strategy: "qr".to_string(),
+            parameters: self.parameters(qr_count)?,
+        })
+    }
+
+    async fn decode_stream(
+        &self,
+        input: &mut ByteReader<'_>,
+        output: &mut ByteWriter<'_>,
+    ) -> Result<u64> {
+        let mut header = ContainerHeader::read_from(input).await?;
+        let decoder = self.decoder_for(&header)?;
+        let mut mosaic = decoder.mosaic_reader(&header)?;
+        let mut payload = PayloadReader::new(input, &header);
+        // Frame count, unknown for streamed containers
+        payload.read_exact(4).await?;
+
+        let mut crc = crc32fast::Hasher::new();
+        let mut written = 0u64;
+        // Mosaic code contents held back until we know whether they were the
+        // last (whose tail is padding)
+        let mut held: Vec<u8> = Vec::new();
+        let mut index = 0;
+        while let Some(frame) = container::read_frame(&mut payload).await? {
+            let ready = match &mut mosaic {
+                Some((layout, reader)) => reader.push_frame(decoder.scan_mosaic(layout, &frame)),
+                None => vec![read_qr(&frame, index)?],
+            };
+            for content in ready {
+                output.write_all(&held).await?;
+                crc.update(&held);
+                written += held.len() as u64;
+                held = content;
+            }
+            index += 1;
+        }
+
+        payload.finish(&mut header).await?;
+        if let Some((_, reader)) = &mosaic {
+            reader.finish()?;
+            held.truncate(header.original_size.saturating_sub(written) as usize);
+        }
+        output.write_all(&held).await?;
+        output.flush().await?;
+        crc.update(&held);
+        written += held.len() as u64;
+
+        header.verify_summary(written, crc.finalize())?;
+        Ok(written)
+    }
+
+    fn strategy(&self) -> &EncodingStrategy {
+        &self.strategy
     }
 
     fn estimate_size(&self, input_size: usize) -> usize {
-        let num_qr = (input_size + self.max_bytes_per_qr - 1) / self.max_bytes_per_qr;
-        // Each QR ~512x512 pixels RGBA = ~1MB
-        num_qr * 1024 * 1024
+        if let (Some((width, height)), Ok(Some(layout)), Ok(chunk)) =
+            (self.mosaic, self.mosaic_layout(), self.mosaic_chunk_len())
+        {
+            let frames = input_size.div_ceil(chunk).div_ceil(layout.data_codes());
+            return frames * (width * height * 4) as usize;
+        }
+
+        let capacity = self.max_bytes_per_qr().unwrap_or(1).max(1);
+        let num_qr = input_size.div_ceil(capacity);
+
+        // Codes are rendered at whole pixels per module, quiet zone included
+        let modules = 17 + 4 * self.version.clamp(1, MAX_VERSION) as u32 + 8;
+        let side = MIN_SIDE.div_ceil(modules) * modules;
+        num_qr * (side * side * 4) as usize
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::container::testing::{edit_frames, edit_header, test_data};
 
     #[tokio::test]
     async fn test_qr_encoding() {
@@ -144,10 +668,203 @@ mod tests {
         let data = b"Hello, QR Code World!";
 
         let encoded = encoder.encode(data).await.unwrap();
-        assert!(encoded.data.len() > 0);
+        assert!(ContainerHeader::is_container(&encoded.data));
+        assert_eq!(encoder.decode(&encoded).await.unwrap(), data);
+
+        let mut streamed = Vec::new();
+        let metadata = encoder
+            .encode_stream(&mut &data[..], &mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(metadata.encoded_size, streamed.len());
+        let (header, _) = ContainerHeader::parse(&streamed).unwrap();
+        assert!(header.verify(data).is_ok());
 
-        // Decoding not implemented yet
-        // let decoded = encoder.decode(&encoded).await.unwrap();
-        // assert_eq!(data.as_slice(), decoded.as_slice());
+        let mut decoded = Vec::new();
+        encoder
+            .decode_stream(&mut &streamed[..], &mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_capacity_follows_version_and_level() {
+        let capacity = |version, level| {
+            QREncoder::new()
+                .with_version(version)
+                .with_ecc_level(level)
+                .max_bytes_per_qr()
+                .unwrap()
+        };
+
+        // Byte-mode capacities from the QR specification
+        assert_eq!(capacity(1, ECCLevel::Low), 17);
+        assert_eq!(capacity(1, ECCLevel::High), 7);
+        assert_eq!(capacity(10, ECCLevel::Medium), 213);
+        assert_eq!(capacity(40, ECCLevel::Low), 2953);
+        assert_eq!(capacity(40, ECCLevel::Medium), 2331);
+
+        assert!(QREncoder::new().with_version(0).max_bytes_per_qr().is_err());
+        assert!(QREncoder::new()
+            .with_version(41)
+            .max_bytes_per_qr()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_binary_roundtrip_across_codes() {
+        let encoder = QREncoder::new()
+            .with_version(8)
+            .with_ecc_level(ECCLevel::Quartile);
+        let data = test_data(500, 167);
+
+        let encoded = encoder.encode(&data).await.unwrap();
+        let capacity = encoder.max_bytes_per_qr().unwrap();
+        assert_eq!(
+            encoded.metadata.parameters["qr_count"],
+            data.len().div_ceil(capacity)
+        );
+        assert!(matches!(
+            encoder.strategy(),
+            EncodingStrategy::QREncoding {
+                version: 8,
+                ecc_level: ECCLevel::Quartile
+            }
+        ));
+
+        // Settings come from the header
+        assert_eq!(QREncoder::new().decode(&encoded).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_decode_survives_rescaling() {
+        let encoder = QREncoder::new().with_version(5);
+        let data = b"scaled down and back up".repeat(3);
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        let rescaled = edit_frames(&encoded, |frames| {
+            for frame in frames {
+                *frame =
+                    image::imageops::resize(frame, 300, 300, image::imageops::FilterType::Triangle);
+            }
+        });
+
+        assert_eq!(encoder.decode(&rescaled).await.unwrap(), data);
+    }
+
+    /// Paint over cell `cell` of a mosaic frame
+    fn blank(frame: &mut RgbaImage, layout: &MosaicLayout, cell: usize) {
+        let (left, top) = layout.position(cell);
+        for y in top..top + layout.side {
+            for x in left..left + layout.side {
+                frame.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mosaic_roundtrip_with_lost_codes() {
+        let encoder = QREncoder::new()
+            .with_version(5)
+            .with_mosaic(640, 360)
+            .with_module_size(3);
+        let layout = encoder.mosaic_layout().unwrap().unwrap();
+        assert_eq!(layout.cells(), 8);
+        let data: Vec<u8> = (0..700u32).map(|i| (i * 31 % 251) as u8).collect();
+
+        let encoded = encoder.encode(&data).await.unwrap();
+        assert_eq!(encoded.metadata.parameters["codes_per_frame"], 8);
+        assert_eq!(QREncoder::new().decode(&encoded).await.unwrap(), data);
+
+        // One code lost per frame, frames out of order
+        let damaged = edit_frames(&encoded, |frames| {
+            assert_eq!(frames.len(), 2);
+            blank(&mut frames[0], &layout, 2);
+            blank(&mut frames[1], &layout, 0);
+            frames.reverse();
+        });
+        assert_eq!(QREncoder::new().decode(&damaged).await.unwrap(), data);
+
+        // Cells are found again after the frames were resized
+        let resized = edit_frames(&encoded, |frames| {
+            for frame in frames.iter_mut() {
+                *frame =
+                    image::imageops::resize(frame, 960, 540, image::imageops::FilterType::Triangle);
+            }
+        });
+        assert_eq!(QREncoder::new().decode(&resized).await.unwrap(), data);
+
+        // Two codes lost in one frame are beyond its parity
+        let lost = edit_frames(&encoded, |frames| {
+            blank(&mut frames[1], &layout, 1);
+            blank(&mut frames[1], &layout, 3);
+        });
+        let Err(Error::Corruption(message)) = QREncoder::new().decode(&lost).await else {
+            panic!("lost codes not reported");
+        };
+        assert!(message.contains("[8, 10]"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_mosaic_streaming_roundtrip() {
+        let encoder = QREncoder::new()
+            .with_version(4)
+            .with_ecc_level(ECCLevel::Low)
+            .with_mosaic(400, 300)
+            .with_module_size(3);
+        let data = b"streamed through a mosaic of codes ".repeat(12);
+
+        let mut streamed = Vec::new();
+        let metadata = encoder
+            .encode_stream(&mut &data[..], &mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(metadata.encoded_size, streamed.len());
+
+        let mut decoded = Vec::new();
+        QREncoder::new()
+            .decode_stream(&mut &streamed[..], &mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_mosaic_rejects_small_frames() {
+        let encoder = QREncoder::new().with_mosaic(320, 240);
+        assert!(matches!(encoder.mosaic_layout(), Err(Error::Config(_))));
+
+        // Version 1 codes can't hold two sequence headers
+        let encoder = QREncoder::new().with_version(1).with_mosaic(640, 360);
+        assert!(matches!(encoder.mosaic_chunk_len(), Err(Error::Config(_))));
+
+        let encoder = QREncoder::new().with_mosaic(640, 360).with_module_size(0);
+        assert!(matches!(encoder.mosaic_layout(), Err(Error::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_mosaic_rejects_invalid_header_settings() {
+        let encoder = QREncoder::new()
+            .with_version(5)
+            .with_mosaic(640, 360)
+            .with_module_size(3);
+        let encoded = encoder.encode(b"checked settings").await.unwrap();
+
+        for (name, value) in [
+            ("module_size", serde_json::json!(u32::MAX)),
+            ("module_size", serde_json::json!(0)),
+            ("mosaic", serde_json::json!([u64::MAX, 360])),
+            ("mosaic", serde_json::json!([1_000_000, 1_000_000])),
+        ] {
+            let encoded = edit_header(&encoded, |header| header.parameters[name] = value);
+            let result = QREncoder::new().decode(&encoded).await;
+            assert!(
+                matches!(result, Err(Error::Corruption(_))),
+                "{}: {:?}",
+                name,
+                result
+            );
+        }
     }
 }
@@ -0,0 +1,227 @@
+//! Encoder registry
+//!
+//! Builds configured encoders from an [`EncodingStrategy`], so a catalog can
+//! store the strategy used for each block and reconstruct the exact decoder
+//! later. Container blobs and videos can be decoded directly since their
+//! header records the strategy.
+//!
+//! Hybrid strategies are assembled from the registered encoders of their
+//! stages, so custom encoders can take part in a pipeline.
+
+use crate::video;
+use crate::{
+    ColorEncoder, CompressionEncoder, DNAEncoder, HybridEncoder, PixelEncoder, QREncoder,
+    StegoEncoder,
+};
+use isg_core::{EncodedData, Encoder, EncodingStrategy, Error, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Builds an encoder from a strategy of the kind it was registered for
+pub type EncoderFactory = Arc<dyn Fn(&EncodingStrategy) -> Result<Box<dyn Encoder>> + Send + Sync>;
+
+/// Maps strategy kinds to encoder factories
+#[derive(Clone)]
+pub struct EncoderRegistry {
+    factories: HashMap<&'static str, EncoderFactory>,
+}
+
+impl EncoderRegistry {
+    /// Create a registry with no encoders
+    pub fn empty() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Create a registry with all built-in encoders
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+
+        registry.register("PixelEncoding", |strategy| match strategy {
+            EncodingStrategy::PixelEncoding {
+                block_size,
+                fps,
+                resolution,
+                levels,
+            } => Ok(Box::new(
+                PixelEncoder::new()
+                    .with_block_size(*block_size)
+                    .with_resolution(resolution.0, resolution.1)
+                    .with_fps(*fps)
+                    .with_levels(*levels),
+            )),
+            other => Err(mismatch("PixelEncoding", other)),
+        });
+
+        registry.register("ColorEncoding", |strategy| match strategy {
+            EncodingStrategy::ColorEncoding { color_space } => Ok(Box::new(
+                ColorEncoder::with_color_space(color_space.clone()),
+            )),
+            other => Err(mismatch("ColorEncoding", other)),
+        });
+
+        registry.register("QREncoding", |strategy| match strategy {
+            EncodingStrategy::QREncoding { version, ecc_level } => Ok(Box::new(
+                QREncoder::new()
+                    .with_version(*version)
+                    .with_ecc_level(ecc_level.clone()),
+            )),
+            other => Err(mismatch("QREncoding", other)),
+        });
+
+        registry.register("Steganography", |strategy| match strategy {
+            EncodingStrategy::Steganography {
+                cover_media,
+                method,
+            } => Ok(Box::new(
+                StegoEncoder::new(cover_media.clone()).with_method(method.clone()),
+            )),
+            other => Err(mismatch("Steganography", other)),
+        });
+
+        registry.register("RawCompressed", |strategy| match strategy {
+            EncodingStrategy::RawCompressed { codec } => {
+                Ok(Box::new(CompressionEncoder::new(codec.clone())))
+            }
+            other => Err(mismatch("RawCompressed", other)),
+        });
+
+        registry.register("DNAEncoding", |strategy| match strategy {
+            EncodingStrategy::DNAEncoding => Ok(Box::new(DNAEncoder::new())),
+            other => Err(mismatch("DNAEncoding", other)),
+        });
+
+        registry
+    }
+
+    /// Register (or replace) the factory for a strategy kind
+    ///
+    /// `kind` is the value of [`EncodingStrategy::kind`] the factory handles.
+    pub fn register<F>(&mut self, kind: &'static str, factory: F)
+    where
+        F: Fn(&EncodingStrategy) -> Result<Box<dyn Encoder>> + Send + Sync + 'static,
+    {
+        self.factories.insert(kind, Arc::new(factory));
+    }
+
+    /// Check whether a strategy kind has a factory
+    pub fn supports(&self, kind: &str) -> bool {
+        self.factories.contains_key(kind)
+    }
+
+    /// Build an encoder configured for `strategy`
+    pub fn build(&self, strategy: &EncodingStrategy) -> Result<Box<dyn Encoder>> {
+        if let EncodingStrategy::Hybrid { strategies } = strategy {
+            let stages = strategies
+                .iter()
+                .map(|stage| self.build(stage))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(Box::new(HybridEncoder::new(stages)));
+        }
+
+        let factory = self.factories.get(strategy.kind()).ok_or_else(|| {
+            Error::Config(format!("No encoder registered for {}", strategy.kind()))
+        })?;
+        factory(strategy)
+    }
+
+    /// Build an encoder from a serialized strategy
+    pub fn build_from_json(&self, json: &str) -> Result<Box<dyn Encoder>> {
+        let strategy = serde_json::from_str(json)
+            .map_err(|e| Error::Config(format!("Invalid encoding strategy: {}", e)))?;
+        self.build(&strategy)
+    }
+
+    /// Decode a container blob or video with the encoder recorded in its header
+    pub async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
+        let header = video::read_header(&encoded.data)?;
+        self.build(&header.strategy)?.decode(encoded).await
+    }
+}
+
+impl Default for EncoderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error for a factory handed a strategy of the wrong kind
+fn mismatch(expected: &str, found: &EncodingStrategy) -> Error {
+    Error::Config(format!(
+        "Expected {} strategy, found {}",
+        expected,
+        found.kind()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::{AviCodec, VideoFormat};
+    use isg_core::CompressionCodec;
+
+    #[tokio::test]
+    async fn test_build_matches_strategy() {
+        let registry = EncoderRegistry::new();
+        let strategy = EncodingStrategy::PixelEncoding {
+            block_size: 2,
+            fps: 24,
+            resolution: (640, 480),
+            levels: 4,
+        };
+
+        let json = serde_json::to_string(&strategy).unwrap();
+        let encoder = registry.build_from_json(&json).unwrap();
+        assert_eq!(serde_json::to_string(encoder.strategy()).unwrap(), json);
+
+        let data = b"rebuilt from a stored strategy";
+        let encoded = encoder.encode(data).await.unwrap();
+        assert_eq!(registry.decode(&encoded).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_decode_from_header() {
+        let registry = EncoderRegistry::new();
+        let encoded = CompressionEncoder::brotli(5)
+            .encode(&b"brotli blob ".repeat(20))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            registry.decode(&encoded).await.unwrap(),
+            b"brotli blob ".repeat(20)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_video() {
+        let registry = EncoderRegistry::new();
+        let data = b"video blob ".repeat(20);
+        for format in [VideoFormat::Y4m, VideoFormat::Avi(AviCodec::Uncompressed)] {
+            let encoded = PixelEncoder::new()
+                .with_resolution(160, 120)
+                .with_video_format(format)
+                .encode(&data)
+                .await
+                .unwrap();
+            assert_eq!(registry.decode(&encoded).await.unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_unregistered_strategy() {
+        let mut registry = EncoderRegistry::empty();
+        assert!(registry.build(&EncodingStrategy::DNAEncoding).is_err());
+
+        registry.register("DNAEncoding", |_| Ok(Box::new(CompressionEncoder::none())));
+        assert!(registry.supports("DNAEncoding"));
+        let encoder = registry.build(&EncodingStrategy::DNAEncoding).unwrap();
+        assert!(matches!(
+            encoder.strategy(),
+            EncodingStrategy::RawCompressed {
+                codec: CompressionCodec::None
+            }
+        ));
+    }
+}
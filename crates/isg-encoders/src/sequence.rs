@@ -0,0 +1,255 @@
+//! In-band frame headers
+//!
+//! Once frames are in a video, the container's frame count and order can no
+//! longer be trusted: platforms drop or duplicate frames. Each frame therefore
+//! starts with its own header, carried in the frame's data bits:
+//!
+//! | Field       | Size | Notes                                           |
+//! |-------------|------|-------------------------------------------------|
+//! | stream id   | 4    | shared by all frames of one encoding            |
+//! | frame index | 4    | position of the frame in the stream             |
+//! | total       | 4    | frames in the stream, 0 if not yet known        |
+//! | checksum    | 4    | CRC-32 of the fields above and the payload      |
+//!
+//! All fields are little-endian. Streaming encoders don't know the total up
+//! front, so only their last frame records it.
+
+use isg_core::{Error, Result};
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, RandomState};
+use tracing::{debug, trace};
+
+/// Size of a frame header
+pub const FRAME_HEADER_LEN: usize = 16;
+
+/// Total recorded by frames that don't know how many frames follow
+const UNKNOWN_TOTAL: u32 = 0;
+
+/// Header at the start of every frame's data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Identifies the encoding the frame belongs to
+    pub stream_id: u32,
+
+    /// Position of the frame in the stream
+    pub index: u32,
+
+    /// Frames in the stream, if known when the frame was written
+    pub total: Option<u32>,
+}
+
+impl FrameHeader {
+    /// Frame data: the header followed by `payload`
+    pub fn wrap(&self, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+        frame.extend_from_slice(&self.stream_id.to_le_bytes());
+        frame.extend_from_slice(&self.index.to_le_bytes());
+        frame.extend_from_slice(&self.total.unwrap_or(UNKNOWN_TOTAL).to_le_bytes());
+
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&frame);
+        crc.update(payload);
+        frame.extend_from_slice(&crc.finalize().to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Split frame data into its header and payload
+    pub fn parse(frame: &[u8]) -> Result<(Self, &[u8])> {
+        if frame.len() < FRAME_HEADER_LEN {
+            return Err(Error::Decoding(format!(
+                "Frame holds {} bytes, too short for a frame header",
+                frame.len()
+            )));
+        }
+
+        let field = |i: usize| u32::from_le_bytes(frame[i * 4..i * 4 + 4].try_into().unwrap());
+        let payload = &frame[FRAME_HEADER_LEN..];
+
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&frame[..12]);
+        crc.update(payload);
+        let actual = crc.finalize();
+        if actual != field(3) {
+            return Err(Error::Corruption(format!(
+                "Frame checksum mismatch: expected {:08x}, got {:08x}",
+                field(3),
+                actual
+            )));
+        }
+
+        let header = Self {
+            stream_id: field(0),
+            index: field(1),
+            total: Some(field(2)).filter(|&total| total != UNKNOWN_TOTAL),
+        };
+        Ok((header, payload))
+    }
+}
+
+/// A fresh stream id, for encodings whose data isn't known up front
+pub fn random_stream_id() -> u32 {
+    RandomState::new().hash_one(std::time::SystemTime::now()) as u32
+}
+
+/// Puts frames back in stream order, skipping duplicates, damaged frames and
+/// frames from other streams
+pub struct Reassembler {
+    /// Stream the frames must belong to
+    stream_id: u32,
+
+    /// Index of the next frame to hand out
+    next: u32,
+
+    /// Frames in the stream, once a frame has said
+    total: Option<u32>,
+
+    /// Frames that arrived ahead of their turn
+    pending: BTreeMap<u32, Vec<u8>>,
+
+    /// Frames skipped as damaged
+    damaged: usize,
+}
+
+impl Reassembler {
+    /// Reassemble frames of stream `stream_id`
+    pub fn new(stream_id: u32) -> Self {
+        Self {
+            stream_id,
+            next: 0,
+            total: None,
+            pending: BTreeMap::new(),
+            damaged: 0,
+        }
+    }
+
+    /// Add the data of a received frame, returning the payloads that are now
+    /// next in order
+    pub fn push(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        let (header, payload) = match FrameHeader::parse(frame) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                debug!("Skipping damaged frame: {}", e);
+                self.damaged += 1;
+                return Vec::new();
+            }
+        };
+
+        if header.stream_id != self.stream_id {
+            debug!(
+                "Skipping frame {} of stream {:08x}",
+                header.index, header.stream_id
+            );
+            return Vec::new();
+        }
+        if header.index < self.next || self.pending.contains_key(&header.index) {
+            trace!("Skipping duplicate frame {}", header.index);
+            return Vec::new();
+        }
+
+        if let Some(total) = header.total {
+            self.total = Some(total);
+        }
+        self.pending.insert(header.index, payload.to_vec());
+
+        let mut ready = Vec::new();
+        while let Some(payload) = self.pending.remove(&self.next) {
+            ready.push(payload);
+            self.next += 1;
+        }
+        ready
+    }
+
+    /// Check that no frame is missing
+    pub fn finish(&self) -> Result<()> {
+        // Without a total, frames are only known missing up to the last one seen
+        let end = self
+            .total
+            .or_else(|| self.pending.keys().last().map(|&last| last + 1))
+            .unwrap_or(self.next);
+
+        let missing: Vec<u32> = (self.next..end)
+            .filter(|index| !self.pending.contains_key(index))
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        Err(Error::Corruption(format!(
+            "Missing frames {:?} of {} ({} damaged frames skipped)",
+            missing, end, self.damaged
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(index: u32, total: Option<u32>, payload: &[u8]) -> Vec<u8> {
+        FrameHeader {
+            stream_id: 7,
+            index,
+            total,
+        }
+        .wrap(payload)
+    }
+
+    #[test]
+    fn test_frame_header_roundtrip() {
+        let data = frame(3, Some(9), b"payload");
+        assert_eq!(data.len(), FRAME_HEADER_LEN + 7);
+
+        let (header, payload) = FrameHeader::parse(&data).unwrap();
+        assert_eq!((header.index, header.total), (3, Some(9)));
+        assert_eq!(payload, b"payload");
+
+        let mut flipped = data.clone();
+        flipped[5] ^= 1;
+        assert!(matches!(
+            FrameHeader::parse(&flipped),
+            Err(Error::Corruption(_))
+        ));
+    }
+
+    #[test]
+    fn test_reassembler_reorders_and_skips_duplicates() {
+        let mut reassembler = Reassembler::new(7);
+        let mut out = Vec::new();
+
+        let mut damaged = frame(0, None, b"zz");
+        damaged[FRAME_HEADER_LEN] ^= 0xff;
+        let foreign = FrameHeader {
+            stream_id: 8,
+            index: 0,
+            total: None,
+        }
+        .wrap(b"xx");
+
+        for data in [
+            frame(1, None, b"b"),
+            damaged,
+            foreign,
+            frame(0, None, b"a"),
+            frame(1, None, b"b"),
+            frame(2, Some(3), b"c"),
+        ] {
+            out.extend(reassembler.push(&data));
+        }
+
+        assert_eq!(out, [b"a", b"b", b"c"]);
+        reassembler.finish().unwrap();
+    }
+
+    #[test]
+    fn test_reassembler_reports_missing_frames() {
+        let mut reassembler = Reassembler::new(7);
+        reassembler.push(&frame(0, None, b"a"));
+        reassembler.push(&frame(3, Some(5), b"d"));
+
+        let Err(Error::Corruption(message)) = reassembler.finish() else {
+            panic!("missing frames not reported");
+        };
+        assert!(message.contains("[1, 2, 4]"), "{}", message);
+    }
+}
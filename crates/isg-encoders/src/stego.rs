@@ -0,0 +1,756 @@
+//! Steganography over cover images
+//!
+//! [`StegoEncoder`] hides data in images the user supplies, the strategy's
+//! `cover_media`: a PNG file, or a directory whose PNG files (in name order)
+//! form a frame sequence. The output looks like the cover.
+//!
+//! With [`StegoMethod::LSB`] the data replaces the lowest bits of the red,
+//! green and blue samples; alpha is left alone. Samples are visited in a
+//! pseudo-random order derived from a key, so the data is spread thinly over
+//! the whole cover and can't be read back in order without the key. The
+//! order hides where the data sits but is no cipher: encrypt first if the
+//! contents must stay secret.
+//!
+//! The embedded bit stream is
+//!
+//! | Field    | Size   | Notes                             |
+//! |----------|--------|-----------------------------------|
+//! | length   | 4      | payload bytes                     |
+//! | checksum | 4      | CRC-32 of the length and payload  |
+//! | payload  | length |                                   |
+//!
+//! so extraction can tell it recovered the right bits (and had the right
+//! key). The checksum covers the length so that all-zero bits, as left by a
+//! blank image or heavy recompression, don't read as a valid empty payload.
+//!
+//! A single cover comes out as a plain PNG, with nothing marking it as an
+//! ISG container; several come out as a PNG sequence.
+//!
+//! Low bits are lost as soon as a host recompresses the image to JPEG.
+//! [`StegoMethod::DCT`] carries the same stream in the DCT coefficients JPEG
+//! quantizes, with error correction, so it survives recompression down to a
+//! configurable quality (see [`crate::dct`]) at a much lower capacity.
+
+use crate::channel::SplitMix64;
+use crate::container;
+use crate::dct::{self, DctParams};
+use async_trait::async_trait;
+use image::RgbaImage;
+use isg_core::{
+    EncodedData, Encoder, EncodingMetadata, EncodingStrategy, Error, Result, StegoMethod,
+};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::debug;
+
+/// Length and checksum ahead of the payload
+const STREAM_HEADER_LEN: usize = 8;
+
+/// Colour samples per pixel carrying data
+const CHANNELS: usize = 3;
+
+/// Most low bits replaced in each sample
+const MAX_BITS_PER_CHANNEL: u8 = 4;
+
+/// Default lowest JPEG quality DCT embedding survives
+const DEFAULT_MIN_JPEG_QUALITY: u8 = 75;
+
+/// Default Reed–Solomon check bytes per codeword for DCT embedding
+const DEFAULT_PARITY: usize = 32;
+
+/// First bytes of every PNG file
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Steganography encoder configuration
+#[derive(Clone)]
+pub struct StegoEncoder {
+    /// Path of the cover image or directory of cover frames
+    cover_media: String,
+
+    /// Cover frames supplied directly, used instead of `cover_media`
+    covers: Option<Vec<RgbaImage>>,
+
+    /// Where in the cover the data goes
+    method: StegoMethod,
+
+    /// Key choosing the order samples are visited in
+    key: Vec<u8>,
+
+    /// Low bits replaced in each sample (LSB)
+    bits_per_channel: u8,
+
+    /// Lowest JPEG quality the payload must survive (DCT)
+    min_jpeg_quality: u8,
+
+    /// Reed–Solomon check bytes per codeword (DCT)
+    parity: usize,
+
+    /// Strategy describing the cover and method
+    strategy: EncodingStrategy,
+}
+
+impl StegoEncoder {
+    /// Create an LSB encoder hiding data in `cover_media`, a PNG file or a
+    /// directory of PNG frames
+    pub fn new(cover_media: impl Into<String>) -> Self {
+        let cover_media = cover_media.into();
+        Self {
+            strategy: EncodingStrategy::Steganography {
+                cover_media: cover_media.clone(),
+                method: StegoMethod::LSB,
+            },
+            cover_media,
+            covers: None,
+            method: StegoMethod::LSB,
+            key: Vec::new(),
+            bits_per_channel: 1,
+            min_jpeg_quality: DEFAULT_MIN_JPEG_QUALITY,
+            parity: DEFAULT_PARITY,
+        }
+    }
+
+    /// Create with a custom steganography method
+    pub fn with_method(mut self, method: StegoMethod) -> Self {
+        self.method = method;
+        self.refresh_strategy()
+    }
+
+    /// Create with cover frames in memory instead of reading `cover_media`
+    pub fn with_covers(mut self, covers: Vec<RgbaImage>) -> Self {
+        self.covers = Some(covers);
+        self
+    }
+
+    /// Create with a key choosing where the data goes (the same key is
+    /// needed to extract it)
+    pub fn with_key(mut self, key: impl AsRef<[u8]>) -> Self {
+        self.key = key.as_ref().to_vec();
+        self
+    }
+
+    /// Create with a custom number of low bits replaced per sample (1-4)
+    pub fn with_bits_per_channel(mut self, bits: u8) -> Self {
+        self.bits_per_channel = bits;
+        self
+    }
+
+    /// Create with the lowest JPEG quality (1-100) a DCT payload must
+    /// survive; lower qualities need stronger, more visible embedding
+    pub fn with_min_jpeg_quality(mut self, quality: u8) -> Self {
+        self.min_jpeg_quality = quality;
+        self
+    }
+
+    /// Create with custom error correction for DCT embedding (check bytes
+    /// per Reed–Solomon codeword)
+    pub fn with_error_correction(mut self, parity: usize) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Recompute the strategy after a setting changed
+    fn refresh_strategy(mut self) -> Self {
+        self.strategy = EncodingStrategy::Steganography {
+            cover_media: self.cover_media.clone(),
+            method: self.method.clone(),
+        };
+        self
+    }
+
+    /// Low bits per sample, failing outside 1-4
+    fn lsb_bits(&self) -> Result<u8> {
+        if !(1..=MAX_BITS_PER_CHANNEL).contains(&self.bits_per_channel) {
+            return Err(Error::Config(format!(
+                "Bits per channel must be 1 to {}, got {}",
+                MAX_BITS_PER_CHANNEL, self.bits_per_channel
+            )));
+        }
+        Ok(self.bits_per_channel)
+    }
+
+    /// Cover frames, read from `cover_media` unless supplied directly
+    fn load_covers(&self) -> Result<Cow<'_, [RgbaImage]>> {
+        if let Some(covers) = &self.covers {
+            return Ok(Cow::Borrowed(covers));
+        }
+
+        let path = Path::new(&self.cover_media);
+        let frames = if path.is_dir() {
+            let mut files = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            files.retain(|file| {
+                file.extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+            });
+            files.sort();
+            files
+                .iter()
+                .map(|file| load_cover(file))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            vec![load_cover(path)?]
+        };
+
+        if frames.is_empty() {
+            return Err(Error::Config(format!(
+                "No PNG cover frames in {}",
+                self.cover_media
+            )));
+        }
+        Ok(Cow::Owned(frames))
+    }
+
+    fn dct_params(&self) -> DctParams {
+        DctParams {
+            min_quality: self.min_jpeg_quality,
+            parity: self.parity,
+        }
+    }
+
+    /// Bytes of stream (header and payload) `frames` can carry
+    fn stream_capacity(&self, frames: &[RgbaImage]) -> Result<usize> {
+        match self.method {
+            StegoMethod::LSB => Ok(sample_count(frames) * self.lsb_bits()? as usize / 8),
+            StegoMethod::DCT => self.dct_params().capacity(frames),
+            ref other => Err(Error::Config(format!(
+                "Steganography method {:?} is not supported",
+                other
+            ))),
+        }
+    }
+
+    /// Payload bytes `frames` can hold
+    fn payload_capacity(&self, frames: &[RgbaImage]) -> Result<usize> {
+        Ok(self
+            .stream_capacity(frames)?
+            .saturating_sub(STREAM_HEADER_LEN))
+    }
+
+    /// Payload bytes the covers can hold
+    pub fn capacity(&self) -> Result<usize> {
+        self.payload_capacity(&self.load_covers()?)
+    }
+
+    fn parameters(&self, frames: usize, capacity: usize) -> serde_json::Value {
+        let mut parameters = serde_json::json!({
+            "method": format!("{:?}", self.method),
+            "frames": frames,
+            "capacity": capacity,
+        });
+        match self.method {
+            StegoMethod::DCT => {
+                parameters["min_jpeg_quality"] = self.min_jpeg_quality.into();
+                parameters["parity"] = self.parity.into();
+            }
+            _ => parameters["bits_per_channel"] = self.bits_per_channel.into(),
+        }
+        parameters
+    }
+}
+
+impl std::fmt::Debug for StegoEncoder {
+    // Leaves out the key and the cover pixels
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StegoEncoder")
+            .field("cover_media", &self.cover_media)
+            .field("covers", &self.covers.as_ref().map(Vec::len))
+            .field("method", &self.method)
+            .field("bits_per_channel", &self.bits_per_channel)
+            .field("min_jpeg_quality", &self.min_jpeg_quality)
+            .field("parity", &self.parity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read one cover image
+fn load_cover(path: &Path) -> Result<RgbaImage> {
+    Ok(image::open(path)
+        .map_err(|e| Error::Config(format!("Cannot read cover {}: {}", path.display(), e)))?
+        .to_rgba8())
+}
+
+/// Colour samples across all frames
+fn sample_count(frames: &[RgbaImage]) -> usize {
+    frames
+        .iter()
+        .map(|frame| (frame.width() * frame.height()) as usize * CHANNELS)
+        .sum()
+}
+
+/// Cover frames as stored: a plain PNG for one frame, a PNG sequence for more
+fn write_frames(frames: &[RgbaImage]) -> Result<(Vec<u8>, &'static str)> {
+    match frames {
+        [frame] => Ok((container::png_bytes(frame)?, "png")),
+        _ => Ok((container::write_png_sequence(frames)?, "png_sequence")),
+    }
+}
+
+/// Frames written by [`write_frames`]
+fn read_frames(data: &[u8]) -> Result<Vec<RgbaImage>> {
+    if data.starts_with(&PNG_SIGNATURE) {
+        let frame = image::load_from_memory_with_format(data, image::ImageFormat::Png)
+            .map_err(|e| Error::Decoding(format!("PNG decoding failed: {}", e)))?;
+        return Ok(vec![frame.to_rgba8()]);
+    }
+    container::read_png_sequence(data)
+}
+
+/// Seed for the sample order derived from `key` (FNV-1a)
+fn key_seed(key: &[u8]) -> u64 {
+    key.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Samples in the pseudo-random order chosen by a key
+///
+/// A Fisher–Yates shuffle run one step at a time, remembering only the
+/// positions it has swapped, so reading a prefix of the order costs memory
+/// in proportion to the prefix rather than the cover.
+pub(crate) struct KeyedOrder {
+    /// Samples to order
+    count: usize,
+
+    /// Samples handed out so far
+    next: usize,
+
+    /// Shuffled positions that no longer hold their own index
+    swapped: HashMap<usize, usize>,
+
+    rng: SplitMix64,
+}
+
+impl KeyedOrder {
+    /// Order `count` samples by `key`
+    pub(crate) fn new(count: usize, key: &[u8]) -> Self {
+        Self {
+            count,
+            next: 0,
+            swapped: HashMap::new(),
+            rng: SplitMix64::new(key_seed(key)),
+        }
+    }
+}
+
+impl Iterator for KeyedOrder {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.next >= self.count {
+            return None;
+        }
+
+        let pick = self.next + (self.rng.next_u64() % (self.count - self.next) as u64) as usize;
+        let at = |position: usize, swapped: &HashMap<usize, usize>| {
+            swapped.get(&position).copied().unwrap_or(position)
+        };
+        let sample = at(pick, &self.swapped);
+        let displaced = at(self.next, &self.swapped);
+        self.swapped.insert(pick, displaced);
+        self.swapped.remove(&self.next);
+        self.next += 1;
+        Some(sample)
+    }
+}
+
+/// Locates samples, numbered across all frames, in their frame
+struct Samples {
+    /// Samples before each frame
+    starts: Vec<usize>,
+}
+
+impl Samples {
+    fn new(frames: &[RgbaImage]) -> Self {
+        let starts = frames
+            .iter()
+            .scan(0, |start, frame| {
+                let first = *start;
+                *start += (frame.width() * frame.height()) as usize * CHANNELS;
+                Some(first)
+            })
+            .collect();
+        Self { starts }
+    }
+
+    /// Frame, pixel and channel of `sample`
+    fn locate(&self, frames: &[RgbaImage], sample: usize) -> (usize, u32, u32, usize) {
+        let frame = self.starts.partition_point(|&start| start <= sample) - 1;
+        let offset = sample - self.starts[frame];
+        let pixel = (offset / CHANNELS) as u32;
+        let width = frames[frame].width();
+        (frame, pixel % width, pixel / width, offset % CHANNELS)
+    }
+}
+
+/// Write `stream` into the low `bits` bits of the samples of `frames`, in
+/// the order chosen by `key`
+fn embed_lsb(frames: &mut [RgbaImage], stream: &[u8], bits: u8, key: &[u8]) {
+    let samples = Samples::new(frames);
+    let mut order = KeyedOrder::new(sample_count(frames), key);
+    let mask = (1u8 << bits) - 1;
+
+    let stream_bits: Vec<u8> = stream
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |bit| (byte >> bit) & 1))
+        .collect();
+    for group in stream_bits.chunks(bits as usize) {
+        // A short last group keeps its place in the high bits
+        let value = group
+            .iter()
+            .chain(std::iter::repeat(&0))
+            .take(bits as usize)
+            .fold(0u8, |acc, &bit| (acc << 1) | bit);
+
+        let sample = order.next().expect("stream checked against capacity");
+        let (frame, x, y, channel) = samples.locate(frames, sample);
+        let pixel = frames[frame].get_pixel_mut(x, y);
+        pixel[channel] = (pixel[channel] & !mask) | value;
+    }
+}
+
+/// Reads the bit stream back out of the samples, in key order
+struct LsbReader<'a> {
+    frames: &'a [RgbaImage],
+    samples: Samples,
+    order: KeyedOrder,
+    bits: u8,
+
+    /// Bits read but not yet returned, oldest highest
+    pending: u32,
+    pending_bits: u8,
+}
+
+impl<'a> LsbReader<'a> {
+    fn new(frames: &'a [RgbaImage], bits: u8, key: &[u8]) -> Self {
+        Self {
+            frames,
+            samples: Samples::new(frames),
+            order: KeyedOrder::new(sample_count(frames), key),
+            bits,
+            pending: 0,
+            pending_bits: 0,
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        while self.pending_bits < 8 {
+            let sample = self.order.next()?;
+            let (frame, x, y, channel) = self.samples.locate(self.frames, sample);
+            let value = self.frames[frame].get_pixel(x, y)[channel] & ((1 << self.bits) - 1);
+            self.pending = (self.pending << self.bits) | value as u32;
+            self.pending_bits += self.bits;
+        }
+
+        self.pending_bits -= 8;
+        let byte = (self.pending >> self.pending_bits) as u8;
+        self.pending &= (1 << self.pending_bits) - 1;
+        Some(byte)
+    }
+
+    fn read(&mut self, len: usize) -> Option<Vec<u8>> {
+        (0..len).map(|_| self.read_byte()).collect()
+    }
+}
+
+/// Checksum of a stream's length field and payload
+fn checksum(len: &[u8], payload: &[u8]) -> u32 {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(len);
+    crc.update(payload);
+    crc.finalize()
+}
+
+/// The payload of a stream read through `read` (which returns the next
+/// `len` bytes, if there are that many), checked against its checksum
+///
+/// `capacity` is the most payload the image could hold.
+fn read_payload(
+    mut read: impl FnMut(usize) -> Option<Vec<u8>>,
+    capacity: usize,
+) -> Result<Vec<u8>> {
+    let header = read(STREAM_HEADER_LEN)
+        .ok_or_else(|| Error::Decoding("Image too small to hold a payload".to_string()))?;
+
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let expected = u32::from_le_bytes(header[4..].try_into().unwrap());
+    if len > capacity {
+        return Err(Error::Corruption(format!(
+            "No payload found (claimed {} bytes, image holds {}); wrong key?",
+            len, capacity
+        )));
+    }
+
+    let payload =
+        read(len).ok_or_else(|| Error::Decoding("Payload runs past the image".to_string()))?;
+    let actual = checksum(&header[..4], &payload);
+    if actual != expected {
+        return Err(Error::Corruption(format!(
+            "Payload checksum mismatch: expected {:08x}, got {:08x}",
+            expected, actual
+        )));
+    }
+    Ok(payload)
+}
+
+#[async_trait]
+impl Encoder for StegoEncoder {
+    async fn encode(&self, data: &[u8]) -> Result<EncodedData> {
+        let mut frames = self.load_covers()?.into_owned();
+        let capacity = self.payload_capacity(&frames)?;
+        if data.len() > capacity || data.len() > u32::MAX as usize {
+            return Err(Error::Encoding(format!(
+                "{} bytes don't fit in the cover, which holds {} with {:?} embedding",
+                data.len(),
+                capacity,
+                self.method
+            )));
+        }
+        debug!(
+            "Hiding {} bytes in {} cover frames ({} bytes capacity)",
+            data.len(),
+            frames.len(),
+            capacity
+        );
+
+        let mut stream = Vec::with_capacity(STREAM_HEADER_LEN + data.len());
+        let len = (data.len() as u32).to_le_bytes();
+        stream.extend_from_slice(&len);
+        stream.extend_from_slice(&checksum(&len, data).to_le_bytes());
+        stream.extend_from_slice(data);
+        match self.method {
+            StegoMethod::DCT => dct::embed(&mut frames, &stream, &self.dct_params(), &self.key)?,
+            _ => embed_lsb(&mut frames, &stream, self.lsb_bits()?, &self.key),
+        }
+
+        let (encoded, format) = write_frames(&frames)?;
+        let parameters = self.parameters(frames.len(), capacity);
+        let metadata = EncodingMetadata {
+            original_size: data.len(),
+            encoded_size: encoded.len(),
+            compression_ratio: encoded.len() as f64 / data.len() as f64,
+            strategy: "stego".to_string(),
+            parameters,
+        };
+
+        Ok(EncodedData {
+            data: encoded,
+            format: format.to_string(),
+            metadata,
+        })
+    }
+
+    async fn decode(&self, encoded: &EncodedData) -> Result<Vec<u8>> {
+        debug!("Extracting hidden payload");
+        let frames = read_frames(&encoded.data)?;
+        let capacity = self.payload_capacity(&frames)?;
+
+        match self.method {
+            StegoMethod::DCT => {
+                let stream = dct::extract(&frames, &self.dct_params(), &self.key)?;
+                let mut rest = &stream[..];
+                read_payload(
+                    |len| {
+                        let (bytes, after) = rest.split_at_checked(len)?;
+                        rest = after;
+                        Some(bytes.to_vec())
+                    },
+                    capacity,
+                )
+            }
+            _ => {
+                let mut reader = LsbReader::new(&frames, self.lsb_bits()?, &self.key);
+                read_payload(|len| reader.read(len), capacity)
+            }
+        }
+    }
+
+    fn strategy(&self) -> &EncodingStrategy {
+        &self.strategy
+    }
+
+    /// Size of the cover frames as raw RGBA, or `usize::MAX` when the input
+    /// doesn't fit in the cover at all
+    fn estimate_size(&self, input_size: usize) -> usize {
+        let Ok(frames) = self.load_covers() else {
+            return usize::MAX;
+        };
+        match self.payload_capacity(&frames) {
+            Ok(capacity) if input_size <= capacity => sample_count(&frames) / CHANNELS * 4,
+            _ => usize::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::testing::test_data;
+    use crate::{Channel, Impairment};
+
+    /// A busy, photo-like cover
+    fn cover(width: u32, height: u32, seed: u64) -> RgbaImage {
+        let mut rng = SplitMix64::new(seed);
+        RgbaImage::from_fn(width, height, |x, y| {
+            let noise = (rng.next_u64() % 16) as u8;
+            image::Rgba([
+                (x * 255 / width) as u8 ^ noise,
+                (y * 255 / height) as u8,
+                ((x + y) % 256) as u8 | noise,
+                255,
+            ])
+        })
+    }
+
+    #[tokio::test]
+    async fn test_lsb_roundtrip_keeps_cover_close() {
+        let cover = cover(96, 64, 1);
+        let encoder = StegoEncoder::new("cover.png")
+            .with_covers(vec![cover.clone()])
+            .with_key("secret");
+        let data = b"hidden in plain sight".repeat(8);
+
+        let encoded = encoder.encode(&data).await.unwrap();
+        assert_eq!(encoded.format, "png");
+        assert!(encoded.data.starts_with(&PNG_SIGNATURE));
+        assert_eq!(encoder.decode(&encoded).await.unwrap(), data);
+
+        let stego = read_frames(&encoded.data).unwrap().remove(0);
+        let changed = stego
+            .pixels()
+            .zip(cover.pixels())
+            .flat_map(|(a, b)| a.0.iter().zip(b.0).map(|(&a, b)| a.abs_diff(b)))
+            .inspect(|&diff| assert!(diff <= 1))
+            .filter(|&diff| diff > 0)
+            .count();
+        // About half the bits written already matched the cover
+        let written = (data.len() + STREAM_HEADER_LEN) * 8;
+        assert!(changed > written / 3 && changed < written * 2 / 3);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_or_damage_is_detected() {
+        let encoder = StegoEncoder::new("cover.png")
+            .with_covers(vec![cover(64, 64, 2)])
+            .with_key("right");
+        let data = b"checksummed".to_vec();
+        let encoded = encoder.encode(&data).await.unwrap();
+
+        let wrong = encoder.clone().with_key("wrong");
+        assert!(matches!(
+            wrong.decode(&encoded).await,
+            Err(Error::Corruption(_))
+        ));
+
+        // Flip every low bit: the length or the checksum gives it away
+        let mut frames = read_frames(&encoded.data).unwrap();
+        for pixel in frames[0].pixels_mut() {
+            pixel[0] ^= 1;
+        }
+        let damaged = EncodedData::from_bytes(write_frames(&frames).unwrap().0);
+        assert!(matches!(
+            encoder.decode(&damaged).await,
+            Err(Error::Corruption(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_capacity_and_frame_sequences() {
+        let covers = vec![cover(40, 30, 3), cover(40, 30, 4), cover(20, 10, 5)];
+        let encoder = StegoEncoder::new("frames")
+            .with_covers(covers)
+            .with_bits_per_channel(2)
+            .with_key([7u8; 16]);
+
+        let samples = (40 * 30 * 2 + 20 * 10) * 3;
+        let capacity = encoder.capacity().unwrap();
+        assert_eq!(capacity, samples * 2 / 8 - STREAM_HEADER_LEN);
+        assert_eq!(encoder.estimate_size(capacity), samples / 3 * 4);
+        assert_eq!(encoder.estimate_size(capacity + 1), usize::MAX);
+
+        // Filled to the last bit, spread over every frame
+        let data = test_data(capacity, 73);
+        let encoded = encoder.encode(&data).await.unwrap();
+        assert_eq!(encoded.format, "png_sequence");
+        assert_eq!(encoder.decode(&encoded).await.unwrap(), data);
+
+        assert!(matches!(
+            encoder.encode(&[0; 1][..].repeat(capacity + 1)).await,
+            Err(Error::Encoding(_))
+        ));
+        assert!(matches!(
+            encoder.clone().with_bits_per_channel(5).capacity(),
+            Err(Error::Config(_))
+        ));
+    }
+
+    /// `encoded` after a JPEG round trip at `quality`
+    fn recompress(encoded: &EncodedData, quality: u8) -> EncodedData {
+        let channel = Channel::new().then(Impairment::Jpeg { quality });
+        let frames: Vec<RgbaImage> = read_frames(&encoded.data)
+            .unwrap()
+            .iter()
+            .map(|frame| channel.apply(frame, 0).unwrap())
+            .collect();
+        EncodedData::from_bytes(write_frames(&frames).unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_dct_survives_jpeg_recompression() {
+        let encoder = StegoEncoder::new("photo.png")
+            .with_method(StegoMethod::DCT)
+            .with_covers(vec![cover(256, 192, 8)])
+            .with_min_jpeg_quality(70)
+            .with_key("jpeg-proof");
+        let data = b"survives the image host".repeat(4);
+        assert!(data.len() <= encoder.capacity().unwrap());
+
+        let encoded = encoder.encode(&data).await.unwrap();
+        assert_eq!(encoded.metadata.parameters["min_jpeg_quality"], 70);
+        assert_eq!(encoder.decode(&encoded).await.unwrap(), data);
+        for quality in [70, 85, 95] {
+            let recompressed = recompress(&encoded, quality);
+            assert_eq!(
+                encoder.decode(&recompressed).await.unwrap(),
+                data,
+                "quality {}",
+                quality
+            );
+        }
+
+        // Well below the minimum, the checksum catches what ECC can't fix
+        assert!(encoder.decode(&recompress(&encoded, 10)).await.is_err());
+
+        // LSB embedding is lost at any JPEG quality
+        let lsb = encoder.clone().with_method(StegoMethod::LSB);
+        let encoded = lsb.encode(&data).await.unwrap();
+        assert!(lsb.decode(&recompress(&encoded, 95)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cover_read_from_path() {
+        let dir = std::env::temp_dir().join(format!("isg-stego-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        cover(32, 32, 6).save(dir.join("b.png")).unwrap();
+        cover(32, 32, 7).save(dir.join("a.png")).unwrap();
+
+        let single = StegoEncoder::new(dir.join("a.png").to_string_lossy());
+        let sequence = StegoEncoder::new(dir.to_string_lossy());
+        let data = b"from disk".to_vec();
+
+        let encoded = single.encode(&data).await.unwrap();
+        assert_eq!(encoded.format, "png");
+        assert_eq!(single.decode(&encoded).await.unwrap(), data);
+
+        let encoded = sequence.encode(&data).await.unwrap();
+        assert_eq!(encoded.metadata.parameters["frames"], 2);
+        assert_eq!(sequence.decode(&encoded).await.unwrap(), data);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            StegoEncoder::new(dir.to_string_lossy()).capacity(),
+            Err(Error::Config(_))
+        ));
+    }
+}
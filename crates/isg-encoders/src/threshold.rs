@@ -0,0 +1,435 @@
+//! Adaptive thresholding for black/white frames
+//!
+//! Platforms shift brightness and contrast when they re-encode video, so a
+//! fixed cut-off between black and white does not survive the round trip.
+//! Instead the decoder picks a threshold per frame. When the encoder wrote
+//! calibration cells in a known alternating pattern, the threshold sits halfway
+//! between the black and white levels they measure, so data biased toward one
+//! level can't drag it; otherwise Otsu's method splits the block brightness
+//! histogram. Regional mode refines the frame threshold with Otsu per region.
+//! The calibration cells also give a reference for the confidence reported per
+//! frame.
+
+/// How the decoder separates black from white blocks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Thresholding {
+    /// Compare against a fixed brightness
+    Fixed(u8),
+
+    /// One threshold per frame, from calibration cells or Otsu
+    #[default]
+    Frame,
+
+    /// Otsu thresholds over an `n x n` grid of regions, falling back to the
+    /// frame threshold where a region has too little contrast
+    Regional(u32),
+}
+
+/// Normalized margin below which a block counts as uncertain
+pub(crate) const UNCERTAIN_MARGIN: f64 = 0.25;
+
+/// Minimum class separation for trusting Otsu on frames without reference cells
+const MIN_CONTRAST: f64 = 48.0;
+
+/// Noise floor (in brightness levels) for soft decisions
+pub(crate) const MIN_NOISE: f64 = 1.0;
+
+/// Threshold used when a frame offers nothing to adapt to
+const DEFAULT_THRESHOLD: u8 = 128;
+
+/// Per-frame decoding diagnostics
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameReport {
+    /// Frame-wide threshold (blocks darker than this read as 1)
+    pub threshold: u8,
+
+    /// Average brightness of black blocks (reference cells when present)
+    pub black_level: f64,
+
+    /// Average brightness of white blocks (reference cells when present)
+    pub white_level: f64,
+
+    /// Mean distance of data blocks from their threshold, relative to half
+    /// the black/white contrast (1.0 = clean, 0.0 = indistinguishable)
+    pub confidence: f64,
+
+    /// Data blocks too close to their threshold to read reliably
+    pub uncertain_blocks: usize,
+
+    /// Calibration and alignment marker cells that read back wrong
+    pub calibration_errors: usize,
+
+    /// Estimated bit error rate, assuming Gaussian noise around each level
+    pub estimated_bit_error_rate: f64,
+
+    /// Bytes fixed by error correction (0 without it)
+    pub corrected_symbols: usize,
+}
+
+/// Thresholds chosen for one frame
+pub(crate) struct FrameThresholds {
+    /// Threshold for every block, row-major
+    per_block: Vec<u8>,
+
+    /// Spread of block brightness around the black and white levels
+    noise: f64,
+
+    /// Diagnostics for the frame
+    pub report: FrameReport,
+}
+
+impl FrameThresholds {
+    /// Log-likelihood ratio that the block at `index` is black, assuming
+    /// Gaussian noise around the black and white levels
+    pub fn soft(&self, index: usize, level: u8) -> f32 {
+        let contrast = (self.report.white_level - self.report.black_level).max(1.0);
+        let distance = self.per_block[index] as f64 - level as f64;
+        (distance * contrast / self.noise.powi(2)) as f32
+    }
+}
+
+/// Choose thresholds for a frame of block brightness `levels` (row-major,
+/// `blocks_x` wide)
+///
+/// `reference` lists cells with a known value (calibration cells and
+/// markers, true = black); `data` lists the cells carrying data.
+pub(crate) fn analyze(
+    levels: &[u8],
+    blocks_x: usize,
+    reference: &[(usize, bool)],
+    data: &[usize],
+    mode: Thresholding,
+) -> FrameThresholds {
+    let frame_threshold = match mode {
+        Thresholding::Fixed(threshold) => threshold,
+        _ => match reference_levels(levels, reference) {
+            Some((black, white)) if white > black => ((black + white) / 2.0).round() as u8,
+            _ => {
+                let threshold = otsu(levels.iter().copied());
+                let (dark, bright) = class_means(levels.iter().copied(), threshold);
+
+                // Without usable reference cells a uniform frame is all noise to Otsu
+                if bright - dark < MIN_CONTRAST {
+                    DEFAULT_THRESHOLD
+                } else {
+                    threshold
+                }
+            }
+        },
+    };
+
+    let per_block = spread(levels, blocks_x, mode, frame_threshold);
+    let report = report(levels, reference, data, frame_threshold, &per_block);
+    let noise =
+        class_spread(levels, &per_block).map_or(MIN_NOISE, |(noise, _)| noise.max(MIN_NOISE));
+    FrameThresholds {
+        per_block,
+        noise,
+        report,
+    }
+}
+
+/// Threshold for every pixel of a row-major grayscale image `width` wide,
+/// used to binarize whole frames when searching for alignment markers
+pub(crate) fn pixel_thresholds(luma: &[u8], width: usize, mode: Thresholding) -> Vec<u8> {
+    let frame_threshold = match mode {
+        Thresholding::Fixed(threshold) => threshold,
+        _ => otsu(luma.iter().copied()),
+    };
+    spread(luma, width, mode, frame_threshold)
+}
+
+/// Per-element thresholds for `mode` around a frame-wide threshold
+fn spread(levels: &[u8], width: usize, mode: Thresholding, frame_threshold: u8) -> Vec<u8> {
+    match mode {
+        Thresholding::Regional(regions) if regions > 1 && !levels.is_empty() => {
+            regional(levels, width, regions as usize, frame_threshold)
+        }
+        _ => vec![frame_threshold; levels.len()],
+    }
+}
+
+/// Otsu's method: the cut maximizing between-class variance
+///
+/// Returns the first brightness of the bright class.
+pub(crate) fn otsu(levels: impl Iterator<Item = u8>) -> u8 {
+    let mut histogram = [0u64; 256];
+    let mut total = 0u64;
+    for level in levels {
+        histogram[level as usize] += 1;
+        total += 1;
+    }
+
+    if total == 0 {
+        return DEFAULT_THRESHOLD;
+    }
+
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &count)| value as f64 * count as f64)
+        .sum();
+
+    let mut best = (0.0, DEFAULT_THRESHOLD);
+    let mut dark_count = 0u64;
+    let mut dark_sum = 0.0;
+
+    for (value, &count) in histogram.iter().enumerate().take(255) {
+        dark_count += count;
+        dark_sum += value as f64 * count as f64;
+        if dark_count == 0 || dark_count == total {
+            continue;
+        }
+
+        let bright_count = total - dark_count;
+        let dark_mean = dark_sum / dark_count as f64;
+        let bright_mean = (sum - dark_sum) / bright_count as f64;
+        let variance = dark_count as f64 * bright_count as f64 * (dark_mean - bright_mean).powi(2);
+
+        if variance > best.0 {
+            best = (variance, value as u8 + 1);
+        }
+    }
+
+    // Ties span the empty gap between classes; cut in its middle
+    let (variance, first) = best;
+    let mut last = first;
+    while last < 255 && histogram[last as usize] == 0 {
+        last += 1;
+    }
+    if variance > 0.0 && histogram[first as usize] == 0 {
+        return first + (last - first) / 2;
+    }
+
+    first
+}
+
+/// Per-block thresholds from Otsu over each region
+fn regional(levels: &[u8], blocks_x: usize, regions: usize, fallback: u8) -> Vec<u8> {
+    let blocks_y = levels.len().div_ceil(blocks_x);
+    let region_w = blocks_x.div_ceil(regions).max(1);
+    let region_h = blocks_y.div_ceil(regions).max(1);
+    let (black, white) = class_means(levels.iter().copied(), fallback);
+    let contrast = white - black;
+
+    let mut per_block = vec![fallback; levels.len()];
+    for ry in (0..blocks_y).step_by(region_h) {
+        for rx in (0..blocks_x).step_by(region_w) {
+            let indices: Vec<usize> = (ry..(ry + region_h).min(blocks_y))
+                .flat_map(|y| (rx..(rx + region_w).min(blocks_x)).map(move |x| y * blocks_x + x))
+                .filter(|&i| i < levels.len())
+                .collect();
+
+            let local = otsu(indices.iter().map(|&i| levels[i]));
+            let (dark, bright) = class_means(indices.iter().map(|&i| levels[i]), local);
+
+            // A region holding only one class has no meaningful local cut
+            let threshold = if bright - dark >= contrast / 2.0 {
+                local
+            } else {
+                fallback
+            };
+            for i in indices {
+                per_block[i] = threshold;
+            }
+        }
+    }
+
+    per_block
+}
+
+/// Mean brightness below and at/above `threshold`
+fn class_means(levels: impl Iterator<Item = u8>, threshold: u8) -> (f64, f64) {
+    let (mut dark, mut bright) = ((0.0, 0u64), (0.0, 0u64));
+    for level in levels {
+        let class = if level < threshold {
+            &mut dark
+        } else {
+            &mut bright
+        };
+        class.0 += level as f64;
+        class.1 += 1;
+    }
+
+    let mean = |(sum, count): (f64, u64), default: f64| {
+        if count == 0 {
+            default
+        } else {
+            sum / count as f64
+        }
+    };
+    (mean(dark, 0.0), mean(bright, 255.0))
+}
+
+/// Mean brightness of the black and white reference cells, if both are present
+fn reference_levels(levels: &[u8], reference: &[(usize, bool)]) -> Option<(f64, f64)> {
+    let mean = |black: bool| {
+        let cells: Vec<f64> = reference
+            .iter()
+            .filter(|&&(_, bit)| bit == black)
+            .map(|&(i, _)| levels[i] as f64)
+            .collect();
+        (!cells.is_empty()).then(|| cells.iter().sum::<f64>() / cells.len() as f64)
+    };
+    Some((mean(true)?, mean(false)?))
+}
+
+/// Build the diagnostics for a frame
+fn report(
+    levels: &[u8],
+    reference: &[(usize, bool)],
+    data: &[usize],
+    threshold: u8,
+    per_block: &[u8],
+) -> FrameReport {
+    let (black_level, white_level) = reference_levels(levels, reference)
+        .unwrap_or_else(|| class_means(levels.iter().copied(), threshold));
+
+    let calibration_errors = reference
+        .iter()
+        .filter(|&&(i, bit)| (levels[i] < per_block[i]) != bit)
+        .count();
+
+    let half_contrast = ((white_level - black_level) / 2.0).max(1.0);
+    let mut margin_sum = 0.0;
+    let mut uncertain_blocks = 0;
+    for &i in data {
+        let margin = ((levels[i] as f64 - per_block[i] as f64).abs() / half_contrast).min(1.0);
+        margin_sum += margin;
+        if margin < UNCERTAIN_MARGIN {
+            uncertain_blocks += 1;
+        }
+    }
+    let data_blocks = data.len();
+    let confidence = if data_blocks == 0 {
+        1.0
+    } else {
+        margin_sum / data_blocks as f64
+    };
+
+    FrameReport {
+        threshold,
+        black_level,
+        white_level,
+        confidence,
+        uncertain_blocks,
+        calibration_errors,
+        estimated_bit_error_rate: bit_error_rate(levels, per_block),
+        corrected_symbols: 0,
+    }
+}
+
+/// Gaussian estimate: each class is spread around its mean with the pooled
+/// standard deviation, and errors are the tails crossing the midpoint
+fn bit_error_rate(levels: &[u8], per_block: &[u8]) -> f64 {
+    match class_spread(levels, per_block) {
+        Some((pooled, distance)) if pooled >= 1e-9 => {
+            0.5 * erfc(distance / (pooled * std::f64::consts::SQRT_2))
+        }
+        _ => 0.0,
+    }
+}
+
+/// Pooled standard deviation of the dark and bright classes, and half the
+/// distance between their means
+fn class_spread(levels: &[u8], per_block: &[u8]) -> Option<(f64, f64)> {
+    let mut classes = [(0.0, 0.0, 0u64); 2];
+    for (&level, &threshold) in levels.iter().zip(per_block) {
+        let class = &mut classes[(level >= threshold) as usize];
+        class.0 += level as f64;
+        class.1 += (level as f64).powi(2);
+        class.2 += 1;
+    }
+
+    let [dark, bright] = classes;
+    if dark.2 == 0 || bright.2 == 0 {
+        return None;
+    }
+
+    let variance = |(sum, squares, count): (f64, f64, u64)| squares - sum * sum / count as f64;
+    let pooled = ((variance(dark) + variance(bright)) / levels.len() as f64).sqrt();
+    let distance = (bright.0 / bright.2 as f64 - dark.0 / dark.2 as f64) / 2.0;
+    Some((pooled, distance))
+}
+
+/// Complementary error function (Abramowitz & Stegun 7.1.26, |error| < 1.5e-7)
+pub(crate) fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    poly * (-x * x).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_otsu_splits_shifted_levels() {
+        // Low-contrast, brightened frame: black at ~150, white at ~230
+        let levels: Vec<u8> = (0..200)
+            .map(|i| if i % 3 == 0 { 148 } else { 231 })
+            .collect();
+        let threshold = otsu(levels.iter().copied());
+        assert!(threshold > 148 && threshold <= 231, "{}", threshold);
+
+        let data: Vec<usize> = (0..200).collect();
+        let frame = analyze(&levels, 20, &[], &data, Thresholding::Frame);
+        assert_eq!(frame.report.uncertain_blocks, 0);
+        assert!(frame.report.confidence > 0.9);
+        assert!(frame.report.estimated_bit_error_rate < 1e-6);
+    }
+
+    #[test]
+    fn test_calibration_and_noise() {
+        let mut levels: Vec<u8> = (0..20).map(|i| if i % 2 == 0 { 10 } else { 245 }).collect();
+        // Uniform data would give Otsu nothing to split without calibration
+        levels.extend(std::iter::repeat_n(245, 180));
+
+        let reference: Vec<(usize, bool)> = (0..20).map(|i| (i, i % 2 == 0)).collect();
+        let data: Vec<usize> = (20..200).collect();
+        let frame = analyze(&levels, 20, &reference, &data, Thresholding::Frame);
+        assert_eq!(frame.report.calibration_errors, 0);
+        assert_eq!(frame.report.black_level, 10.0);
+        assert!((20..200).all(|i| frame.soft(i, levels[i]) < 0.0));
+
+        // Noisy levels near the middle lower confidence
+        for (i, level) in levels.iter_mut().enumerate().skip(20) {
+            *level = if i % 2 == 0 { 110 } else { 150 };
+        }
+        let noisy = analyze(&levels, 20, &reference, &data, Thresholding::Frame);
+        assert!(noisy.report.confidence < 0.6);
+        assert!(noisy.report.estimated_bit_error_rate > 0.0);
+    }
+
+    #[test]
+    fn test_calibration_sets_threshold_for_biased_data() {
+        // Reference cells at 60 and 187; data all white, but noisy enough to
+        // land at 150 or 224
+        let mut levels: Vec<u8> = (0..20).map(|i| if i % 2 == 0 { 60 } else { 187 }).collect();
+        levels.extend((0..380).map(|i| if i == 7 { 60 } else { [150, 224][i % 2] }));
+
+        let reference: Vec<(usize, bool)> = (0..20).map(|i| (i, i % 2 == 0)).collect();
+        let data: Vec<usize> = (20..400).collect();
+
+        // Otsu alone splits the white noise in two
+        assert!(otsu(levels.iter().copied()) > 160);
+
+        let frame = analyze(&levels, 20, &reference, &data, Thresholding::Frame);
+        assert_eq!(frame.report.threshold, 124);
+        assert_eq!(frame.report.calibration_errors, 0);
+        assert!((20..400).all(|i| (levels[i] < frame.report.threshold) == (i == 27)));
+    }
+
+    #[test]
+    fn test_erfc() {
+        assert!((erfc(0.0) - 1.0).abs() < 1e-6);
+        assert!((erfc(1.0) - 0.157299207).abs() < 1e-6);
+        assert!((erfc(-1.0) - 1.842700793).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,1098 @@
+//! Video files holding encoded frames
+//!
+//! A PNG sequence is convenient but no platform accepts it as a video. These
+//! writers turn frames into files that players and upload forms understand,
+//! without external tools:
+//!
+//! - Y4M (YUV4MPEG2): raw BT.601 limited-range YUV 4:2:0. Luma survives
+//!   exactly up to rounding; chroma is shared by each 2x2 pixels.
+//! - AVI with uncompressed 24-bit frames (lossless) or Motion JPEG.
+//!
+//! The ISG container header (without payload) travels inside the file so the
+//! video decodes without knowing how it was made: as an `XISG=<hex>` stream
+//! parameter in Y4M, and as an `isgh` chunk between the header and movie
+//! lists in AVI. Players skip both.
+//!
+//! [`VideoReader`] reads either format back one frame at a time. AVI files
+//! are limited to 4 GiB (no OpenDML extension).
+//!
+//! Y4M also streams: [`FrameWriter`] and [`FrameReader`] handle it one frame
+//! at a time alongside PNG sequence containers. A streamed container header
+//! lacks the size and checksum, so the last frame carries the container
+//! trailer as an `XISGEND=<hex>` frame parameter. AVI headers record sizes
+//! known only once every frame is written, so AVI doesn't stream.
+
+use crate::color::{chroma_420, yuv_of, yuv_to_rgb};
+use crate::container::{self, ContainerHeader, PayloadReader};
+use image::{ImageOutputFormat, Rgba, RgbaImage};
+use isg_core::{ByteReader, ByteWriter, Error, Result};
+use std::io::Cursor;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+/// Y4M stream signature
+const Y4M_MAGIC: &[u8] = b"YUV4MPEG2";
+
+/// Y4M stream parameter carrying the ISG header
+const Y4M_METADATA: &str = "XISG=";
+
+/// Y4M frame parameter carrying the trailer of a streamed ISG header
+const Y4M_TRAILER: &str = "XISGEND=";
+
+/// Longest Y4M header line read from a stream, room for any ISG header in hex
+const MAX_Y4M_LINE: usize = 4 * 1024 * 1024;
+
+/// Chunk holding the ISG header in AVI files
+const AVI_METADATA: &[u8; 4] = b"isgh";
+
+/// AVI main header flag: the file has an `idx1` index
+const AVIF_HASINDEX: u32 = 0x10;
+
+/// AVI index entry flag: the chunk is a key frame
+const AVIIF_KEYFRAME: u32 = 0x10;
+
+/// How encoded frames are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// ISG container holding size-prefixed PNG frames
+    #[default]
+    PngSequence,
+
+    /// YUV4MPEG2 with 4:2:0 chroma
+    Y4m,
+
+    /// AVI with the given frame codec
+    Avi(AviCodec),
+}
+
+/// Frame codec inside an AVI file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AviCodec {
+    /// 24-bit bottom-up RGB, lossless
+    Uncompressed,
+
+    /// Motion JPEG at `quality` (1-100)
+    Mjpeg { quality: u8 },
+}
+
+impl VideoFormat {
+    /// Name recorded as [`isg_core::EncodedData::format`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoFormat::PngSequence => "png_sequence",
+            VideoFormat::Y4m => "y4m",
+            VideoFormat::Avi(_) => "avi",
+        }
+    }
+
+    /// Write `frames` as a video at `fps`, carrying `metadata` (an ISG
+    /// container header)
+    ///
+    /// PNG sequences are written by the container module, not here.
+    pub fn write(&self, frames: &[RgbaImage], fps: u32, metadata: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            VideoFormat::PngSequence => Err(Error::Config(
+                "PNG sequences are not a video format".to_string(),
+            )),
+            VideoFormat::Y4m => write_y4m(frames, fps, metadata),
+            VideoFormat::Avi(codec) => write_avi(frames, fps, codec, metadata),
+        }
+    }
+}
+
+/// Container header and frames of an encoding, written either as a PNG
+/// sequence or as a video carrying the header
+pub(crate) fn read_frames(data: &[u8]) -> Result<(ContainerHeader, Vec<RgbaImage>)> {
+    if !is_video(data) {
+        let (header, payload) = ContainerHeader::parse(data)?;
+        return Ok((header, container::read_png_sequence(payload)?));
+    }
+
+    let reader = VideoReader::parse(data)?;
+    let header = reader.header()?;
+    Ok((header, reader.collect::<Result<_>>()?))
+}
+
+/// Container header of an encoding, without decoding its frames
+pub(crate) fn read_header(data: &[u8]) -> Result<ContainerHeader> {
+    if is_video(data) {
+        VideoReader::parse(data)?.header()
+    } else {
+        Ok(ContainerHeader::parse(data)?.0)
+    }
+}
+
+/// Check whether `data` starts like a video file this module reads
+pub fn is_video(data: &[u8]) -> bool {
+    data.starts_with(Y4M_MAGIC) || (data.starts_with(b"RIFF") && data.get(8..12) == Some(b"AVI "))
+}
+
+/// Write frames as Y4M
+pub fn write_y4m(frames: &[RgbaImage], fps: u32, metadata: &[u8]) -> Result<Vec<u8>> {
+    let (width, height) = frame_size(frames, fps)?;
+
+    let mut out = y4m_header((width, height), fps, metadata);
+    for frame in frames {
+        out.extend_from_slice(&y4m_frame(frame, ""));
+    }
+
+    Ok(out)
+}
+
+/// Y4M stream header line
+fn y4m_header((width, height): (u32, u32), fps: u32, metadata: &[u8]) -> Vec<u8> {
+    format!(
+        "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED {}{}\n",
+        width,
+        height,
+        fps,
+        Y4M_METADATA,
+        hex::encode(metadata)
+    )
+    .into_bytes()
+}
+
+/// Y4M frame: its header line, with `parameters` if any, then its planes
+fn y4m_frame(frame: &RgbaImage, parameters: &str) -> Vec<u8> {
+    let mut out = format!("FRAME{}\n", parameters).into_bytes();
+    for pixel in frame.pixels() {
+        out.push(to_limited(yuv_of(pixel))[0]);
+    }
+    let (u_plane, v_plane): (Vec<u8>, Vec<u8>) = chroma_420(frame)
+        .into_iter()
+        .map(|[u, v]| {
+            let [_, u, v] = to_limited([0.0, u, v]);
+            (u, v)
+        })
+        .unzip();
+    out.extend_from_slice(&u_plane);
+    out.extend_from_slice(&v_plane);
+    out
+}
+
+/// Write frames as AVI
+pub fn write_avi(
+    frames: &[RgbaImage],
+    fps: u32,
+    codec: AviCodec,
+    metadata: &[u8],
+) -> Result<Vec<u8>> {
+    let (width, height) = frame_size(frames, fps)?;
+    let image_size = u32::try_from(bgr_stride(width) * height as usize)
+        .map_err(|_| Error::Encoding("AVI frames are limited to 4 GiB".to_string()))?;
+
+    let (chunk_id, handler, compression) = match codec {
+        AviCodec::Uncompressed => (b"00db", *b"DIB ", 0u32),
+        AviCodec::Mjpeg { .. } => (b"00dc", *b"MJPG", u32::from_le_bytes(*b"MJPG")),
+    };
+
+    let images = frames
+        .iter()
+        .map(|frame| match codec {
+            AviCodec::Uncompressed => Ok(bottom_up_bgr(frame)),
+            AviCodec::Mjpeg { quality } => jpeg_bytes(frame, quality),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let largest = images.iter().map(Vec::len).max().unwrap_or(0) as u32;
+
+    // Main header
+    let mut avih = Vec::with_capacity(56);
+    for value in [
+        1_000_000 / fps,
+        largest.saturating_mul(fps),
+        0,
+        AVIF_HASINDEX,
+        frames.len() as u32,
+        0,
+        1,
+        largest,
+        width,
+        height,
+        0,
+        0,
+        0,
+        0,
+    ] {
+        avih.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Stream header
+    let mut strh = Vec::with_capacity(56);
+    strh.extend_from_slice(b"vids");
+    strh.extend_from_slice(&handler);
+    for value in [
+        0u32,
+        0,
+        0,
+        1,
+        fps,
+        0,
+        frames.len() as u32,
+        largest,
+        u32::MAX,
+        0,
+    ] {
+        strh.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [0u16, 0, width as u16, height as u16] {
+        strh.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Stream format: BITMAPINFOHEADER, bottom-up
+    let mut strf = Vec::with_capacity(40);
+    strf.extend_from_slice(&40u32.to_le_bytes());
+    strf.extend_from_slice(&(width as i32).to_le_bytes());
+    strf.extend_from_slice(&(height as i32).to_le_bytes());
+    strf.extend_from_slice(&1u16.to_le_bytes());
+    strf.extend_from_slice(&24u16.to_le_bytes());
+    strf.extend_from_slice(&compression.to_le_bytes());
+    strf.extend_from_slice(&image_size.to_le_bytes());
+    strf.extend_from_slice(&[0u8; 16]);
+
+    let mut strl = b"strl".to_vec();
+    push_chunk(&mut strl, b"strh", &strh);
+    push_chunk(&mut strl, b"strf", &strf);
+
+    let mut hdrl = b"hdrl".to_vec();
+    push_chunk(&mut hdrl, b"avih", &avih);
+    push_chunk(&mut hdrl, b"LIST", &strl);
+
+    // Frames, indexed by offset from the `movi` list type
+    let mut movi = b"movi".to_vec();
+    let mut index = Vec::with_capacity(images.len() * 16);
+    for image in &images {
+        index.extend_from_slice(chunk_id);
+        index.extend_from_slice(&AVIIF_KEYFRAME.to_le_bytes());
+        index.extend_from_slice(&(movi.len() as u32).to_le_bytes());
+        index.extend_from_slice(&(image.len() as u32).to_le_bytes());
+        push_chunk(&mut movi, chunk_id, image);
+    }
+
+    let mut riff = b"AVI ".to_vec();
+    push_chunk(&mut riff, b"LIST", &hdrl);
+    push_chunk(&mut riff, AVI_METADATA, metadata);
+    push_chunk(&mut riff, b"LIST", &movi);
+    push_chunk(&mut riff, b"idx1", &index);
+
+    let size = u32::try_from(riff.len())
+        .map_err(|_| Error::Encoding("AVI files are limited to 4 GiB".to_string()))?;
+    let mut out = Vec::with_capacity(riff.len() + 8);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&riff);
+    Ok(out)
+}
+
+/// How frames are stored in a file being read
+#[derive(Clone, Copy, Debug)]
+enum FrameCodec {
+    /// Y4M planes with chroma subsampled by `(x, y)`, and whether the range
+    /// is full
+    Yuv {
+        subsampling: (u32, u32),
+        full_range: bool,
+    },
+
+    /// Y4M luma only
+    Mono { full_range: bool },
+
+    /// 24-bit BGR rows, bottom-up unless `top_down`
+    Bgr { top_down: bool },
+
+    /// JPEG images
+    Jpeg,
+}
+
+/// Reads the frames of a Y4M or AVI file one at a time
+pub struct VideoReader<'a> {
+    width: u32,
+    height: u32,
+    fps: f64,
+    metadata: Vec<u8>,
+    codec: FrameCodec,
+
+    /// Trailer completing a streamed ISG header, from the last Y4M frame
+    trailer: Vec<u8>,
+
+    /// Encoded frames not yet read
+    frames: std::vec::IntoIter<&'a [u8]>,
+}
+
+impl<'a> VideoReader<'a> {
+    /// Parse the headers of a Y4M or AVI file and locate its frames
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if data.starts_with(Y4M_MAGIC) {
+            Self::parse_y4m(data)
+        } else if is_video(data) {
+            Self::parse_avi(data)
+        } else {
+            Err(Error::Decoding("Not a Y4M or AVI file".to_string()))
+        }
+    }
+
+    /// Frame width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Frame height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Frames per second
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Bytes embedded by the writer (the ISG container header), empty if
+    /// the file has none
+    pub fn metadata(&self) -> &[u8] {
+        &self.metadata
+    }
+
+    /// Frames not yet read
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// ISG container header embedded by the writer, completed by its
+    /// trailer if it was streamed
+    fn header(&self) -> Result<ContainerHeader> {
+        if self.metadata.is_empty() {
+            return Err(Error::Decoding("Video carries no ISG header".to_string()));
+        }
+        let bytes = [&self.metadata[..], &self.trailer].concat();
+        Ok(ContainerHeader::parse(&bytes)?.0)
+    }
+
+    fn parse_y4m(data: &'a [u8]) -> Result<Self> {
+        let line_end = data
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| Error::Decoding("Y4M header truncated".to_string()))?;
+        let (mut reader, frame_len) = Self::parse_y4m_header(&data[..line_end])?;
+
+        let mut frames = Vec::new();
+        let mut cursor = line_end + 1;
+        while cursor < data.len() {
+            let header_end = data[cursor..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or_else(|| Error::Decoding("Y4M frame header truncated".to_string()))?;
+            reader.read_frame_header(&data[cursor..cursor + header_end])?;
+            cursor += header_end + 1;
+
+            if data.len() - cursor < frame_len {
+                return Err(Error::Decoding("Y4M frame truncated".to_string()));
+            }
+            frames.push(&data[cursor..cursor + frame_len]);
+            cursor += frame_len;
+        }
+
+        reader.frames = frames.into_iter();
+        Ok(reader)
+    }
+
+    /// Parse a Y4M header line (without its newline), returning a reader
+    /// with no frames yet and the length of each frame
+    fn parse_y4m_header(line: &[u8]) -> Result<(Self, usize)> {
+        let header = std::str::from_utf8(line)
+            .map_err(|_| Error::Decoding("Y4M header is not text".to_string()))?;
+
+        let (mut width, mut height, mut fps) = (0, 0, 0.0);
+        let mut colorspace = "420jpeg";
+        let mut full_range = false;
+        let mut metadata = Vec::new();
+
+        for token in header.split(' ').skip(1).filter(|token| !token.is_empty()) {
+            let mut chars = token.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            let number = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| Error::Decoding(format!("Invalid Y4M parameter: {}", token)))
+            };
+            match tag {
+                Some('W') => width = number()?,
+                Some('H') => height = number()?,
+                Some('F') => {
+                    let (num, den) = value.split_once(':').unwrap_or((value, "1"));
+                    let num: f64 = num.parse().unwrap_or(0.0);
+                    let den: f64 = den.parse().unwrap_or(1.0);
+                    fps = if den > 0.0 { num / den } else { 0.0 };
+                }
+                Some('C') => colorspace = value,
+                Some('X') if token == "XCOLORRANGE=FULL" => full_range = true,
+                Some('X') if token.starts_with(Y4M_METADATA) => {
+                    metadata = hex::decode(&token[Y4M_METADATA.len()..])
+                        .map_err(|_| Error::Decoding("Invalid ISG metadata in Y4M".to_string()))?;
+                }
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err(Error::Decoding("Y4M header lacks frame size".to_string()));
+        }
+
+        let luma = plane_len(width, height)?;
+        let (codec, frame_len) = match colorspace {
+            "mono" => (FrameCodec::Mono { full_range }, luma),
+            _ => {
+                let subsampling = match colorspace {
+                    "420" | "420jpeg" | "420paldv" | "420mpeg2" => (2, 2),
+                    "422" => (2, 1),
+                    "444" => (1, 1),
+                    other => {
+                        return Err(Error::Decoding(format!(
+                            "Unsupported Y4M colorspace: {}",
+                            other
+                        )))
+                    }
+                };
+                let chroma = plane_len(
+                    width.div_ceil(subsampling.0),
+                    height.div_ceil(subsampling.1),
+                )?;
+                let frame_len = chroma
+                    .checked_mul(2)
+                    .and_then(|chroma| chroma.checked_add(luma))
+                    .ok_or_else(|| Error::Decoding("Y4M frame size too large".to_string()))?;
+                (
+                    FrameCodec::Yuv {
+                        subsampling,
+                        full_range,
+                    },
+                    frame_len,
+                )
+            }
+        };
+
+        let reader = Self {
+            width,
+            height,
+            fps,
+            metadata,
+            codec,
+            trailer: Vec::new(),
+            frames: Vec::new().into_iter(),
+        };
+        Ok((reader, frame_len))
+    }
+
+    /// Check a Y4M frame header line (without its newline), keeping the
+    /// trailer it may carry
+    fn read_frame_header(&mut self, line: &[u8]) -> Result<()> {
+        if !line.starts_with(b"FRAME") {
+            return Err(Error::Decoding("Y4M frame header missing".to_string()));
+        }
+
+        let parameters = line[5..].split(|&b| b == b' ');
+        for parameter in parameters {
+            if let Some(trailer) = parameter.strip_prefix(Y4M_TRAILER.as_bytes()) {
+                self.trailer = hex::decode(trailer)
+                    .ok()
+                    .filter(|trailer| trailer.len() == container::TRAILER_LEN)
+                    .ok_or_else(|| Error::Decoding("Invalid ISG trailer in Y4M".to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_avi(data: &'a [u8]) -> Result<Self> {
+        let size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let riff = &data[12..(8 + size).min(data.len())];
+
+        let mut reader = Self {
+            width: 0,
+            height: 0,
+            fps: 0.0,
+            metadata: Vec::new(),
+            codec: FrameCodec::Jpeg,
+            trailer: Vec::new(),
+            frames: Vec::new().into_iter(),
+        };
+        let mut frames = Vec::new();
+        let mut codec = None;
+        reader.walk_avi(riff, &mut frames, &mut codec, &mut false)?;
+
+        reader.codec =
+            codec.ok_or_else(|| Error::Decoding("AVI has no video stream".to_string()))?;
+        if reader.width == 0 || reader.height == 0 {
+            return Err(Error::Decoding("AVI header lacks frame size".to_string()));
+        }
+        reader.frames = frames.into_iter();
+        Ok(reader)
+    }
+
+    /// Visit the chunks of an AVI list, recursing into sub-lists
+    fn walk_avi(
+        &mut self,
+        mut list: &'a [u8],
+        frames: &mut Vec<&'a [u8]>,
+        codec: &mut Option<FrameCodec>,
+        in_video: &mut bool,
+    ) -> Result<()> {
+        while list.len() >= 8 {
+            let id: [u8; 4] = list[..4].try_into().unwrap();
+            let len = u32::from_le_bytes(list[4..8].try_into().unwrap()) as usize;
+            if 8 + len > list.len() {
+                return Err(Error::Decoding("AVI chunk truncated".to_string()));
+            }
+            let body = &list[8..8 + len];
+            let field = |i: usize| {
+                body.get(i..i + 4)
+                    .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                    .ok_or_else(|| Error::Decoding("AVI header truncated".to_string()))
+            };
+
+            match &id {
+                b"LIST" if body.len() >= 4 => self.walk_avi(&body[4..], frames, codec, in_video)?,
+                b"avih" => {
+                    self.width = field(32)?;
+                    self.height = field(36)?;
+                    if self.fps == 0.0 && field(0)? > 0 {
+                        self.fps = 1_000_000.0 / field(0)? as f64;
+                    }
+                }
+                b"strh" => {
+                    // Only the first video stream is read; its format follows
+                    *in_video = body.starts_with(b"vids") && codec.is_none();
+                    let (scale, rate) = (field(20)?, field(24)?);
+                    if *in_video && scale > 0 {
+                        self.fps = rate as f64 / scale as f64;
+                    }
+                }
+                b"strf" if *in_video => {
+                    *in_video = false;
+                    let height = field(8)? as i32;
+                    self.width = field(4)?;
+                    self.height = height.unsigned_abs();
+                    *codec = Some(match &body.get(16..20) {
+                        Some([0, 0, 0, 0]) if field(12)? >> 16 == 24 => FrameCodec::Bgr {
+                            top_down: height < 0,
+                        },
+                        Some(b"MJPG") => FrameCodec::Jpeg,
+                        _ => {
+                            return Err(Error::Decoding("Unsupported AVI video codec".to_string()))
+                        }
+                    });
+                }
+                id if id == AVI_METADATA => self.metadata = body.to_vec(),
+                [b'0', b'0', b'd', b'b' | b'c'] => frames.push(body),
+                _ => {}
+            }
+
+            // Chunks are padded to an even length
+            let next = (8 + len + len % 2).min(list.len());
+            list = &list[next..];
+        }
+        Ok(())
+    }
+
+    /// Decode one frame's bytes
+    fn decode(&self, bytes: &[u8]) -> Result<RgbaImage> {
+        let (width, height) = (self.width, self.height);
+        match self.codec {
+            FrameCodec::Yuv {
+                subsampling: (sx, sy),
+                full_range,
+            } => {
+                // Plane sizes were checked against the frame length when parsing
+                let luma = width as usize * height as usize;
+                let chroma_width = width.div_ceil(sx) as usize;
+                let chroma = chroma_width * height.div_ceil(sy) as usize;
+                let (y_plane, rest) = bytes.split_at(luma);
+                let (u_plane, v_plane) = rest.split_at(chroma);
+                Ok(RgbaImage::from_fn(width, height, |x, y| {
+                    let c = (y / sy) as usize * chroma_width + (x / sx) as usize;
+                    let i = y as usize * width as usize + x as usize;
+                    let yuv = [y_plane[i], u_plane[c], v_plane[c]];
+                    rgb_of(yuv, full_range)
+                }))
+            }
+            FrameCodec::Mono { full_range } => Ok(RgbaImage::from_fn(width, height, |x, y| {
+                rgb_of(
+                    [bytes[y as usize * width as usize + x as usize], 128, 128],
+                    full_range,
+                )
+            })),
+            FrameCodec::Bgr { top_down } => {
+                let stride = bgr_stride(width);
+                if stride
+                    .checked_mul(height as usize)
+                    .is_none_or(|len| bytes.len() < len)
+                {
+                    return Err(Error::Decoding("AVI frame truncated".to_string()));
+                }
+                Ok(RgbaImage::from_fn(width, height, |x, y| {
+                    let row = if top_down { y } else { height - 1 - y };
+                    let i = row as usize * stride + x as usize * 3;
+                    Rgba([bytes[i + 2], bytes[i + 1], bytes[i], 255])
+                }))
+            }
+            FrameCodec::Jpeg => {
+                let frame = image::load_from_memory_with_format(bytes, image::ImageFormat::Jpeg)
+                    .map_err(|e| Error::Decoding(format!("JPEG decoding failed: {}", e)))?
+                    .to_rgba8();
+                if frame.dimensions() != (width, height) {
+                    return Err(Error::Decoding("AVI frame size mismatch".to_string()));
+                }
+                Ok(frame)
+            }
+        }
+    }
+}
+
+impl Iterator for VideoReader<'_> {
+    type Item = Result<RgbaImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.frames.next()?;
+        Some(self.decode(bytes))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.frames.size_hint()
+    }
+}
+
+/// Writes the frames of a streamed encoding as a PNG sequence container or
+/// a Y4M video
+pub(crate) enum FrameWriter {
+    PngSequence,
+
+    /// The latest frame is held back so the last one can carry the trailer
+    Y4m {
+        size: (u32, u32),
+        held: Option<RgbaImage>,
+    },
+}
+
+impl FrameWriter {
+    /// Write `header` (a streaming container header) at the start of a
+    /// `format` output of `size` frames, returning the bytes written
+    pub async fn start(
+        format: VideoFormat,
+        output: &mut ByteWriter<'_>,
+        header: &ContainerHeader,
+        size: (u32, u32),
+        fps: u32,
+    ) -> Result<(Self, usize)> {
+        let prefix = header.to_bytes()?;
+        match format {
+            VideoFormat::PngSequence => {
+                output.write_all(&prefix).await?;
+                output
+                    .write_all(&container::UNKNOWN_FRAME_COUNT.to_le_bytes())
+                    .await?;
+                Ok((Self::PngSequence, prefix.len() + 4))
+            }
+            VideoFormat::Y4m => {
+                check_fps(fps)?;
+                let line = y4m_header(size, fps, &prefix);
+                output.write_all(&line).await?;
+                Ok((Self::Y4m { size, held: None }, line.len()))
+            }
+            VideoFormat::Avi(_) => Err(Error::Config(
+                "AVI output can't be streamed; use Y4M or a PNG sequence".to_string(),
+            )),
+        }
+    }
+
+    /// Write one frame, returning the bytes written
+    pub async fn push(&mut self, output: &mut ByteWriter<'_>, frame: RgbaImage) -> Result<usize> {
+        match self {
+            Self::PngSequence => container::write_frame(output, &frame).await,
+            Self::Y4m { size, held } => {
+                if frame.dimensions() != *size {
+                    return Err(Error::Encoding("Video frames differ in size".to_string()));
+                }
+                let Some(previous) = held.replace(frame) else {
+                    return Ok(0);
+                };
+                let bytes = y4m_frame(&previous, "");
+                output.write_all(&bytes).await?;
+                Ok(bytes.len())
+            }
+        }
+    }
+
+    /// Write the container trailer, returning the bytes written
+    pub async fn finish(
+        self,
+        output: &mut ByteWriter<'_>,
+        original_size: u64,
+        checksum: u32,
+    ) -> Result<usize> {
+        let trailer = ContainerHeader::trailer(original_size, checksum);
+        let bytes = match self {
+            Self::PngSequence => trailer.to_vec(),
+            Self::Y4m { held, .. } => {
+                let last = held
+                    .ok_or_else(|| Error::Encoding("Video needs at least one frame".to_string()))?;
+                let parameters = format!(" {}{}", Y4M_TRAILER, hex::encode(trailer));
+                y4m_frame(&last, &parameters)
+            }
+        };
+        output.write_all(&bytes).await?;
+        Ok(bytes.len())
+    }
+}
+
+/// Reads the frames of a streamed PNG sequence container or Y4M video
+pub(crate) enum FrameReader<'a, 'r> {
+    PngSequence {
+        payload: PayloadReader<'a, 'r>,
+        remaining: u32,
+    },
+
+    Y4m {
+        input: BufReader<&'a mut ByteReader<'r>>,
+
+        /// Frame format and ISG header, without frames of its own
+        format: Box<VideoReader<'static>>,
+        frame: Vec<u8>,
+    },
+}
+
+impl<'a, 'r> FrameReader<'a, 'r> {
+    /// Read the container header at the start of `input`, whose first bytes
+    /// `magic` tell the formats apart
+    pub async fn start(
+        input: &'a mut ByteReader<'r>,
+        magic: &[u8],
+    ) -> Result<(Self, ContainerHeader)> {
+        if !is_video(magic) {
+            let header = ContainerHeader::read_from(input).await?;
+            let mut payload = PayloadReader::new(input, &header);
+            let remaining = u32::from_le_bytes(payload.read_exact(4).await?.try_into().unwrap());
+            return Ok((Self::PngSequence { payload, remaining }, header));
+        }
+        if !magic.starts_with(Y4M_MAGIC) {
+            return Err(Error::Decoding(
+                "AVI input can't be streamed; decode it in one piece".to_string(),
+            ));
+        }
+
+        let mut input = BufReader::new(input);
+        let line = read_y4m_line(&mut input)
+            .await?
+            .ok_or_else(|| Error::Decoding("Y4M header truncated".to_string()))?;
+        let (format, frame_len) = VideoReader::parse_y4m_header(&line)?;
+        let (width, height) = (format.width, format.height);
+        if width > container::MAX_FRAME_SIDE || height > container::MAX_FRAME_SIDE {
+            return Err(Error::Decoding(format!(
+                "Y4M frames of {}x{} are too large",
+                width, height
+            )));
+        }
+        if format.metadata.is_empty() {
+            return Err(Error::Decoding("Video carries no ISG header".to_string()));
+        }
+
+        // Streamed headers are completed by the trailer on the last frame
+        let header = ContainerHeader::read_from(&mut &format.metadata[..]).await?;
+        let reader = Self::Y4m {
+            input,
+            format: Box::new(format),
+            frame: vec![0u8; frame_len],
+        };
+        Ok((reader, header))
+    }
+
+    /// Read the next frame, or `None` at the end of the stream
+    pub async fn next(&mut self) -> Result<Option<RgbaImage>> {
+        match self {
+            Self::PngSequence { payload, remaining } => {
+                if *remaining == 0 {
+                    return Ok(None);
+                }
+                *remaining -= 1;
+                container::read_frame(payload).await
+            }
+            Self::Y4m {
+                input,
+                format,
+                frame,
+            } => {
+                let Some(line) = read_y4m_line(input).await? else {
+                    return Ok(None);
+                };
+                format.read_frame_header(&line)?;
+                input
+                    .read_exact(frame)
+                    .await
+                    .map_err(|_| Error::Decoding("Y4M frame truncated".to_string()))?;
+                format.decode(frame).map(Some)
+            }
+        }
+    }
+
+    /// Check the stream was fully consumed and complete `header` from its
+    /// trailer, if any
+    pub async fn finish(self, header: &mut ContainerHeader) -> Result<()> {
+        match self {
+            Self::PngSequence { payload, .. } => payload.finish(header).await,
+            Self::Y4m { format, .. } => {
+                *header = format.header()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Read a Y4M header line without its newline, or `None` at the end of the
+/// stream
+async fn read_y4m_line(input: &mut BufReader<&mut ByteReader<'_>>) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    input
+        .take(MAX_Y4M_LINE as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    match line.pop() {
+        None => Ok(None),
+        Some(b'\n') => Ok(Some(line)),
+        Some(_) => Err(Error::Decoding("Y4M header line truncated".to_string())),
+    }
+}
+
+/// Size shared by all frames, failing on an empty or mixed sequence
+fn frame_size(frames: &[RgbaImage], fps: u32) -> Result<(u32, u32)> {
+    check_fps(fps)?;
+    let first = frames
+        .first()
+        .ok_or_else(|| Error::Encoding("Video needs at least one frame".to_string()))?;
+    if frames
+        .iter()
+        .any(|frame| frame.dimensions() != first.dimensions())
+    {
+        return Err(Error::Encoding("Video frames differ in size".to_string()));
+    }
+    Ok(first.dimensions())
+}
+
+/// Fail on a frame rate of zero
+fn check_fps(fps: u32) -> Result<()> {
+    if fps == 0 {
+        return Err(Error::Config("Video needs at least 1 fps".to_string()));
+    }
+    Ok(())
+}
+
+/// Append a RIFF chunk, padded to an even length
+fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Bytes per 24-bit row, padded to four bytes
+fn bgr_stride(width: u32) -> usize {
+    (width as usize * 3).div_ceil(4) * 4
+}
+
+/// Bytes in a `width` x `height` plane of one byte per sample
+fn plane_len(width: u32, height: u32) -> Result<usize> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| Error::Decoding("Y4M frame size too large".to_string()))
+}
+
+/// Frame as bottom-up BGR rows
+fn bottom_up_bgr(frame: &RgbaImage) -> Vec<u8> {
+    let stride = bgr_stride(frame.width());
+    let mut out = vec![0u8; stride * frame.height() as usize];
+    for (x, y, pixel) in frame.enumerate_pixels() {
+        let i = (frame.height() - 1 - y) as usize * stride + x as usize * 3;
+        out[i..i + 3].copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+    }
+    out
+}
+
+/// Frame as a JPEG image
+fn jpeg_bytes(frame: &RgbaImage, quality: u8) -> Result<Vec<u8>> {
+    let rgb = image::DynamicImage::ImageRgba8(frame.clone()).to_rgb8();
+    let mut bytes = Vec::new();
+    rgb.write_to(
+        &mut Cursor::new(&mut bytes),
+        ImageOutputFormat::Jpeg(quality.clamp(1, 100)),
+    )
+    .map_err(|e| Error::Encoding(format!("JPEG encoding failed: {}", e)))?;
+    Ok(bytes)
+}
+
+/// Limited-range (studio swing) bytes of full-range YUV
+fn to_limited([y, u, v]: [f64; 3]) -> [u8; 3] {
+    [
+        16.0 + y * 219.0 / 255.0,
+        128.0 + u * 224.0 / 255.0,
+        128.0 + v * 224.0 / 255.0,
+    ]
+    .map(|value| value.round().clamp(0.0, 255.0) as u8)
+}
+
+/// RGB pixel of stored YUV bytes
+fn rgb_of([y, u, v]: [u8; 3], full_range: bool) -> Rgba<u8> {
+    let (y, u, v) = (y as f64, u as f64 - 128.0, v as f64 - 128.0);
+    let yuv = if full_range {
+        [y, u, v]
+    } else {
+        [
+            (y - 16.0) * 255.0 / 219.0,
+            u * 255.0 / 224.0,
+            v * 255.0 / 224.0,
+        ]
+    };
+    let [r, g, b] = yuv_to_rgb(yuv).map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+    Rgba([r, g, b, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isg_core::EncodingStrategy;
+    use serde_json::Value;
+
+    /// Gray ramp with a colored corner, odd-sized to exercise padding
+    fn frames() -> Vec<RgbaImage> {
+        (0..3u32)
+            .map(|n| {
+                RgbaImage::from_fn(37, 21, |x, y| {
+                    if x < 4 && y < 4 {
+                        Rgba([200, 40, 90, 255])
+                    } else {
+                        let level = ((x * 7 + y * 3 + n * 50) % 256) as u8;
+                        Rgba([level, level, level, 255])
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn max_difference(a: &RgbaImage, b: &RgbaImage) -> u8 {
+        a.pixels()
+            .zip(b.pixels())
+            .flat_map(|(p, q)| (0..3).map(move |c| p[c].abs_diff(q[c])))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_y4m_roundtrip() {
+        let frames = frames();
+        let video = write_y4m(&frames, 24, b"ISG header").unwrap();
+        assert!(video.starts_with(b"YUV4MPEG2 W37 H21 F24:1"));
+        assert!(is_video(&video));
+
+        let reader = VideoReader::parse(&video).unwrap();
+        assert_eq!((reader.width(), reader.height()), (37, 21));
+        assert_eq!(reader.fps(), 24.0);
+        assert_eq!(reader.metadata(), b"ISG header");
+        assert_eq!(reader.remaining(), 3);
+
+        let read: Vec<_> = reader.collect::<Result<_>>().unwrap();
+        for (original, read) in frames.iter().zip(&read) {
+            // Gray survives up to rounding through limited range
+            let gray =
+                |frame: &RgbaImage| image::imageops::crop_imm(frame, 4, 4, 33, 17).to_image();
+            assert!(max_difference(&gray(original), &gray(read)) <= 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_y4m_streaming() {
+        let frames = frames();
+        let header = ContainerHeader::streaming(EncodingStrategy::DNAEncoding, Value::Null);
+
+        let mut video = Vec::new();
+        let (mut writer, mut written) =
+            FrameWriter::start(VideoFormat::Y4m, &mut video, &header, (37, 21), 24)
+                .await
+                .unwrap();
+        for frame in &frames {
+            written += writer.push(&mut video, frame.clone()).await.unwrap();
+        }
+        written += writer.finish(&mut video, 1234, 99).await.unwrap();
+        assert_eq!(written, video.len());
+
+        // Buffered reading completes the header from the last frame
+        let reader = VideoReader::parse(&video).unwrap();
+        assert_eq!(reader.remaining(), 3);
+        assert_eq!(reader.header().unwrap().original_size, 1234);
+
+        let input: &mut ByteReader<'_> = &mut &video[..];
+        let (mut reader, mut header) = FrameReader::start(input, &video[..12]).await.unwrap();
+        assert!(header.streamed);
+        let mut read = Vec::new();
+        while let Some(frame) = reader.next().await.unwrap() {
+            read.push(frame);
+        }
+        reader.finish(&mut header).await.unwrap();
+        assert_eq!((header.original_size, header.checksum), (1234, 99));
+        assert_eq!(read.len(), 3);
+        assert!(max_difference(&frames[2], &read[2]) < 64);
+
+        // Without the trailer the header is incomplete
+        let end = video.windows(8).rposition(|w| w == b" XISGEND").unwrap();
+        let mut cut = video[..end].to_vec();
+        cut.extend_from_slice(&video[end + 9 + 2 * container::TRAILER_LEN..]);
+        assert!(VideoReader::parse(&cut).unwrap().header().is_err());
+
+        // AVI doesn't stream
+        let avi = VideoFormat::Avi(AviCodec::Uncompressed);
+        assert!(
+            FrameWriter::start(avi, &mut Vec::new(), &header, (37, 21), 24)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_avi_roundtrip() {
+        let frames = frames();
+
+        let lossless = write_avi(&frames, 30, AviCodec::Uncompressed, b"meta").unwrap();
+        assert!(is_video(&lossless));
+        let reader = VideoReader::parse(&lossless).unwrap();
+        assert_eq!(reader.fps(), 30.0);
+        assert_eq!(reader.metadata(), b"meta");
+        let read: Vec<_> = reader.collect::<Result<_>>().unwrap();
+        assert_eq!(read, frames);
+
+        let mjpeg = write_avi(&frames, 25, AviCodec::Mjpeg { quality: 95 }, b"").unwrap();
+        let reader = VideoReader::parse(&mjpeg).unwrap();
+        assert_eq!(reader.fps(), 25.0);
+        assert!(reader.metadata().is_empty());
+        let read: Vec<_> = reader.collect::<Result<_>>().unwrap();
+        assert_eq!(read.len(), 3);
+        assert!(frames
+            .iter()
+            .zip(&read)
+            .all(|(a, b)| a.dimensions() == b.dimensions()));
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let frames = frames();
+        assert!(write_y4m(&frames, 0, b"").is_err());
+        assert!(write_avi(&[], 30, AviCodec::Uncompressed, b"").is_err());
+
+        let mixed = vec![frames[0].clone(), RgbaImage::new(8, 8)];
+        assert!(write_y4m(&mixed, 30, b"").is_err());
+
+        assert!(VideoReader::parse(b"not a video").is_err());
+        let video = write_y4m(&frames, 30, b"").unwrap();
+        assert!(VideoReader::parse(&video[..video.len() - 10]).is_err());
+        let video = write_avi(&frames, 30, AviCodec::Uncompressed, b"").unwrap();
+        assert!(VideoReader::parse(&video[..video.len() / 2]).is_err());
+
+        // Hostile headers fail instead of panicking
+        assert!(VideoReader::parse("YUV4MPEG2 W8 H8 é\n".as_bytes()).is_ok());
+        let huge = b"YUV4MPEG2 W4294967295 H4294967295 C444\nFRAME\n";
+        assert!(VideoReader::parse(huge).is_err());
+    }
+}